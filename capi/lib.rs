@@ -0,0 +1,4 @@
+//! The exports of `mdtoepson::ffi`, linked into a shared and a static
+//! library for C programs. See include/mdtoepson.h for how to build them.
+
+pub use mdtoepson::ffi::*;
@@ -0,0 +1,26 @@
+//! Calls the library through the declarations in include/mdtoepson.h, as a
+//! C program linking it would.
+
+use std::ffi::c_char;
+use std::{ptr, slice};
+
+extern crate mdtoepson_c;
+
+extern "C" {
+    fn mp_transpile(input: *const c_char, out_len: *mut usize) -> *mut u8;
+    fn mp_free(bytes: *mut u8, len: usize);
+}
+
+#[test]
+fn results_come_back_and_are_freed() {
+    let mut len = 0;
+    unsafe {
+        let out = mp_transpile(c"**hi**".as_ptr(), &mut len);
+        assert!(!out.is_null());
+        assert!(slice::from_raw_parts(out, len).starts_with(b"\x1B@\x1BEhi\x1BF"));
+        mp_free(out, len);
+
+        assert!(mp_transpile(ptr::null(), &mut len).is_null());
+        mp_free(ptr::null_mut(), 0);
+    }
+}
@@ -0,0 +1,30 @@
+/* C interface to the mdtoepson renderer, built with
+ *
+ *   cargo build --release -p mdtoepson-c
+ *
+ * as target/release/libmdtoepson_c.so and libmdtoepson_c.a (mdtoepson_c.dll
+ * and mdtoepson_c.lib on Windows). Link with -lmdtoepson_c.
+ */
+#ifndef MDTOEPSON_H
+#define MDTOEPSON_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Renders NUL-terminated UTF-8 markdown as ESC/P escape codes and stores
+ * their length in *out_len. Returns NULL if input is NULL. The result is
+ * not NUL-terminated; free it with mp_free. */
+uint8_t *mp_transpile(const char *input, size_t *out_len);
+
+/* Frees a result of mp_transpile, given the length it reported. */
+void mp_free(uint8_t *bytes, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
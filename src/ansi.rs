@@ -0,0 +1,134 @@
+use clap::ValueEnum;
+
+/// What to do with ANSI escape sequences in the input, as left by tools
+/// that colour their output.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Ansi {
+    /// Remove them
+    #[default]
+    Strip,
+    /// Turn bold, italic and underline into markdown, removing the rest
+    Map,
+    /// Leave them in the text
+    Keep,
+}
+
+const ESC: char = '\x1B';
+const BEL: char = '\x07';
+
+/// Removes CSI and OSC sequences, or maps their SGR styles to markdown
+/// markers, closing any still open at the end.
+pub fn convert(text: &str, mode: Ansi) -> String {
+    if mode == Ansi::Keep || !text.contains(ESC) {
+        return text.to_string();
+    }
+
+    let mut res = String::with_capacity(text.len());
+    let mut styles = Styles::default();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            (ESC, Some('[')) => {
+                chars.next();
+                let mut params = String::new();
+                let mut end = None;
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7E').contains(&c) {
+                        end = Some(c);
+                        break;
+                    }
+                    params.push(c);
+                }
+                if mode == Ansi::Map && end == Some('m') {
+                    styles.apply(&params, &mut res);
+                }
+            }
+            (ESC, Some(']')) => {
+                while let Some(c) = chars.next() {
+                    if c == BEL || (c == ESC && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => res.push(c),
+        }
+    }
+    styles.apply("0", &mut res);
+
+    res
+}
+
+const BOLD: &str = "**";
+const ITALIC: &str = "*";
+const UNDERLINE: &str = "__";
+
+/// Markdown markers opened by SGR sequences so far, innermost last.
+#[derive(Debug, Default)]
+struct Styles {
+    markers: Vec<&'static str>,
+}
+
+impl Styles {
+    fn apply(&mut self, params: &str, res: &mut String) {
+        for param in params.split(';') {
+            match param.parse::<u8>().unwrap_or(0) {
+                0 => {
+                    while let Some(marker) = self.markers.pop() {
+                        res.push_str(marker);
+                    }
+                }
+                1 => self.open(BOLD, res),
+                3 => self.open(ITALIC, res),
+                4 => self.open(UNDERLINE, res),
+                22 => self.close(BOLD, res),
+                23 => self.close(ITALIC, res),
+                24 => self.close(UNDERLINE, res),
+                _ => {}
+            }
+        }
+    }
+
+    fn open(&mut self, marker: &'static str, res: &mut String) {
+        if !self.markers.contains(&marker) {
+            res.push_str(marker);
+            self.markers.push(marker);
+        }
+    }
+
+    /// Closes a style, closing and reopening any opened inside it so the
+    /// markers stay nested.
+    fn close(&mut self, marker: &'static str, res: &mut String) {
+        let Some(i) = self.markers.iter().position(|&open| open == marker) else {
+            return;
+        };
+        let inner = self.markers.split_off(i + 1);
+        for open in inner.iter().rev() {
+            res.push_str(open);
+        }
+        res.push_str(marker);
+        self.markers.pop();
+        for open in inner {
+            self.open(open, res);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colours_and_titles_are_stripped() {
+        let text = "\x1B]0;title\x07\x1B[1;31merror\x1B[0m: \x1B[2Kdone";
+
+        assert_eq!(convert(text, Ansi::Strip), "error: done");
+        assert_eq!(convert(text, Ansi::Keep), text);
+    }
+
+    #[test]
+    fn styles_map_to_markdown() {
+        let text = "\x1B[1mbold\x1B[22m \x1B[4;3mboth\x1B[m \x1B[1mopen";
+
+        assert_eq!(convert(text, Ansi::Map), "**bold** __*both*__ **open**");
+    }
+}
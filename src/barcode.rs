@@ -0,0 +1,463 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Machine readable symbols printed from fenced blocks tagged with their name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Symbology {
+    Qr,
+    Pdf417,
+    DataMatrix,
+    Code128,
+    /// 13 digit retail barcode, the last digit a check digit
+    Ean13,
+    /// 12 digit North American retail barcode
+    UpcA,
+    /// Code 128 carrying GS1 application identifiers, like `(01)…(10)…`
+    Gs1_128,
+}
+
+/// Where a barcode's human readable interpretation is printed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Hri {
+    None,
+    Above,
+    #[default]
+    Below,
+    Both,
+}
+
+/// Layout attributes of a symbol block, like `h=80 w=3 hri=below quiet=10`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SymbolOptions {
+    /// Bar height in dots
+    pub height: Option<u8>,
+    /// Width of the narrowest bar, or of a QR module, in dots
+    pub module: Option<u8>,
+    pub hri: Hri,
+    /// Blank modules kept left of a barcode
+    pub quiet: Option<u8>,
+    /// Error correction level, L, M, Q or H for QR codes and 0 to 8 for PDF417
+    pub error_correction: Option<char>,
+    /// Data columns of a PDF417 or Data Matrix symbol, automatic when unset
+    pub columns: Option<u8>,
+    pub rows: Option<u8>,
+}
+
+impl SymbolOptions {
+    pub fn parse(attributes: &str) -> Result<Self, String> {
+        let mut options = SymbolOptions::default();
+        for attribute in attributes.split_whitespace() {
+            let (key, value) = attribute
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, found {}", attribute))?;
+            let number = |min: u8, max: u8| {
+                value
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|n| (min..=max).contains(n))
+                    .ok_or_else(|| format!("{} must be from {} to {}", key, min, max))
+            };
+            match key {
+                "h" | "height" => options.height = Some(number(1, 255)?),
+                "w" | "width" | "module" => options.module = Some(number(1, 16)?),
+                "quiet" => options.quiet = Some(number(0, 40)?),
+                "columns" | "cols" => options.columns = Some(number(0, 144)?),
+                "rows" => options.rows = Some(number(0, 144)?),
+                "hri" => {
+                    options.hri = match value {
+                        "none" => Hri::None,
+                        "above" => Hri::Above,
+                        "below" => Hri::Below,
+                        "both" => Hri::Both,
+                        _ => return Err(String::from("hri must be none, above, below or both")),
+                    }
+                }
+                "ec" => {
+                    options.error_correction = match value.to_ascii_uppercase().as_str() {
+                        level @ ("L" | "M" | "Q" | "H" | "0" | "1" | "2" | "3" | "4" | "5"
+                        | "6" | "7" | "8") => level.chars().next(),
+                        _ => return Err(String::from("ec must be L, M, Q, H or 0 to 8")),
+                    }
+                }
+                _ => return Err(format!("unknown attribute {}", key)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+impl Symbology {
+    /// Symbology named by a code fence's info string.
+    pub fn from_language(language: &str) -> Option<Self> {
+        match language.to_ascii_lowercase().as_str() {
+            "qr" | "qrcode" => Some(Symbology::Qr),
+            "pdf417" => Some(Symbology::Pdf417),
+            "datamatrix" | "data-matrix" => Some(Symbology::DataMatrix),
+            "barcode" | "code128" => Some(Symbology::Code128),
+            "ean13" | "ean-13" => Some(Symbology::Ean13),
+            "upc" | "upca" | "upc-a" => Some(Symbology::UpcA),
+            "gs1" | "gs1-128" | "gs1128" => Some(Symbology::Gs1_128),
+            _ => None,
+        }
+    }
+
+    pub fn is_2d(self) -> bool {
+        matches!(
+            self,
+            Symbology::Qr | Symbology::Pdf417 | Symbology::DataMatrix
+        )
+    }
+
+    /// Checks that `data` makes a symbol scanners will read, and returns it as
+    /// it's encoded: with any missing check digit added, and GS1 application
+    /// identifiers joined by FNC1 as `{1`.
+    pub fn check(self, data: &str) -> Result<String, String> {
+        match self {
+            Symbology::Ean13 => check_digits(data, 13, "EAN-13"),
+            Symbology::UpcA => check_digits(data, 12, "UPC-A"),
+            Symbology::Gs1_128 => gs1_elements(data),
+            _ => Ok(String::from(data)),
+        }
+    }
+
+    /// Checks attributes against what the symbology allows.
+    pub fn validate(self, options: &SymbolOptions) -> Result<(), String> {
+        let within = |value: Option<u8>, name: &str, min: u8, max: u8| match value {
+            Some(n) if !(min..=max).contains(&n) => {
+                Err(format!("{} must be from {} to {}", name, min, max))
+            }
+            _ => Ok(()),
+        };
+        let unused = |set: bool, name: &str| match set {
+            true => Err(format!("{} doesn't apply to this symbology", name)),
+            false => Ok(()),
+        };
+        let ec = options.error_correction;
+        let tabular = options.columns.is_some() || options.rows.is_some();
+
+        match self {
+            Symbology::Qr => {
+                unused(options.height.is_some(), "h")?;
+                unused(tabular, "columns or rows")?;
+                unused(ec.is_some_and(|ec| ec.is_ascii_digit()), "numeric ec")
+            }
+            Symbology::Pdf417 => {
+                within(options.module, "w", 2, 8)?;
+                within(options.height, "h", 2, 8)?;
+                within(options.columns, "columns", 0, 30)?;
+                within(options.rows, "rows", 0, 90)?;
+                unused(ec.is_some_and(|ec| !ec.is_ascii_digit()), "lettered ec")
+            }
+            Symbology::DataMatrix => {
+                within(options.module, "w", 2, 16)?;
+                unused(options.height.is_some(), "h")?;
+                unused(ec.is_some(), "ec")
+            }
+            Symbology::Code128 | Symbology::Ean13 | Symbology::UpcA | Symbology::Gs1_128 => {
+                within(options.module, "w", 2, 6)?;
+                unused(tabular, "columns or rows")?;
+                unused(ec.is_some(), "ec")
+            }
+        }
+    }
+
+    /// ESC/POS commands printing `data` as this symbol.
+    pub fn encode(self, data: &str, options: &SymbolOptions) -> Vec<u8> {
+        let data = data.as_bytes();
+        let mut res = Vec::new();
+        match self {
+            Symbology::Qr => {
+                // GS ( k with cn = 49: model 2, module size, error correction, store, print
+                let level = match options.error_correction.unwrap_or('M') {
+                    'L' => b'0',
+                    'Q' => b'2',
+                    'H' => b'3',
+                    _ => b'1',
+                };
+                res.extend_from_slice(b"\x1D(k\x04\x001A2\x00");
+                res.extend_from_slice(b"\x1D(k\x03\x001C");
+                res.push(options.module.unwrap_or(6));
+                res.extend_from_slice(b"\x1D(k\x03\x001E");
+                res.push(level);
+                store_and_print(&mut res, b'1', data);
+            }
+            Symbology::Pdf417 => {
+                // GS ( k with cn = 48: columns, rows, module width, row height,
+                // error correction level, store, print
+                let level = options.error_correction.map_or(b'1', |ec| ec as u8);
+                res.extend_from_slice(b"\x1D(k\x03\x000A");
+                res.push(options.columns.unwrap_or(0));
+                res.extend_from_slice(b"\x1D(k\x03\x000B");
+                res.push(options.rows.unwrap_or(0));
+                res.extend_from_slice(b"\x1D(k\x03\x000C");
+                res.push(options.module.unwrap_or(3));
+                res.extend_from_slice(b"\x1D(k\x03\x000D");
+                res.push(options.height.unwrap_or(3));
+                res.extend_from_slice(b"\x1D(k\x04\x000E0");
+                res.push(level);
+                store_and_print(&mut res, b'0', data);
+            }
+            Symbology::DataMatrix => {
+                // GS ( k with cn = 54: square or rectangular size, module size,
+                // store, print
+                let shape = match options.columns != options.rows {
+                    true => b'1',
+                    false => b'0',
+                };
+                res.extend_from_slice(b"\x1D(k\x05\x006B");
+                res.extend_from_slice(&[shape, options.columns.unwrap_or(0)]);
+                res.push(options.rows.unwrap_or(0));
+                res.extend_from_slice(b"\x1D(k\x03\x006C");
+                res.push(options.module.unwrap_or(6));
+                store_and_print(&mut res, b'6', data);
+            }
+            Symbology::Code128 | Symbology::Ean13 | Symbology::UpcA | Symbology::Gs1_128 => {
+                let module = options.module.unwrap_or(2);
+                let hri = match options.hri {
+                    Hri::None => 0,
+                    Hri::Above => 1,
+                    Hri::Below => 2,
+                    Hri::Both => 3,
+                };
+                res.extend_from_slice(&[0x1D, b'H', hri]);
+                res.extend_from_slice(&[0x1D, b'h', options.height.unwrap_or(80)]);
+                res.extend_from_slice(&[0x1D, b'w', module]);
+                // Start the bars after the quiet zone, ESC $ taking dots
+                let quiet = u16::from(options.quiet.unwrap_or(10)) * u16::from(module);
+                res.extend_from_slice(&[0x1B, b'$']);
+                res.extend_from_slice(&quiet.to_le_bytes());
+                let (m, start): (u8, &[u8]) = match self {
+                    Symbology::Ean13 => (67, b""),
+                    Symbology::UpcA => (65, b""),
+                    Symbology::Gs1_128 => (73, b"{B{1"),
+                    _ => (73, b"{B"),
+                };
+                let data = &data[..data.len().min(255 - start.len())];
+                res.extend_from_slice(&[0x1D, b'k', m, (start.len() + data.len()) as u8]);
+                res.extend_from_slice(start);
+                res.extend_from_slice(data);
+            }
+        }
+        res.push(b'\n');
+
+        res
+    }
+}
+
+/// Stores symbol data with GS ( k function 80, then prints it with 81.
+fn store_and_print(res: &mut Vec<u8>, cn: u8, data: &[u8]) {
+    let data = &data[..data.len().min(7089)];
+    res.extend_from_slice(b"\x1D(k");
+    res.extend_from_slice(&(data.len() as u16 + 3).to_le_bytes());
+    res.extend_from_slice(&[cn, b'P', b'0']);
+    res.extend_from_slice(data);
+    res.extend_from_slice(&[0x1D, b'(', b'k', 3, 0, cn, b'Q', b'0']);
+}
+
+/// Digits of a retail barcode `length` long, adding its check digit when
+/// it's left off and refusing a wrong one.
+fn check_digits(data: &str, length: usize, name: &str) -> Result<String, String> {
+    if !data.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("{} takes only digits, not {}", name, data));
+    }
+    if data.len() == length - 1 {
+        return Ok(format!("{}{}", data, char::from(check_digit(data))));
+    }
+    if data.len() != length {
+        return Err(format!(
+            "{} takes {} digits, or {} without the check digit, not {}",
+            name,
+            length,
+            length - 1,
+            data.len()
+        ));
+    }
+
+    let (body, given) = data.split_at(length - 1);
+    let expected = check_digit(body);
+    match given.bytes().next() == Some(expected) {
+        true => Ok(String::from(data)),
+        false => Err(format!(
+            "{} check digit should be {}, not {}",
+            data,
+            char::from(expected),
+            given
+        )),
+    }
+}
+
+/// GS1 modulo 10 check digit: digits weighted 3, 1, 3… from the right.
+fn check_digit(digits: &str) -> u8 {
+    let sum = digits
+        .bytes()
+        .rev()
+        .enumerate()
+        .map(|(i, b)| u32::from(b - b'0') * if i % 2 == 0 { 3 } else { 1 })
+        .sum::<u32>();
+
+    b'0' + ((10 - sum % 10) % 10) as u8
+}
+
+/// Length of an application identifier's data, and whether it's fixed. Only
+/// the identifiers receipts and shipping labels commonly carry are known.
+fn application_identifier(ai: &str) -> Option<(usize, bool)> {
+    match ai {
+        "00" => Some((18, true)),
+        "01" | "02" => Some((14, true)),
+        "11" | "12" | "13" | "15" | "16" | "17" => Some((6, true)),
+        "20" => Some((2, true)),
+        "10" | "21" | "22" => Some((20, false)),
+        "30" | "37" => Some((8, false)),
+        "400" | "401" | "403" => Some((30, false)),
+        "410" | "411" | "412" | "413" | "414" | "415" => Some((13, true)),
+        "420" => Some((20, false)),
+        // Measures like 3103, net weight in kg with 3 decimals
+        _ if ai.len() == 4
+            && ai.bytes().all(|b| b.is_ascii_digit())
+            && matches!(&ai[..2], "31" | "32" | "33" | "34" | "35" | "36") =>
+        {
+            Some((6, true))
+        }
+        _ => None,
+    }
+}
+
+/// Checks `(01)09501101530003(10)AB12` style data, element by element, and
+/// joins the elements with FNC1 wherever a variable length one ends.
+fn gs1_elements(data: &str) -> Result<String, String> {
+    let mut res = String::new();
+    let mut rest = data.trim();
+    if !rest.starts_with('(') {
+        return Err(String::from(
+            "GS1-128 data is application identifiers in brackets, like (01)09501101530003",
+        ));
+    }
+    while let Some(element) = rest.strip_prefix('(') {
+        let (ai, after) = element
+            .split_once(')')
+            .ok_or_else(|| format!("unclosed application identifier in {}", data))?;
+        let value = after.split('(').next().unwrap_or_default();
+        rest = &after[value.len()..];
+
+        let (length, fixed) = application_identifier(ai)
+            .ok_or_else(|| format!("unknown application identifier ({})", ai))?;
+        let numeric = fixed || matches!(ai, "30" | "37");
+        if numeric && !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("({}) takes only digits, not {}", ai, value));
+        }
+        let value = match (ai, fixed) {
+            ("00" | "01" | "02" | "410" | "411" | "412" | "413" | "414" | "415", _) => {
+                check_digits(value, length, &format!("({})", ai))?
+            }
+            (_, true) if value.len() != length => {
+                return Err(format!(
+                    "({}) takes {} digits, not {}",
+                    ai,
+                    length,
+                    value.len()
+                ));
+            }
+            (_, false) if value.is_empty() || value.len() > length => {
+                return Err(format!(
+                    "({}) takes 1 to {} characters, not {}",
+                    ai,
+                    length,
+                    value.len()
+                ));
+            }
+            _ => String::from(value),
+        };
+
+        res.push_str(ai);
+        res.push_str(&value.replace('{', "{{"));
+        if !fixed && !rest.is_empty() {
+            res.push_str("{1");
+        }
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code128_uses_code_set_b() {
+        assert_eq!(
+            Symbology::Code128.encode("AB", &SymbolOptions::default()),
+            b"\x1DH\x02\x1Dh\x50\x1Dw\x02\x1B$\x14\x00\x1Dk\x49\x04{BAB\n"
+        );
+    }
+
+    #[test]
+    fn attributes_set_barcode_layout() {
+        let options = SymbolOptions::parse("h=40 w=3 hri=none quiet=0").unwrap();
+        let encoded = Symbology::Code128.encode("AB", &options);
+
+        assert!(encoded.starts_with(b"\x1DH\x00\x1Dh\x28\x1Dw\x03\x1B$\x00\x00"));
+        assert!(SymbolOptions::parse("hri=left").is_err());
+        assert!(Symbology::Code128
+            .validate(&SymbolOptions::parse("w=9").unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn two_dimensional_symbols_use_their_function_family() {
+        let options = SymbolOptions::parse("ec=5").unwrap();
+        let encoded = Symbology::Pdf417.encode("hi", &options);
+
+        assert!(Symbology::Pdf417.validate(&options).is_ok());
+        assert!(encoded.windows(9).any(|w| w == b"\x1D(k\x04\x000E05"));
+        assert!(encoded.ends_with(b"\x1D(k\x03\x000Q0\n"));
+
+        let encoded = Symbology::DataMatrix.encode("hi", &SymbolOptions::default());
+        assert!(encoded.ends_with(b"\x1D(k\x03\x006Q0\n"));
+        assert!(Symbology::DataMatrix.validate(&options).is_err());
+    }
+
+    #[test]
+    fn qr_store_length_counts_its_header() {
+        let encoded = Symbology::Qr.encode("hi", &SymbolOptions::default());
+
+        assert!(encoded.windows(9).any(|w| w == b"\x1D(k\x05\x001P0h"));
+    }
+
+    #[test]
+    fn retail_barcodes_get_their_check_digit() {
+        assert_eq!(
+            Symbology::Ean13.check("400638133393").unwrap(),
+            "4006381333931"
+        );
+        assert_eq!(
+            Symbology::Ean13.check("4006381333931").unwrap(),
+            "4006381333931"
+        );
+        assert!(Symbology::Ean13.check("4006381333932").is_err());
+        assert!(Symbology::Ean13.check("40063813339").is_err());
+        assert!(Symbology::UpcA.check("03600029145X").is_err());
+        assert_eq!(
+            Symbology::UpcA.check("03600029145").unwrap(),
+            "036000291452"
+        );
+
+        let encoded = Symbology::Ean13.encode("4006381333931", &SymbolOptions::default());
+        assert!(encoded.ends_with(b"\x1Dk\x43\x0D4006381333931\n"));
+    }
+
+    #[test]
+    fn gs1_elements_are_checked_and_joined() {
+        assert_eq!(
+            Symbology::Gs1_128
+                .check("(01)0950110153000(10)AB12(17)250101")
+                .unwrap(),
+            "010950110153000310AB12{117250101"
+        );
+        assert!(Symbology::Gs1_128.check("(01)09501101530004").is_err());
+        assert!(Symbology::Gs1_128.check("(17)2501").is_err());
+        assert!(Symbology::Gs1_128.check("(99)x").is_err());
+        assert!(Symbology::Gs1_128.check("0109501101530003").is_err());
+    }
+}
@@ -0,0 +1,203 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+/// Where the SHA-256 of an archived job is kept.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Checksum {
+    /// In `<file>.sha256` beside it, as `sha256sum` writes them
+    Sidecar,
+    /// On a line of its own at the end of the file
+    Trailer,
+}
+
+/// Starts the trailer line, which the digest in hex and a newline finish.
+const TRAILER: &[u8] = b"\nSHA-256 ";
+const TRAILER_LEN: usize = TRAILER.len() + 64 + 1;
+
+/// Records the SHA-256 of the job just written to a file, taken over the
+/// bytes that were sent rather than whatever the file holds.
+pub fn seal(path: &Path, checksum: Checksum, written: &[u8]) -> io::Result<()> {
+    let digest = hex(&sha256(written));
+    match checksum {
+        Checksum::Sidecar => {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            fs::write(sidecar(path), format!("{}  {}\n", digest, name))
+        }
+        Checksum::Trailer => {
+            let mut file = OpenOptions::new().append(true).open(path)?;
+            file.write_all(TRAILER)?;
+            file.write_all(digest.as_bytes())?;
+            file.write_all(b"\n")
+        }
+    }
+}
+
+/// Checks a file against its sidecar, or else its trailer, saying which.
+pub fn verify(path: &Path) -> Result<Checksum, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+
+    let (checksum, expected, content) = match fs::read_to_string(sidecar(path)) {
+        Ok(line) => {
+            let expected = line.split_whitespace().next().unwrap_or_default();
+            (Checksum::Sidecar, expected.to_ascii_lowercase(), &bytes[..])
+        }
+        Err(_) => {
+            let split = bytes.len().saturating_sub(TRAILER_LEN);
+            let (content, trailer) = bytes.split_at(split);
+            let Some(digest) = trailer
+                .strip_prefix(TRAILER)
+                .and_then(|rest| rest.strip_suffix(b"\n"))
+            else {
+                return Err(format!(
+                    "{} has neither a {} nor a checksum trailer",
+                    path.display(),
+                    sidecar(path).display()
+                ));
+            };
+            let digest = String::from_utf8_lossy(digest).to_ascii_lowercase();
+            (Checksum::Trailer, digest, content)
+        }
+    };
+
+    match hex(&sha256(content)) == expected {
+        true => Ok(checksum),
+        false => Err(format!(
+            "{} does not match its checksum; it has changed since it was written",
+            path.display()
+        )),
+    }
+}
+
+fn sidecar(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".sha256");
+
+    PathBuf::from(sidecar)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// SHA-256 as FIPS 180-4 describes it.
+fn sha256(bytes: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    let mut message = bytes.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(bytes.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (word, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0; 32];
+    for (out, word) in digest.chunks_exact_mut(4).zip(state) {
+        out.copy_from_slice(&word.to_be_bytes());
+    }
+
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digests_match_the_standard_vectors() {
+        assert_eq!(
+            hex(&sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(&sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(&sha256(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn sealed_files_verify_until_changed() {
+        let dir = std::env::temp_dir().join(format!("mdtoepson-checksum-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (sidecar, trailer) = (dir.join("a.bin"), dir.join("b.bin"));
+        fs::write(&sidecar, b"\x1B@receipt").unwrap();
+        fs::write(&trailer, b"\x1B@receipt").unwrap();
+        seal(&sidecar, Checksum::Sidecar, b"\x1B@receipt").unwrap();
+        seal(&trailer, Checksum::Trailer, b"\x1B@receipt").unwrap();
+
+        assert_eq!(verify(&sidecar), Ok(Checksum::Sidecar));
+        assert_eq!(verify(&trailer), Ok(Checksum::Trailer));
+        fs::write(
+            &trailer,
+            [&b"\x1B@receipt!"[..], &fs::read(&trailer).unwrap()[9..]].concat(),
+        )
+        .unwrap();
+        assert!(verify(&trailer).is_err());
+        fs::write(&sidecar, b"\x1B@receipt!").unwrap();
+        assert!(verify(&sidecar).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
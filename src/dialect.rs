@@ -0,0 +1,459 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::codepage::{Codepage, PC437, PC737, PC850, PC866};
+
+/// Printer command set used to express styles and layout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(clap::ValueEnum))]
+pub enum Dialect {
+    /// Epson ESC/P, as spoken by 9 and 24 pin dot matrix printers
+    #[default]
+    #[cfg_attr(feature = "std", value(name = "escp"))]
+    EscP,
+    /// Epson ESC/POS, as spoken by thermal receipt printers
+    #[cfg_attr(feature = "std", value(name = "escpos"))]
+    EscPos,
+    /// No escape codes at all, only text and line breaks
+    #[cfg_attr(feature = "std", value(skip))]
+    Plain,
+    /// Inline HTML markup, for previews
+    #[cfg_attr(feature = "std", value(skip))]
+    Html,
+    /// Private style markers, laid out by the PDF writer
+    #[cfg_attr(feature = "std", value(skip))]
+    Pdf,
+}
+
+/// Style markers of the PDF dialect, control bytes that never reach a printer.
+pub mod marker {
+    pub const BOLD_ON: u8 = 0x02;
+    pub const BOLD_OFF: u8 = 0x03;
+    pub const ITALIC_ON: u8 = 0x04;
+    pub const ITALIC_OFF: u8 = 0x05;
+    pub const UNDERLINE_ON: u8 = 0x06;
+    pub const UNDERLINE_OFF: u8 = 0x07;
+    pub const DOUBLE_ON: u8 = 0x0E;
+    pub const DOUBLE_OFF: u8 = 0x0F;
+    pub const TALL_ON: u8 = 0x11;
+    pub const TALL_OFF: u8 = 0x12;
+}
+
+/// Character fonts, from the standard face down to the smaller one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(clap::ValueEnum))]
+pub enum Font {
+    A,
+    B,
+}
+
+/// Fixed escape sequences for a dialect.
+#[derive(Debug)]
+pub struct Sequences {
+    pub bold_on: &'static [u8],
+    pub bold_off: &'static [u8],
+    pub italic_on: &'static [u8],
+    pub italic_off: &'static [u8],
+    pub underline_on: &'static [u8],
+    pub underline_off: &'static [u8],
+    /// White on black, empty where the printer can't reverse
+    pub inverse_on: &'static [u8],
+    pub inverse_off: &'static [u8],
+    /// Heading styles, without the blank lines around headings
+    pub top_header_on: &'static [u8],
+    pub top_header_off: &'static [u8],
+    pub lower_header_on: &'static [u8],
+    pub lower_header_off: &'static [u8],
+    /// Raised, lowered and back to normal characters, or smaller ones where
+    /// the printer can't shift them
+    pub superscript_on: &'static [u8],
+    pub superscript_off: &'static [u8],
+    pub subscript_on: &'static [u8],
+    pub subscript_off: &'static [u8],
+    pub font_a: &'static [u8],
+    pub font_b: &'static [u8],
+    /// Feed the paper past the cutter and cut it, empty without a cutter
+    pub cut: &'static [u8],
+    /// Feed the paper out so it can be torn off by hand
+    pub eject: &'static [u8],
+    /// Feed to the start of the next label, at its gap or black mark
+    pub next_label: &'static [u8],
+    /// Sound the buzzer, empty where there's none
+    pub beep: &'static [u8],
+    /// Print in the second ribbon or paper colour, usually red
+    pub red_on: &'static [u8],
+    pub red_off: &'static [u8],
+    /// Return the printer to its power-on state at the start of a job
+    pub init: &'static [u8],
+    /// Turn off every style at the end of a job
+    pub reset: &'static [u8],
+    /// Codepages the printer can switch between and the commands selecting
+    /// them, the power-on one first. Empty for dialects that keep UTF-8.
+    pub codepages: &'static [(&'static Codepage, &'static [u8])],
+    /// Horizontal scale of characters printed inside a top header
+    pub top_header_scale: usize,
+}
+
+const ESCP: Sequences = Sequences {
+    bold_on: b"\x1BE",
+    bold_off: b"\x1BF",
+    italic_on: b"\x1B4",
+    italic_off: b"\x1B5",
+    underline_on: b"\x1B-1",
+    underline_off: b"\x1B-0",
+    inverse_on: b"",
+    inverse_off: b"",
+    top_header_on: b"\x1BE\x1Bw1\x1BW1",
+    top_header_off: b"\x1BF\x1Bw0\x1BW0",
+    lower_header_on: b"\x1Bw1",
+    lower_header_off: b"\x1Bw0",
+    superscript_on: b"\x1BS\x00",
+    superscript_off: b"\x1BT",
+    subscript_on: b"\x1BS\x01",
+    subscript_off: b"\x1BT",
+    font_a: b"\x1BP",
+    font_b: b"\x1BM",
+    cut: b"",
+    eject: b"\x0C",
+    // A form feed, given the label's length as the page length
+    next_label: b"\x0C",
+    beep: b"\x07",
+    red_on: b"\x1Br\x01",
+    red_off: b"\x1Br\x00",
+    init: b"\x1B@",
+    reset: b"\x1BF\x1B5\x1B-0\x1Bw0\x1BW0\x1Br\x00",
+    codepages: &[
+        // ESC ( t assigns the codepage to table 1, ESC t selects that table
+        (&PC437, b"\x1B(t\x03\x00\x01\x01\x00\x1Bt\x01"),
+        (&PC850, b"\x1B(t\x03\x00\x01\x03\x00\x1Bt\x01"),
+    ],
+    top_header_scale: 2,
+};
+
+const ESCPOS: Sequences = Sequences {
+    bold_on: b"\x1BE\x01",
+    bold_off: b"\x1BE\x00",
+    // ESC/POS has no italic face
+    italic_on: b"",
+    italic_off: b"",
+    underline_on: b"\x1B-\x01",
+    underline_off: b"\x1B-\x00",
+    inverse_on: b"\x1DB\x01",
+    inverse_off: b"\x1DB\x00",
+    top_header_on: b"\x1BE\x01\x1D!\x11",
+    top_header_off: b"\x1BE\x00\x1D!\x00",
+    lower_header_on: b"\x1D!\x01",
+    lower_header_off: b"\x1D!\x00",
+    // No raised characters, so the smaller Font B stands in
+    superscript_on: b"\x1BM\x01",
+    superscript_off: b"\x1BM\x00",
+    subscript_on: b"\x1BM\x01",
+    subscript_off: b"\x1BM\x00",
+    font_a: b"\x1BM\x00",
+    font_b: b"\x1BM\x01",
+    cut: b"\x1DVA\x03",
+    eject: b"\x1Bd\x06",
+    // ESC B n t: two beeps of 200 ms
+    next_label: b"\x1D\x0C",
+    beep: b"\x1BB\x02\x02",
+    red_on: b"\x1Br\x01",
+    red_off: b"\x1Br\x00",
+    init: b"\x1B@",
+    reset: b"\x1BE\x00\x1B-\x00\x1DB\x00\x1D!\x00\x1Br\x00",
+    codepages: &[
+        (&PC437, b"\x1Bt\x00"),
+        (&PC850, b"\x1Bt\x02"),
+        (&PC737, b"\x1Bt\x0E"),
+        (&PC866, b"\x1Bt\x11"),
+    ],
+    top_header_scale: 2,
+};
+
+const PLAIN: Sequences = Sequences {
+    bold_on: b"",
+    bold_off: b"",
+    italic_on: b"",
+    italic_off: b"",
+    underline_on: b"",
+    underline_off: b"",
+    inverse_on: b"",
+    inverse_off: b"",
+    top_header_on: b"",
+    top_header_off: b"",
+    lower_header_on: b"",
+    lower_header_off: b"",
+    superscript_on: b"",
+    superscript_off: b"",
+    subscript_on: b"",
+    subscript_off: b"",
+    font_a: b"",
+    font_b: b"",
+    cut: b"",
+    eject: b"\x0C",
+    next_label: b"\x0C",
+    beep: b"",
+    red_on: b"",
+    red_off: b"",
+    init: b"",
+    reset: b"",
+    codepages: &[],
+    top_header_scale: 1,
+};
+
+const HTML: Sequences = Sequences {
+    bold_on: b"<b>",
+    bold_off: b"</b>",
+    italic_on: b"<i>",
+    italic_off: b"</i>",
+    underline_on: b"<u>",
+    underline_off: b"</u>",
+    inverse_on: b"<span class=\"inverse\">",
+    inverse_off: b"</span>",
+    top_header_on: b"<b class=\"double\">",
+    top_header_off: b"</b>",
+    lower_header_on: b"<span class=\"tall\">",
+    lower_header_off: b"</span>",
+    superscript_on: b"<sup>",
+    superscript_off: b"</sup>",
+    subscript_on: b"<sub>",
+    subscript_off: b"</sub>",
+    font_a: b"",
+    font_b: b"",
+    cut: b"",
+    eject: b"<hr>",
+    next_label: b"<hr>",
+    beep: b"",
+    red_on: b"<span class=\"red\">",
+    red_off: b"</span>",
+    init: b"",
+    reset: b"",
+    codepages: &[],
+    top_header_scale: 2,
+};
+
+const PDF: Sequences = Sequences {
+    bold_on: &[marker::BOLD_ON],
+    bold_off: &[marker::BOLD_OFF],
+    italic_on: &[marker::ITALIC_ON],
+    italic_off: &[marker::ITALIC_OFF],
+    underline_on: &[marker::UNDERLINE_ON],
+    underline_off: &[marker::UNDERLINE_OFF],
+    inverse_on: b"",
+    inverse_off: b"",
+    top_header_on: &[marker::BOLD_ON, marker::DOUBLE_ON],
+    top_header_off: &[marker::BOLD_OFF, marker::DOUBLE_OFF],
+    lower_header_on: &[marker::TALL_ON],
+    lower_header_off: &[marker::TALL_OFF],
+    superscript_on: b"",
+    superscript_off: b"",
+    subscript_on: b"",
+    subscript_off: b"",
+    font_a: b"",
+    font_b: b"",
+    cut: b"",
+    eject: b"\x0C",
+    next_label: b"\x0C",
+    beep: b"",
+    red_on: b"",
+    red_off: b"",
+    init: b"",
+    reset: b"",
+    codepages: &[],
+    top_header_scale: 2,
+};
+
+/// What a printer can actually do, so renderers can fall back instead of
+/// sending commands it would ignore or misread.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    pub has_cutter: bool,
+    /// QR codes and the other 2D symbols of GS ( k
+    pub has_qr: bool,
+    pub has_barcode: bool,
+    /// Double height characters for headings, which are underlined instead
+    /// where there are none
+    pub has_double_height: bool,
+    /// Superscript and subscript, written out as `x^2` and `H_2O` where there are none
+    pub has_scripts: bool,
+    /// Box drawing characters for frames, which are drawn in ASCII where
+    /// there are none
+    pub has_box_drawing: bool,
+    /// Ink or paper colours, 2 for black and red
+    pub colors: u8,
+    /// Widest line the printer can print, in Font A columns
+    pub max_width: Option<usize>,
+}
+
+impl Dialect {
+    /// Capabilities of a typical printer speaking this dialect.
+    pub fn capabilities(self) -> Capabilities {
+        match self {
+            Dialect::EscP => Capabilities {
+                has_double_height: true,
+                has_scripts: true,
+                has_box_drawing: true,
+                colors: 1,
+                max_width: Some(80),
+                ..Default::default()
+            },
+            Dialect::EscPos => Capabilities {
+                has_cutter: true,
+                has_qr: true,
+                has_barcode: true,
+                has_double_height: true,
+                has_scripts: true,
+                has_box_drawing: true,
+                colors: 1,
+                max_width: None,
+            },
+            Dialect::Plain => Capabilities {
+                has_box_drawing: true,
+                colors: 1,
+                ..Default::default()
+            },
+            Dialect::Pdf => Capabilities {
+                has_double_height: true,
+                colors: 1,
+                ..Default::default()
+            },
+            Dialect::Html => Capabilities {
+                has_double_height: true,
+                has_scripts: true,
+                has_box_drawing: true,
+                colors: 2,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn sequences(self) -> &'static Sequences {
+        match self {
+            Dialect::EscP => &ESCP,
+            Dialect::EscPos => &ESCPOS,
+            Dialect::Plain => &PLAIN,
+            Dialect::Html => &HTML,
+            Dialect::Pdf => &PDF,
+        }
+    }
+
+    /// Whether markdown syntax that has no printer equivalent should be removed.
+    pub fn strips_markup(self) -> bool {
+        matches!(self, Dialect::Plain | Dialect::Html | Dialect::Pdf)
+    }
+
+    /// Replacement for characters that would be mistaken for markup.
+    pub fn escape(self, c: char) -> Option<&'static str> {
+        match (self, c) {
+            (Dialect::Html, '&') => Some("&amp;"),
+            (Dialect::Html, '<') => Some("&lt;"),
+            (Dialect::Html, '>') => Some("&gt;"),
+            (Dialect::Pdf, c) if c.is_ascii_control() && c != '\n' => Some(""),
+            _ => None,
+        }
+    }
+
+    pub fn font(self, font: Font) -> &'static [u8] {
+        match font {
+            Font::A => self.sequences().font_a,
+            Font::B => self.sequences().font_b,
+        }
+    }
+
+    /// Margins in character columns, out of a line `columns` wide.
+    pub fn margins(self, font: Font, columns: usize, left: usize, right: usize) -> Vec<u8> {
+        match self {
+            Dialect::EscP => {
+                let mut res = vec![0x1B, b'l', clamp_u8(left)];
+                res.extend_from_slice(&[0x1B, b'Q', clamp_u8(columns.saturating_sub(right))]);
+                res
+            }
+            Dialect::EscPos => {
+                let dots = match font {
+                    Font::A => 12,
+                    Font::B => 9,
+                };
+                let mut res = vec![0x1D, b'L'];
+                res.extend_from_slice(&clamp_u16(left * dots).to_le_bytes());
+                res.extend_from_slice(&[0x1D, b'W']);
+                let area = columns.saturating_sub(left + right) * dots;
+                res.extend_from_slice(&clamp_u16(area).to_le_bytes());
+                res
+            }
+            Dialect::Plain | Dialect::Html | Dialect::Pdf => Vec::new(),
+        }
+    }
+
+    /// Widest image in dots that fits `columns` characters of Font A.
+    pub fn image_width(self, columns: Option<usize>) -> usize {
+        match self {
+            // 180 dpi at 10 characters per inch
+            Dialect::EscP => columns.unwrap_or(80) * 18,
+            Dialect::EscPos => columns.unwrap_or(48) * 12,
+            Dialect::Plain | Dialect::Html | Dialect::Pdf => 0,
+        }
+    }
+
+    /// Default line spacing, a sixth of an inch on both command sets.
+    pub fn line_pitch_mm(self) -> f64 {
+        25.4 / 6.0
+    }
+
+    /// Typical paper feed while printing text, in millimetres per second.
+    pub fn print_speed_mm_s(self) -> f64 {
+        match self {
+            // About 3.5 lines a second from a dot matrix head
+            Dialect::EscP | Dialect::Plain | Dialect::Html | Dialect::Pdf => 15.0,
+            Dialect::EscPos => 150.0,
+        }
+    }
+
+    /// Resolution images are printed at.
+    pub fn dots_per_mm(self) -> f64 {
+        match self {
+            Dialect::EscP | Dialect::Plain | Dialect::Html | Dialect::Pdf => 180.0 / 25.4,
+            // 203 dpi thermal heads
+            Dialect::EscPos => 8.0,
+        }
+    }
+
+    /// Print darkness from -6, lightest, to 6, darkest, where 0 is the
+    /// printer's standard. Impact printers have no such setting.
+    pub fn density(self, level: i8) -> Vec<u8> {
+        match self {
+            // GS ( K, function 49: levels below 0 are sent as 250 to 255
+            Dialect::EscPos => vec![0x1D, b'(', b'K', 2, 0, 49, level.clamp(-6, 6) as u8],
+            Dialect::EscP | Dialect::Plain | Dialect::Html | Dialect::Pdf => Vec::new(),
+        }
+    }
+
+    /// Length of each label, so a form feed moves on by exactly one. Printers
+    /// with a gap or black mark sensor find the next label themselves.
+    pub fn label_length(self, mm: usize) -> Vec<u8> {
+        match self {
+            // ESC ( C in the default unit of 1/360 inch
+            Dialect::EscP => {
+                let units = clamp_u16((mm * 3600 + 127) / 254);
+                let mut res = vec![0x1B, b'(', b'C', 2, 0];
+                res.extend_from_slice(&units.to_le_bytes());
+                res
+            }
+            Dialect::EscPos | Dialect::Plain | Dialect::Html | Dialect::Pdf => Vec::new(),
+        }
+    }
+
+    /// Page length in lines, for dialects that drive cut sheet or fanfold paper.
+    pub fn page_length(self, lines: usize) -> Vec<u8> {
+        match self {
+            Dialect::EscP => vec![0x1B, b'C', clamp_u8(lines)],
+            Dialect::EscPos | Dialect::Plain | Dialect::Html | Dialect::Pdf => Vec::new(),
+        }
+    }
+}
+
+fn clamp_u8(n: usize) -> u8 {
+    n.min(u8::MAX as usize) as u8
+}
+
+fn clamp_u16(n: usize) -> u16 {
+    n.min(u16::MAX as usize) as u16
+}
@@ -0,0 +1,124 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use clap::ValueEnum;
+
+/// Character encoding of input files.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Go by the byte order mark, else UTF-8, UTF-16 or Windows-1252
+    /// by what the bytes look like
+    #[default]
+    Auto,
+    #[value(name = "utf-8")]
+    Utf8,
+    #[value(name = "utf-16le")]
+    Utf16Le,
+    #[value(name = "utf-16be")]
+    Utf16Be,
+    /// ISO 8859-1
+    Latin1,
+    Windows1252,
+}
+
+/// Windows-1252 characters for 0x80 to 0x9F, where it differs from Latin-1
+const WINDOWS_1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+/// Reads a text file in any of the encodings, as UTF-8 without a byte order mark.
+pub fn read(path: &Path, encoding: Encoding) -> io::Result<String> {
+    fs::read(path).map(|bytes| decode(&bytes, encoding))
+}
+
+pub fn decode(bytes: &[u8], encoding: Encoding) -> String {
+    let encoding = match encoding {
+        Encoding::Auto => detect(bytes),
+        encoding => encoding,
+    };
+
+    match encoding {
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let units = bytes.chunks_exact(2).map(|pair| match encoding {
+                Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                _ => u16::from_be_bytes([pair[0], pair[1]]),
+            });
+            let mut text = char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect::<String>();
+            if text.starts_with('\u{FEFF}') {
+                text.remove(0);
+            }
+            text
+        }
+        Encoding::Latin1 => bytes.iter().map(|&b| char::from(b)).collect(),
+        Encoding::Windows1252 => bytes
+            .iter()
+            .map(|&b| match b {
+                0x80..=0x9F => WINDOWS_1252[usize::from(b - 0x80)],
+                _ => char::from(b),
+            })
+            .collect(),
+        _ => {
+            let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+            String::from_utf8_lossy(bytes).into_owned()
+        }
+    }
+}
+
+fn detect(bytes: &[u8]) -> Encoding {
+    if bytes.starts_with(b"\xEF\xBB\xBF") {
+        return Encoding::Utf8;
+    }
+    if bytes.starts_with(b"\xFF\xFE") {
+        return Encoding::Utf16Le;
+    }
+    if bytes.starts_with(b"\xFE\xFF") {
+        return Encoding::Utf16Be;
+    }
+
+    // Mostly ASCII text in UTF-16 has a zero in every other byte
+    let pairs = bytes.len() / 2;
+    let zeros = |offset: usize| {
+        bytes
+            .iter()
+            .skip(offset)
+            .step_by(2)
+            .filter(|&&b| b == 0)
+            .count()
+    };
+    let (even, odd) = (zeros(0), zeros(1));
+    if pairs > 0 && odd * 2 > pairs && even * 10 < pairs {
+        return Encoding::Utf16Le;
+    }
+    if pairs > 0 && even * 2 > pairs && odd * 10 < pairs {
+        return Encoding::Utf16Be;
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(_) => Encoding::Utf8,
+        Err(_) => Encoding::Windows1252,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_order_marks_are_skipped() {
+        assert_eq!(decode(b"\xEF\xBB\xBF# hi", Encoding::Auto), "# hi");
+        assert_eq!(decode(b"\xFF\xFE#\x00 \x00\xE9\x00", Encoding::Auto), "# é");
+        assert_eq!(decode(b"\xFE\xFF\x00#\x20\xAC", Encoding::Auto), "#€");
+    }
+
+    #[test]
+    fn unmarked_encodings_are_guessed() {
+        assert_eq!(decode(b"h\x00i\x00!\x00", Encoding::Auto), "hi!");
+        assert_eq!(decode(b"caf\xE9 \x80", Encoding::Auto), "café €");
+        assert_eq!(decode(b"caf\xE9 \x80", Encoding::Latin1), "café \u{80}");
+        assert_eq!(decode("café".as_bytes(), Encoding::Auto), "café");
+    }
+}
@@ -0,0 +1,538 @@
+use alloc::format;
+use core::ops::Range;
+
+use logos::{Lexer, Logos};
+
+#[derive(Logos, Clone, Copy, Debug, PartialEq)]
+enum Token {
+    #[token("**")]
+    Bold,
+
+    #[token("*")]
+    Italic,
+
+    #[token("__")]
+    Underline,
+
+    #[regex(r"#( +)?")]
+    TopHeader,
+
+    #[regex(r"#{2,}( +)?")]
+    LowerHeader,
+
+    #[regex(r"( ?)\{#*[^\}\n]*\}", priority = 98)]
+    Tag,
+
+    #[token("\n")]
+    RemovableNewline,
+
+    #[regex(r"\n{2,}|\\\\")]
+    ActiveNewline,
+
+    #[regex(r"[^(\*\*)\*(__)#\n\r\t\f]")]
+    // Non-ASCII characters whole, rather than byte by byte
+    #[regex(r"[\u{80}-\u{10FFFF}]")]
+    Text,
+
+    #[regex(r"[ \t]*[\-\*+] .+(\n)")]
+    UnorderedList,
+
+    #[regex(r"[ \t]*[0-9]{1,9}[\.\)] .+(\n)")]
+    OrderedList,
+
+    #[regex(r"\[[^\[\]]+\]\([^\(\)]+\)", priority = 99)]
+    Link,
+
+    #[regex(r"!\[[^\[\]]*\]\([^\(\)]+\)", priority = 99)]
+    Image,
+
+    #[regex(r"(\n)?`{3}[^`]*`{3}(\n)?", priority = 100)]
+    Codeblock,
+
+    #[regex(r"<!--([^-]|-[^-]|--[^>])*-->", priority = 100)]
+    Comment,
+
+    #[regex(r" \.{4,} ")]
+    Leader,
+
+    #[token("^")]
+    #[token("~")]
+    Script,
+
+    #[regex(r"\*\[[^\]\n]+\]:[^\n]*(\n)?", priority = 100)]
+    Abbreviation,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    Bold,
+    Italic,
+    Underline,
+}
+
+/// A piece of a markdown document, as the renderer sees it before turning
+/// it into printer bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event<'a> {
+    StyleOn(Style),
+    StyleOff(Style),
+    /// A `#` heading of this level, lasting until the next break
+    Heading(usize),
+    Text(&'a str),
+    /// A single newline, printed as a space unless line breaks are kept
+    SoftBreak,
+    /// A blank line or `\\`
+    HardBreak,
+    /// A `{#id}` tag, without its braces
+    Tag(&'a str),
+    /// A bulleted or numbered line, indented this many columns in the source
+    ListItem {
+        indent: usize,
+        /// The number a numbered item was written with
+        number: Option<usize>,
+        text: &'a str,
+    },
+    CodeBlock(Fence<'a>),
+    Link {
+        text: &'a str,
+        target: &'a str,
+    },
+    Image {
+        alt: &'a str,
+        target: &'a str,
+    },
+    /// `<!-- cut -->`, with the newlines after it
+    Cut,
+    /// `<!-- beep -->`
+    Beep,
+    /// Any other HTML comment, trimmed
+    Comment(&'a str),
+    /// Dots between a label and the rest of its line, like `Coffee ...... $3.50`,
+    /// with that value
+    Leader(&'a str),
+    /// `^2^`, without its carets
+    Superscript(&'a str),
+    /// `~2~`, without its tildes
+    Subscript(&'a str),
+    /// A `*[HTML]: HyperText Markup Language` line defining an abbreviation
+    Abbreviation {
+        abbreviation: &'a str,
+        expansion: &'a str,
+    },
+    /// Input that starts some markup but isn't valid, like an unclosed
+    /// `[link` or code fence, to print as it was written
+    Malformed(&'a str),
+}
+
+/// A fenced code block, split so that `head + body + tail` is the original slice.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fence<'a> {
+    pub head: &'a str,
+    pub language: &'a str,
+    /// Rest of the info string, like `h=80 w=3`
+    pub attributes: &'a str,
+    pub body: &'a str,
+    pub tail: &'a str,
+}
+
+/// The events of a document in order. Styles alternate between on and off
+/// with each marker, and text between markers comes as one event.
+pub struct Events<'a> {
+    lex: Lexer<'a, Token>,
+    span: Range<usize>,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+}
+
+impl<'a> Events<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Events {
+            lex: Token::lexer(input),
+            span: 0..0,
+            bold: false,
+            italic: false,
+            underline: false,
+        }
+    }
+
+    /// The events from byte `start` on, with spans still counting from the
+    /// beginning of `input`.
+    pub fn starting_at(input: &'a str, start: usize) -> Self {
+        let mut events = Events::new(input);
+        events.lex.bump(start);
+        events
+    }
+
+    /// Where in the input the last event came from.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    fn toggle(&mut self, style: Style) -> Event<'a> {
+        let on = match style {
+            Style::Bold => &mut self.bold,
+            Style::Italic => &mut self.italic,
+            Style::Underline => &mut self.underline,
+        };
+        *on = !*on;
+
+        match on {
+            true => Event::StyleOn(style),
+            false => Event::StyleOff(style),
+        }
+    }
+
+    /// Markup that only counts at the start of a line, found partway through
+    /// one, as text. The line feed it took is lexed again after it, so the
+    /// line joins the next as any other would.
+    fn inline_text(&mut self) -> Event<'a> {
+        let input = self.lex.source();
+        let slice = self.lex.slice();
+        let text = slice.strip_suffix('\n').unwrap_or(slice);
+        self.span.end = self.span.start + text.len();
+        self.lex = Token::lexer(input);
+        self.lex.bump(self.span.end);
+
+        Event::Text(text)
+    }
+
+    /// Text from the current token on, up to the next markup.
+    fn text(&mut self) -> Event<'a> {
+        let input = self.lex.source();
+        let mut ahead = self.lex.clone();
+        loop {
+            match ahead.next() {
+                Some(Ok(Token::Text)) => {}
+                Some(Ok(Token::Script)) if script_at(input, ahead.span().start).is_none() => {}
+                _ => break,
+            }
+            self.span.end = ahead.span().end;
+            self.lex = ahead.clone();
+        }
+
+        Event::Text(&input[self.span()])
+    }
+}
+
+impl<'a> Iterator for Events<'a> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        let input = self.lex.source();
+        let token = loop {
+            match self.lex.next()? {
+                Ok(token) => break token,
+                // Stray whitespace, like the \r of \r\n, is left out
+                Err(()) if matches!(self.lex.slice(), "\r" | "\t" | "\x0C") => {}
+                Err(()) => {
+                    let (start, slice) = (self.lex.span().start, self.lex.slice());
+                    let last = self.lex.span().end == input.len();
+                    if let Some(token) = unterminated(slice).filter(|_| last) {
+                        break token;
+                    }
+
+                    // Only up to the end of the line is printed as written,
+                    // and lexing starts again after it
+                    let line = slice.trim_start_matches('\n');
+                    let (event, len) = match slice.len() - line.len() {
+                        0 => {
+                            let line = line.split('\n').next().unwrap_or(line);
+                            (Event::Malformed(line), line.len())
+                        }
+                        1 => (Event::SoftBreak, 1),
+                        newlines => (Event::HardBreak, newlines),
+                    };
+                    self.lex = Token::lexer(input);
+                    self.lex.bump(start + len);
+                    self.span = start..start + len;
+                    return Some(event);
+                }
+            }
+        };
+        self.span = self.lex.span();
+        let slice = self.lex.slice();
+
+        let event = match token {
+            Token::Bold => self.toggle(Style::Bold),
+            Token::Italic => self.toggle(Style::Italic),
+            Token::Underline => self.toggle(Style::Underline),
+            Token::TopHeader | Token::LowerHeader => Event::Heading(slice.trim_end().len()),
+            Token::RemovableNewline => Event::SoftBreak,
+            Token::ActiveNewline => Event::HardBreak,
+            Token::Tag => Event::Tag(slice.trim().trim_start_matches('{').trim_end_matches('}')),
+            Token::Script => match script_at(input, self.span.start) {
+                Some(text) => {
+                    self.lex.bump(text.len() + 1);
+                    self.span.end = self.lex.span().end;
+                    match slice {
+                        "^" => Event::Superscript(text),
+                        _ => Event::Subscript(text),
+                    }
+                }
+                None => self.text(),
+            },
+            Token::Text => self.text(),
+            // Markers partway through a line are just text
+            Token::UnorderedList | Token::OrderedList
+                if !input[..self.span.start].is_empty()
+                    && !input[..self.span.start].ends_with('\n') =>
+            {
+                self.inline_text()
+            }
+            Token::UnorderedList | Token::OrderedList => {
+                let item = slice.trim_start_matches([' ', '\t']);
+                let indent = slice[..slice.len() - item.len()]
+                    .chars()
+                    .map(|c| if c == '\t' { 4 } else { 1 })
+                    .sum();
+                let (marker, text) = item.split_once(' ').unwrap_or_default();
+                Event::ListItem {
+                    indent,
+                    number: marker[..marker.len() - 1].parse().ok(),
+                    text: text.trim_end_matches('\n'),
+                }
+            }
+            // Definitions take a line of their own
+            Token::Abbreviation
+                if !input[..self.span.start].is_empty()
+                    && !input[..self.span.start].ends_with('\n') =>
+            {
+                self.inline_text()
+            }
+            Token::Abbreviation => {
+                let (abbreviation, expansion) = slice[2..].split_once("]:").unwrap_or_default();
+                Event::Abbreviation {
+                    abbreviation: abbreviation.trim(),
+                    expansion: expansion.trim(),
+                }
+            }
+            Token::Codeblock => Event::CodeBlock(split_fence(slice)),
+            Token::Link => {
+                let (text, target) = split_link(slice);
+                Event::Link { text, target }
+            }
+            Token::Image => {
+                let (alt, target) = split_link(&slice[1..]);
+                Event::Image { alt, target }
+            }
+            Token::Comment => {
+                let directive = slice[4..slice.len() - 3].trim();
+                if directive.eq_ignore_ascii_case("cut") {
+                    // The cut ends the line, so newlines after it would only add space
+                    let rest = &input[self.span.end..];
+                    self.lex
+                        .bump(rest.len() - rest.trim_start_matches('\n').len());
+                    self.span.end = self.lex.span().end;
+                    Event::Cut
+                } else if directive.eq_ignore_ascii_case("beep") {
+                    Event::Beep
+                } else {
+                    Event::Comment(directive)
+                }
+            }
+            Token::Leader => {
+                let rest = &input[self.span.end..];
+                let value = &rest[..rest.find('\n').unwrap_or(rest.len())];
+                self.lex.bump(value.len());
+                self.span.end = self.lex.span().end;
+                Event::Leader(value.trim_end())
+            }
+        };
+
+        Some(event)
+    }
+}
+
+/// The token a line at the end of the input would be with a newline, for
+/// a last list item written without one.
+fn unterminated(slice: &str) -> Option<Token> {
+    let line = format!("{}\n", slice);
+    let mut lex = Token::lexer(&line);
+    let token = lex.next()?.ok()?;
+
+    (matches!(token, Token::UnorderedList | Token::OrderedList) && lex.span().end == line.len())
+        .then_some(token)
+}
+
+fn split_fence(slice: &str) -> Fence<'_> {
+    let open = slice.find("```").map_or(0, |i| i + 3);
+    let close = slice
+        .rfind("```")
+        .filter(|&i| i >= open)
+        .unwrap_or(slice.len());
+    let (info, body_start) = match slice[open..close].find('\n') {
+        Some(i) => (&slice[open..open + i], open + i + 1),
+        None => ("", open),
+    };
+
+    // Pandoc style `{barcode h=80}` info strings too
+    let info = info.trim().trim_start_matches('{').trim_end_matches('}');
+    let (language, attributes) = info.split_once(char::is_whitespace).unwrap_or((info, ""));
+
+    Fence {
+        head: &slice[..body_start],
+        language,
+        attributes: attributes.trim(),
+        body: &slice[body_start..close],
+        tail: &slice[close..],
+    }
+}
+
+/// Text of a `^2^` or `~2~` starting at `start`, if it's closed before any
+/// space. Doubled markers, like `~~struck~~`, are left as text.
+fn script_at(input: &str, start: usize) -> Option<&str> {
+    let marker = input[start..].chars().next()?;
+    if input[..start].ends_with(marker) {
+        return None;
+    }
+    let rest = &input[start + 1..];
+    let end = rest.find(|c: char| c == marker || c.is_whitespace())?;
+
+    (end > 0 && rest[end..].starts_with(marker)).then(|| &rest[..end])
+}
+
+/// Text and target of an inline `[text](target)` link.
+fn split_link(slice: &str) -> (&str, &str) {
+    let (text, target) = slice.split_once("](").unwrap_or((slice, ""));
+
+    (text.trim_start_matches('['), target.trim_end_matches(')'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn styles_alternate_around_joined_text() {
+        let events = Events::new("# Hi {#top}\n**bold** text").collect::<Vec<_>>();
+
+        assert_eq!(
+            events,
+            [
+                Event::Heading(1),
+                Event::Text("Hi"),
+                Event::Tag("#top"),
+                Event::SoftBreak,
+                Event::StyleOn(Style::Bold),
+                Event::Text("bold"),
+                Event::StyleOff(Style::Bold),
+                Event::Text(" text"),
+            ]
+        );
+    }
+
+    #[test]
+    fn blocks_are_split_into_their_parts() {
+        let input = "- one\n  - two\na - b\n<!-- cut -->\n\n![logo](logo.pgm)";
+        let mut events = Events::new(input);
+
+        assert_eq!(
+            events.next(),
+            Some(Event::ListItem {
+                indent: 0,
+                number: None,
+                text: "one"
+            })
+        );
+        assert_eq!(
+            events.next(),
+            Some(Event::ListItem {
+                indent: 2,
+                number: None,
+                text: "two"
+            })
+        );
+        assert_eq!(events.next(), Some(Event::Text("a")));
+        assert_eq!(events.next(), Some(Event::Text(" - b")));
+        assert_eq!(events.next(), Some(Event::SoftBreak));
+        assert_eq!(events.next(), Some(Event::Cut));
+        assert_eq!(&input[events.span()], "<!-- cut -->\n\n");
+        assert_eq!(
+            events.next(),
+            Some(Event::Image {
+                alt: "logo",
+                target: "logo.pgm"
+            })
+        );
+        assert_eq!(events.next(), None);
+    }
+
+    #[test]
+    fn scripts_need_a_closing_marker_and_no_spaces() {
+        let events = Events::new("x^2^ H~2~O a ^b c^ ~~d~~").collect::<Vec<_>>();
+
+        assert_eq!(
+            events,
+            [
+                Event::Text("x"),
+                Event::Superscript("2"),
+                Event::Text(" H"),
+                Event::Subscript("2"),
+                Event::Text("O a ^b c^ ~~d~~"),
+            ]
+        );
+    }
+
+    #[test]
+    fn abbreviations_are_defined_on_their_own_lines() {
+        let events =
+            Events::new("a *[b]: c\n*[HTML]: HyperText Markup Language\n").collect::<Vec<_>>();
+
+        assert_eq!(
+            events,
+            [
+                Event::Text("a "),
+                Event::Text("*[b]: c"),
+                Event::SoftBreak,
+                Event::Abbreviation {
+                    abbreviation: "HTML",
+                    expansion: "HyperText Markup Language"
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn leaders_take_the_rest_of_the_line() {
+        let events = Events::new("wait ... **Tea** .... $2 *each*\n").collect::<Vec<_>>();
+
+        assert_eq!(
+            events,
+            [
+                Event::Text("wait ... "),
+                Event::StyleOn(Style::Bold),
+                Event::Text("Tea"),
+                Event::StyleOff(Style::Bold),
+                Event::Leader("$2 *each*"),
+                Event::SoftBreak,
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_markup_is_kept() {
+        let events = Events::new("a [b\n```\nc").collect::<Vec<_>>();
+
+        assert_eq!(
+            events,
+            [
+                Event::Text("a "),
+                Event::Malformed("[b"),
+                Event::SoftBreak,
+                Event::Malformed("```"),
+                Event::SoftBreak,
+                Event::Text("c"),
+            ]
+        );
+        assert_eq!(
+            Events::new("- last").collect::<Vec<_>>(),
+            [Event::ListItem {
+                indent: 0,
+                number: None,
+                text: "last"
+            }]
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use core::ffi::{c_char, CStr};
+use core::{mem, ptr};
+
+use crate::render::{render_markdown, Options};
+
+/// Renders NUL-terminated UTF-8 markdown as ESC/P escape codes, with the
+/// command line's defaults, and writes the result's length to `out_len`.
+/// Invalid UTF-8 is replaced rather than refused. Returns null if `input`
+/// is. Free the result with `mp_free`.
+///
+/// # Safety
+///
+/// `input` must be null or a NUL-terminated string, and `out_len` writable.
+#[no_mangle]
+pub unsafe extern "C" fn mp_transpile(input: *const c_char, out_len: *mut usize) -> *mut u8 {
+    if input.is_null() {
+        return ptr::null_mut();
+    }
+    let markdown = String::from_utf8_lossy(CStr::from_ptr(input).to_bytes());
+    let options = Options {
+        highlight: true,
+        init: true,
+        ..Default::default()
+    };
+
+    let mut bytes = render_markdown(&markdown, &options)
+        .bytes
+        .into_boxed_slice();
+    *out_len = bytes.len();
+    let res = bytes.as_mut_ptr();
+    mem::forget(bytes);
+
+    res
+}
+
+/// Frees a result of `mp_transpile`, given the length it reported.
+///
+/// # Safety
+///
+/// `bytes` must be null or a result of `mp_transpile` with its length, not
+/// already freed.
+#[no_mangle]
+pub unsafe extern "C" fn mp_free(bytes: *mut u8, len: usize) {
+    if !bytes.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(bytes, len)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::slice;
+
+    #[test]
+    fn transpiles_c_strings() {
+        let mut len = 0;
+        unsafe {
+            let out = mp_transpile(c"# Hi".as_ptr(), &mut len);
+            assert!(slice::from_raw_parts(out, len).starts_with(b"\x1B@\n\n\x1BE"));
+            mp_free(out, len);
+
+            assert!(mp_transpile(ptr::null(), &mut len).is_null());
+        }
+    }
+}
@@ -0,0 +1,409 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use mdtoepson::dialect::Dialect;
+
+const ESC: u8 = 0x1B;
+const FS: u8 = 0x1C;
+const GS: u8 = 0x1D;
+
+/// A step the rendered bytes pass through on their way to the printer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Filter {
+    /// Drops style commands that change nothing before the next text
+    Optimize,
+    /// Drops commands that change the printer's stored setup, and control
+    /// bytes in the text
+    Sanitize,
+    /// Prints the bytes as offsets, hex and ASCII instead
+    Hexdump,
+    /// Shell command reading the bytes on stdin and writing new ones
+    Command(String),
+}
+
+impl Filter {
+    /// A built-in filter by its name, or else a shell command.
+    pub fn parse(s: &str) -> Self {
+        match s.trim() {
+            "optimize" => Filter::Optimize,
+            "sanitize" => Filter::Sanitize,
+            "hexdump" => Filter::Hexdump,
+            command => Filter::Command(command.to_string()),
+        }
+    }
+
+    pub fn apply(&self, bytes: Vec<u8>, dialect: Dialect) -> Result<Vec<u8>, String> {
+        match self {
+            Filter::Optimize => Ok(optimize(&bytes, dialect)),
+            Filter::Sanitize => Ok(sanitize(&bytes, dialect)),
+            Filter::Hexdump => Ok(hexdump(&bytes)),
+            Filter::Command(command) => run(command, bytes),
+        }
+    }
+}
+
+/// A run of printed bytes, or one whole command with its parameters and data.
+#[derive(Debug, PartialEq, Eq)]
+enum Segment<'a> {
+    Text(&'a [u8]),
+    Command(&'a [u8]),
+}
+
+/// Splits output into text and commands, so filters never look inside the
+/// data of an image or barcode.
+fn segments(bytes: &[u8], dialect: Dialect) -> Vec<Segment<'_>> {
+    let escapes = matches!(dialect, Dialect::EscP | Dialect::EscPos);
+    let mut res = Vec::new();
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let is_command = |b: &u8| escapes && matches!(*b, ESC | FS | GS);
+        let length = match is_command(&rest[0]) {
+            true => command_length(rest, dialect).min(rest.len()),
+            false => rest.iter().position(is_command).unwrap_or(rest.len()),
+        };
+        let (segment, after) = rest.split_at(length);
+        res.push(match is_command(&rest[0]) {
+            true => Segment::Command(segment),
+            false => Segment::Text(segment),
+        });
+        rest = after;
+    }
+
+    res
+}
+
+/// Splits output after each line feed in its text, keeping every command
+/// whole even when its data holds a 0x0A byte.
+pub fn lines(bytes: &[u8], dialect: Dialect) -> Vec<&[u8]> {
+    let mut res = Vec::new();
+    let (mut start, mut at) = (0, 0);
+    for segment in segments(bytes, dialect) {
+        if let Segment::Text(text) = segment {
+            let feeds = text.iter().enumerate().filter(|(_, &b)| b == b'\n');
+            for end in feeds.map(|(i, _)| at + i + 1) {
+                res.push(&bytes[start..end]);
+                start = end;
+            }
+        }
+        let (Segment::Text(segment) | Segment::Command(segment)) = segment;
+        at += segment.len();
+    }
+    if start < bytes.len() {
+        res.push(&bytes[start..]);
+    }
+
+    res
+}
+
+/// Length of the command at the start of `bytes`, as far as the commands
+/// this program and common drivers send go. Unknown ones are taken to have
+/// one parameter.
+fn command_length(bytes: &[u8], dialect: Dialect) -> usize {
+    let at = |i: usize| usize::from(bytes.get(i).copied().unwrap_or(0));
+    let word = |i: usize| at(i) + at(i + 1) * 256;
+    let until_nul = |from: usize| {
+        bytes[from.min(bytes.len())..]
+            .iter()
+            .position(|&b| b == 0)
+            .map_or(bytes.len(), |i| from + i + 1)
+    };
+
+    match (dialect, bytes[0], at(1) as u8) {
+        // ESC ( x, GS ( x and FS ( x carry their parameter length
+        (_, _, b'(') => 5 + word(3),
+
+        (Dialect::EscP, ESC, b'@' | b'E' | b'F' | b'4' | b'5' | b'T' | b'P' | b'M' | b'g')
+        | (Dialect::EscP, ESC, b'0' | b'1' | b'2' | b'G' | b'H' | b'O') => 2,
+        (Dialect::EscP, ESC, b'$' | b'\\') => 4,
+        (Dialect::EscP, ESC, b'C') if at(2) == 0 => 4,
+        (Dialect::EscP, ESC, b'B' | b'D') => until_nul(2),
+        (Dialect::EscP, ESC, b'*') => {
+            let per_column = match at(2) {
+                0..=7 => 1,
+                32..=40 => 3,
+                _ => 6,
+            };
+            5 + word(3) * per_column
+        }
+        (Dialect::EscP, ESC, b'K' | b'L' | b'Y' | b'Z') => 4 + word(2),
+        (Dialect::EscP, ESC, b'&') => {
+            // ESC & NUL n m, then for each character a0 a1 a2 and a1 columns
+            // of three bytes
+            let mut length = 5;
+            for _ in at(3)..=at(4) {
+                length += 3 + at(length + 1) * 3;
+            }
+            length
+        }
+        (Dialect::EscP, ESC, _) => 3,
+
+        (_, ESC, b'@' | b'2' | b'S' | b'L' | b'i' | b'm') => 2,
+        (_, ESC, b'$' | b'\\' | b'B' | b'c') => 4,
+        (_, ESC, b'p') => 5,
+        (_, ESC, b'W') => 10,
+        (_, ESC, b'D') => until_nul(2),
+        (_, ESC, b'*') => 5 + word(3) * if at(2) >= 32 { 3 } else { 1 },
+        (_, ESC, b'&') => {
+            // ESC & y c1 c2, then for each character x and y × x bytes
+            let mut length = 5;
+            for _ in at(3)..=at(4) {
+                length += 1 + at(2) * at(length);
+            }
+            length
+        }
+        (_, GS, b'V') if at(2) >= 65 => 4,
+        (_, GS, b'k') if at(2) <= 6 => until_nul(3),
+        (_, GS, b'k') => 4 + at(3),
+        (_, GS, b'v') => 8 + word(4) * word(6),
+        (_, GS, b'*') => 4 + at(2) * at(3) * 8,
+        (_, GS, b'\x0C' | b':') => 2,
+        (_, GS, b'L' | b'W' | b'P' | b'$' | b'\\') => 4,
+        (_, FS, b'p') => 4,
+        (_, FS, b'.' | b'&') => 2,
+        _ => 3,
+    }
+}
+
+/// Which printer setting a command sets, for commands that only set one.
+fn setting(command: &[u8], dialect: Dialect) -> Option<&[u8]> {
+    let key = command.get(..2)?;
+    let settings: &[&[u8]] = match dialect {
+        Dialect::EscP => &[b"\x1B-", b"\x1Bw", b"\x1BW", b"\x1Br", b"\x1Bt"],
+        _ => &[
+            b"\x1BE", b"\x1B-", b"\x1BM", b"\x1Br", b"\x1Bt", b"\x1Ba", b"\x1D!", b"\x1DB",
+        ],
+    };
+    // ESC/P toggles some styles with a command each way
+    let pairs: &[(&[u8], &[u8])] = match dialect {
+        Dialect::EscP => &[
+            (b"\x1BE", b"\x1BF"),
+            (b"\x1B4", b"\x1B5"),
+            (b"\x1BS", b"\x1BT"),
+            (b"\x1BP", b"\x1BM"),
+        ],
+        _ => &[],
+    };
+
+    settings
+        .iter()
+        .find(|setting| **setting == key)
+        .copied()
+        .or_else(|| {
+            pairs
+                .iter()
+                .find(|(on, off)| *on == key || *off == key)
+                .map(|(on, _)| *on)
+        })
+}
+
+/// Drops style commands overridden before any text prints, and ones that
+/// set what's already set. Anything else it doesn't know stays, and makes it
+/// forget what's set.
+fn optimize(bytes: &[u8], dialect: Dialect) -> Vec<u8> {
+    let mut res = Vec::with_capacity(bytes.len());
+    // Settings last sent, and ones waiting for text to print
+    let mut current: Vec<(&[u8], &[u8])> = Vec::new();
+    let mut pending: Vec<(&[u8], &[u8])> = Vec::new();
+    for segment in segments(bytes, dialect) {
+        match segment {
+            Segment::Command(command) => match setting(command, dialect) {
+                Some(key) => {
+                    pending.retain(|(k, _)| *k != key);
+                    pending.push((key, command));
+                }
+                None => {
+                    flush(&mut res, &mut pending, &mut current);
+                    current.clear();
+                    res.extend_from_slice(command);
+                }
+            },
+            Segment::Text(text) => {
+                flush(&mut res, &mut pending, &mut current);
+                res.extend_from_slice(text);
+            }
+        }
+    }
+    flush(&mut res, &mut pending, &mut current);
+
+    res
+}
+
+/// Writes the settings waiting for text, other than ones already in effect.
+fn flush<'a>(
+    res: &mut Vec<u8>,
+    pending: &mut Vec<(&'a [u8], &'a [u8])>,
+    current: &mut Vec<(&'a [u8], &'a [u8])>,
+) {
+    for (key, command) in pending.drain(..) {
+        if current.contains(&(key, command)) {
+            continue;
+        }
+        current.retain(|(k, _)| *k != key);
+        current.push((key, command));
+        res.extend_from_slice(command);
+    }
+}
+
+/// Drops commands that write the printer's non-volatile memory or change its
+/// power-on setup, and control bytes in the text other than line, page and
+/// tab breaks.
+fn sanitize(bytes: &[u8], dialect: Dialect) -> Vec<u8> {
+    let mut res = Vec::with_capacity(bytes.len());
+    for segment in segments(bytes, dialect) {
+        match segment {
+            Segment::Command(command) => {
+                let stored = match dialect {
+                    // Remote mode changes the defaults kept across power cycles
+                    Dialect::EscP => command.starts_with(b"\x1B(R"),
+                    // User setup, NV user memory, NV graphics and NV bit images
+                    _ => {
+                        command.starts_with(b"\x1D(E")
+                            || command.starts_with(b"\x1D(C")
+                            || command.starts_with(b"\x1D(L")
+                            || command.starts_with(b"\x1Cq")
+                            || command.starts_with(b"\x1Cg")
+                    }
+                };
+                if !stored {
+                    res.extend_from_slice(command);
+                }
+            }
+            Segment::Text(text) => res.extend(
+                text.iter()
+                    .filter(|&&b| b >= 0x20 || matches!(b, b'\n' | b'\r' | b'\t' | b'\x0C')),
+            ),
+        }
+    }
+
+    res
+}
+
+/// Sixteen bytes a line, like `hexdump -C`.
+fn hexdump(bytes: &[u8]) -> Vec<u8> {
+    let mut res = String::new();
+    for (i, line) in bytes.chunks(16).enumerate() {
+        let hex = line
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let ascii = line
+            .iter()
+            .map(|&b| match b {
+                0x20..=0x7E => char::from(b),
+                _ => '.',
+            })
+            .collect::<String>();
+        res.push_str(&format!("{:08x}  {:<47}  |{}|\n", i * 16, hex, ascii));
+    }
+    res.push_str(&format!("{:08x}\n", bytes.len()));
+
+    res.into_bytes()
+}
+
+/// Pipes the bytes through a shell command, taking what it writes instead.
+fn run(command: &str, bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+    let mut shell = match cfg!(windows) {
+        true => {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C");
+            shell
+        }
+        false => {
+            let mut shell = Command::new("sh");
+            shell.arg("-c");
+            shell
+        }
+    };
+    let mut child = shell
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run filter `{}`: {}", command, e))?;
+
+    // Written from another thread so a filter that writes as it reads can't
+    // fill its output pipe and stall
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let writer = thread::spawn(move || stdin.write_all(&bytes));
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("filter `{}` failed: {}", command, e))?;
+    let _ = writer.join();
+
+    match output.status.success() {
+        true => Ok(output.stdout),
+        false => Err(format!("filter `{}` failed: {}", command, output.status)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn images_are_one_segment() {
+        let bytes = b"a\x1Dv0\x00\x01\x00\x02\x00\x1B\x45\x1BE\x01b";
+
+        assert_eq!(
+            segments(bytes, Dialect::EscPos),
+            [
+                Segment::Text(b"a"),
+                Segment::Command(b"\x1Dv0\x00\x01\x00\x02\x00\x1B\x45"),
+                Segment::Command(b"\x1BE\x01"),
+                Segment::Text(b"b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_end_only_at_line_feeds_in_the_text() {
+        let bytes = b"a\n\x1Dv0\x00\x01\x00\x02\x00\x0A\x0A\nb";
+
+        assert_eq!(
+            lines(bytes, Dialect::EscPos),
+            [&b"a\n"[..], b"\x1Dv0\x00\x01\x00\x02\x00\x0A\x0A\n", b"b"]
+        );
+    }
+
+    #[test]
+    fn optimize_drops_settings_that_change_nothing() {
+        let bytes = b"\x1BE\x01\x1BE\x00a\x1BE\x00\n\x1BE\x00\x1B-\x00\x1BE\x01b";
+
+        assert_eq!(
+            optimize(bytes, Dialect::EscPos),
+            b"\x1BE\x00a\n\x1B-\x00\x1BE\x01b"
+        );
+        assert_eq!(
+            optimize(b"\x1BEa\x1BF\x1BE\x1BFb", Dialect::EscP),
+            b"\x1BEa\x1BFb"
+        );
+    }
+
+    #[test]
+    fn sanitize_keeps_printing_commands() {
+        let bytes = b"\x1D(E\x03\x00\x05\x03\x02a\x07\x1BE\x01b\n";
+
+        assert_eq!(sanitize(bytes, Dialect::EscPos), b"a\x1BE\x01b\n");
+    }
+
+    #[test]
+    fn hexdump_shows_offsets_and_text() {
+        assert_eq!(
+            String::from_utf8(hexdump(b"\x1B@hi")).unwrap(),
+            "00000000  1b 40 68 69                                      |.@hi|\n00000004\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commands_filter_through_the_shell() {
+        let filtered = Filter::parse("tr a-z A-Z").apply(b"hi".to_vec(), Dialect::Plain);
+
+        assert_eq!(filtered, Ok(b"HI".to_vec()));
+        assert!(Filter::parse("false")
+            .apply(Vec::new(), Dialect::Plain)
+            .is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use clap::ValueEnum;
+
+const STX: u8 = 0x02;
+const ETX: u8 = 0x03;
+
+/// Bytes around each job, for serial print controllers and pass-through
+/// boxes that take packets rather than a raw stream.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Frame {
+    /// The job as it is
+    #[default]
+    None,
+    /// STX before the job and ETX after it. Images and barcodes can hold an
+    /// ETX byte, so controllers that stop at the first one need
+    /// length-prefixed
+    StxEtx,
+    /// The job's length in four bytes, most significant first, before it
+    LengthPrefixed,
+}
+
+impl Frame {
+    pub fn wrap(self, bytes: &[u8]) -> Result<Vec<u8>, String> {
+        match self {
+            Frame::LengthPrefixed => {
+                let length = u32::try_from(bytes.len()).map_err(|_| {
+                    format!(
+                        "{} byte job is too long for a length-prefixed frame",
+                        bytes.len()
+                    )
+                })?;
+                Ok([&length.to_be_bytes()[..], bytes].concat())
+            }
+            _ => Ok([self.open(), bytes, self.close()].concat()),
+        }
+    }
+
+    /// Bytes starting a job that's sent as it's rendered.
+    pub fn open(self) -> &'static [u8] {
+        match self {
+            Frame::StxEtx => &[STX],
+            _ => &[],
+        }
+    }
+
+    /// Bytes ending a job that's sent as it's rendered.
+    pub fn close(self) -> &'static [u8] {
+        match self {
+            Frame::StxEtx => &[ETX],
+            _ => &[],
+        }
+    }
+
+    /// Whether a job has to be held back and sent whole, its length coming
+    /// before any of it.
+    pub fn needs_whole_job(self) -> bool {
+        self == Frame::LengthPrefixed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jobs_are_wrapped_in_their_frame() {
+        assert_eq!(Frame::None.wrap(b"\x1B@hi").unwrap(), b"\x1B@hi");
+        assert_eq!(Frame::StxEtx.wrap(b"\x1B@hi").unwrap(), b"\x02\x1B@hi\x03");
+        assert_eq!(
+            Frame::LengthPrefixed.wrap(&[b'x'; 300]).unwrap(),
+            [&[0, 0, 1, 44][..], &[b'x'; 300]].concat()
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+
+use crate::dialect::Dialect;
+use crate::image::Bitmap;
+use crate::toml::{Table, Value};
+
+/// Dots in a Font A character cell, shared by ESC/P LQ and ESC/POS
+const CELL_HEIGHT: usize = 24;
+/// Highest code a user-defined character can replace
+const LAST_CODE: u8 = 0x7E;
+/// Lowest such code, leaving the space alone
+const FIRST_CODE: u8 = 0x21;
+
+/// Characters outside the codepage, drawn from bitmaps and downloaded to the
+/// printer's user-defined character set.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Glyphs {
+    slots: Vec<(char, u8, Bitmap)>,
+}
+
+impl Glyphs {
+    /// Reads a table of characters to rows of dots, `#` printed and `.` blank,
+    /// like `"❤" = ".#.#. ##### .###. ..#.."`.
+    pub fn from_table(table: &Table) -> Result<Self, String> {
+        let mut glyphs = Glyphs::default();
+        for (key, value) in &table.entries {
+            let mut chars = key.chars();
+            let (Some(c), None) = (chars.next(), chars.next()) else {
+                return Err(format!("glyph name {} is not a single character", key));
+            };
+            let Value::String(rows) = value else {
+                return Err(format!("glyph {} is not a string of rows", c));
+            };
+            glyphs.add(c, parse_rows(rows));
+        }
+
+        Ok(glyphs)
+    }
+
+    fn add(&mut self, c: char, bitmap: Bitmap) {
+        let used = self.slots.len() as u8;
+        if used <= LAST_CODE - FIRST_CODE {
+            self.slots.push((c, LAST_CODE - used, bitmap));
+        }
+    }
+
+    /// Code that prints `c` while the user-defined set is selected.
+    pub fn code(&self, c: char) -> Option<u8> {
+        self.slots
+            .iter()
+            .find(|(glyph, _, _)| *glyph == c)
+            .map(|(_, code, _)| *code)
+    }
+
+    /// Commands downloading every glyph, sent once at the start of a job.
+    pub fn define(&self, dialect: Dialect) -> Vec<u8> {
+        let mut res = Vec::new();
+        for (_, code, bitmap) in &self.slots {
+            match dialect {
+                Dialect::EscP => {
+                    // 10 cpi LQ cells are 36 dots wide, up to 29 of them printable
+                    let columns = columns(bitmap, 29);
+                    let left = (36 - columns.len()) / 2;
+                    let right = 36 - columns.len() - left;
+                    res.extend_from_slice(&[0x1B, b'&', 0, *code, *code]);
+                    res.extend_from_slice(&[left as u8, columns.len() as u8, right as u8]);
+                    res.extend(columns.concat());
+                }
+                Dialect::EscPos => {
+                    let columns = columns(bitmap, 12);
+                    res.extend_from_slice(&[0x1B, b'&', 3, *code, *code, columns.len() as u8]);
+                    res.extend(columns.concat());
+                }
+                Dialect::Plain | Dialect::Html | Dialect::Pdf => {}
+            }
+        }
+
+        res
+    }
+}
+
+/// Prints `code` from the user-defined set, then returns to the resident one.
+pub fn print(code: u8) -> [u8; 7] {
+    [0x1B, b'%', 1, code, 0x1B, b'%', 0]
+}
+
+fn parse_rows(rows: &str) -> Bitmap {
+    let rows = rows.split_whitespace().collect::<Vec<_>>();
+    let width = rows
+        .iter()
+        .map(|row| row.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut dots = Vec::with_capacity(width * rows.len());
+    for row in &rows {
+        let mut n = 0;
+        for c in row.chars() {
+            dots.push(matches!(c, '#' | 'X' | 'x' | '1' | '@'));
+            n += 1;
+        }
+        dots.extend(core::iter::repeat_n(false, width - n));
+    }
+
+    Bitmap {
+        width,
+        height: rows.len(),
+        dots,
+    }
+}
+
+/// Scales a glyph up to fill the cell and slices it into columns of three
+/// bytes, top dot in the high bit.
+fn columns(bitmap: &Bitmap, max_width: usize) -> Vec<[u8; 3]> {
+    if bitmap.width == 0 || bitmap.height == 0 {
+        return vec![[0; 3]];
+    }
+    let scale = (CELL_HEIGHT / bitmap.height)
+        .min(max_width / bitmap.width)
+        .max(1);
+    let width = (bitmap.width * scale).min(max_width);
+    let height = (bitmap.height * scale).min(CELL_HEIGHT);
+    let top = (CELL_HEIGHT - height) / 2;
+
+    (0..width)
+        .map(|x| {
+            let mut column = [0; 3];
+            for y in 0..height {
+                if bitmap.dots[y / scale * bitmap.width + x / scale] {
+                    let dot = top + y;
+                    column[dot / 8] |= 0x80 >> (dot % 8);
+                }
+            }
+            column
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toml;
+
+    #[test]
+    fn glyphs_take_codes_from_the_top_of_ascii() {
+        let tables = toml::parse("[glyphs]\n\"₿\" = \"#. .#\"\n\"❤\" = \"#\"").unwrap();
+        let glyphs = Glyphs::from_table(&tables[0]).unwrap();
+
+        assert_eq!(glyphs.code('₿'), Some(0x7E));
+        assert_eq!(glyphs.code('❤'), Some(0x7D));
+        assert_eq!(glyphs.code('x'), None);
+    }
+
+    #[test]
+    fn escpos_definitions_scale_to_the_cell() {
+        let tables = toml::parse("[glyphs]\n\"▮\" = \"#\"").unwrap();
+        let glyphs = Glyphs::from_table(&tables[0]).unwrap();
+        let defined = glyphs.define(Dialect::EscPos);
+
+        assert_eq!(&defined[..6], b"\x1B&\x03\x7E\x7E\x0C");
+        assert_eq!(defined.len(), 6 + 12 * 3);
+        assert!(defined[6..]
+            .chunks(3)
+            .all(|column| column == [0x03, 0xFF, 0xC0]));
+    }
+}
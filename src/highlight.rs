@@ -0,0 +1,292 @@
+use alloc::vec::Vec;
+
+/// Lexical class of a span of source code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Class {
+    Plain,
+    Keyword,
+    String,
+    Comment,
+}
+
+/// Just enough of a language's syntax to pick out its keywords, strings and comments.
+#[derive(Debug)]
+struct Syntax {
+    names: &'static [&'static str],
+    keywords: &'static [&'static str],
+    line_comment: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+}
+
+const SYNTAXES: &[Syntax] = &[
+    Syntax {
+        names: &["rust", "rs"],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+            "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+            "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super",
+            "trait", "true", "type", "unsafe", "use", "where", "while",
+        ],
+        line_comment: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"'],
+    },
+    Syntax {
+        names: &[
+            "c", "h", "cpp", "c++", "cc", "hpp", "java", "cs", "csharp", "go",
+        ],
+        keywords: &[
+            "auto",
+            "break",
+            "case",
+            "catch",
+            "char",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "do",
+            "double",
+            "else",
+            "enum",
+            "extern",
+            "false",
+            "float",
+            "for",
+            "func",
+            "goto",
+            "if",
+            "import",
+            "int",
+            "long",
+            "namespace",
+            "new",
+            "package",
+            "private",
+            "protected",
+            "public",
+            "return",
+            "short",
+            "signed",
+            "sizeof",
+            "static",
+            "struct",
+            "switch",
+            "template",
+            "this",
+            "throw",
+            "true",
+            "try",
+            "typedef",
+            "union",
+            "unsigned",
+            "using",
+            "var",
+            "virtual",
+            "void",
+            "volatile",
+            "while",
+        ],
+        line_comment: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+    },
+    Syntax {
+        names: &["javascript", "js", "typescript", "ts", "jsx", "tsx"],
+        keywords: &[
+            "async",
+            "await",
+            "break",
+            "case",
+            "catch",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "delete",
+            "do",
+            "else",
+            "export",
+            "extends",
+            "false",
+            "finally",
+            "for",
+            "from",
+            "function",
+            "if",
+            "import",
+            "in",
+            "instanceof",
+            "interface",
+            "let",
+            "new",
+            "null",
+            "of",
+            "return",
+            "super",
+            "switch",
+            "this",
+            "throw",
+            "true",
+            "try",
+            "type",
+            "typeof",
+            "undefined",
+            "var",
+            "void",
+            "while",
+            "yield",
+        ],
+        line_comment: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+    },
+    Syntax {
+        names: &["python", "py"],
+        keywords: &[
+            "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class",
+            "continue", "def", "del", "elif", "else", "except", "finally", "for", "from", "global",
+            "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise",
+            "return", "try", "while", "with", "yield",
+        ],
+        line_comment: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Syntax {
+        names: &["sh", "bash", "shell", "zsh", "console"],
+        keywords: &[
+            "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+            "in", "local", "return", "then", "until", "while",
+        ],
+        line_comment: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Syntax {
+        names: &["sql"],
+        keywords: &[
+            "and", "as", "by", "create", "delete", "from", "group", "insert", "into", "join",
+            "not", "null", "on", "or", "order", "select", "set", "table", "update", "values",
+            "where", "AND", "AS", "BY", "CREATE", "DELETE", "FROM", "GROUP", "INSERT", "INTO",
+            "JOIN", "NOT", "NULL", "ON", "OR", "ORDER", "SELECT", "SET", "TABLE", "UPDATE",
+            "VALUES", "WHERE",
+        ],
+        line_comment: &["--"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['\''],
+    },
+];
+
+/// Splits `code` into classified spans, or `None` for a language we don't know.
+pub fn highlight<'a>(language: &str, code: &'a str) -> Option<Vec<(Class, &'a str)>> {
+    let language = language.to_ascii_lowercase();
+    let syntax = SYNTAXES
+        .iter()
+        .find(|s| s.names.contains(&language.as_str()))?;
+
+    let mut spans = Vec::new();
+    let mut plain_start = 0;
+    let mut i = 0;
+    while i < code.len() {
+        let rest = &code[i..];
+        let c = rest.chars().next().unwrap();
+
+        let special =
+            if let Some(prefix) = syntax.line_comment.iter().find(|p| rest.starts_with(**p)) {
+                let end = rest.find('\n').unwrap_or(rest.len());
+                Some((Class::Comment, end.max(prefix.len())))
+            } else if let Some((open, close)) = syntax
+                .block_comment
+                .filter(|(open, _)| rest.starts_with(open))
+            {
+                let end = rest[open.len()..]
+                    .find(close)
+                    .map_or(rest.len(), |end| open.len() + end + close.len());
+                Some((Class::Comment, end))
+            } else if syntax.quotes.contains(&c) {
+                Some((Class::String, string_len(rest, c)))
+            } else if c.is_alphabetic() || c == '_' {
+                let end = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                let word = &rest[..end];
+                if syntax.keywords.contains(&word) {
+                    Some((Class::Keyword, end))
+                } else {
+                    // Skip whole identifiers so keywords aren't found inside them
+                    i += end;
+                    continue;
+                }
+            } else {
+                None
+            };
+
+        match special {
+            Some((class, len)) => {
+                if plain_start < i {
+                    spans.push((Class::Plain, &code[plain_start..i]));
+                }
+                spans.push((class, &code[i..i + len]));
+                i += len;
+                plain_start = i;
+            }
+            None => i += c.len_utf8(),
+        }
+    }
+    if plain_start < code.len() {
+        spans.push((Class::Plain, &code[plain_start..]));
+    }
+
+    Some(spans)
+}
+
+/// Length of a string literal opened by `quote`, stopping at the end of the line.
+fn string_len(rest: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, c) in rest.char_indices().skip(1) {
+        match c {
+            '\n' => return i,
+            '\\' if !escaped => escaped = true,
+            c if c == quote && !escaped => return i + c.len_utf8(),
+            _ => escaped = false,
+        }
+    }
+
+    rest.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn classifies_rust_keywords_strings_and_comments() {
+        let spans = highlight("rust", "let s = \"hi\"; // greet").unwrap();
+
+        assert_eq!(
+            spans,
+            vec![
+                (Class::Keyword, "let"),
+                (Class::Plain, " s = "),
+                (Class::String, "\"hi\""),
+                (Class::Plain, "; "),
+                (Class::Comment, "// greet"),
+            ]
+        );
+    }
+
+    #[test]
+    fn keywords_inside_identifiers_are_plain() {
+        let spans = highlight("python", "format_if = 1").unwrap();
+
+        assert_eq!(spans, vec![(Class::Plain, "format_if = 1")]);
+    }
+
+    #[test]
+    fn unknown_languages_are_not_highlighted() {
+        assert_eq!(highlight("brainfuck", "+[-]"), None);
+    }
+}
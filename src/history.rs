@@ -0,0 +1,232 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::substitute::{civil_time, state_dir};
+
+/// Jobs whose bytes are kept for reprinting; older ones keep only their log line
+const KEPT_JOBS: u64 = 100;
+
+/// One line of the job log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Job {
+    pub id: u64,
+    /// Seconds since the Unix epoch, in UTC
+    pub timestamp: u64,
+    pub source: String,
+    pub bytes: usize,
+    pub backend: String,
+    pub ok: bool,
+    /// FNV-1a hash of the output, for spotting duplicates
+    pub hash: u64,
+}
+
+impl Job {
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{:016x}\n",
+            self.id,
+            self.timestamp,
+            self.source.replace(['\t', '\n'], " "),
+            self.bytes,
+            self.backend.replace(['\t', '\n'], " "),
+            if self.ok { "ok" } else { "failed" },
+            self.hash
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let job = Job {
+            id: fields.next()?.parse().ok()?,
+            timestamp: fields.next()?.parse().ok()?,
+            source: fields.next()?.to_string(),
+            bytes: fields.next()?.parse().ok()?,
+            backend: fields.next()?.to_string(),
+            ok: fields.next()? == "ok",
+            // Logs from before hashing have no hash column
+            hash: fields
+                .next()
+                .and_then(|hash| u64::from_str_radix(hash, 16).ok())
+                .unwrap_or(0),
+        };
+
+        Some(job)
+    }
+}
+
+impl std::fmt::Display for Job {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (date, time) = civil_time(self.timestamp);
+        write!(
+            f,
+            "{:>5}  {} {}  {:<6}  {:>7} bytes  {} -> {}",
+            self.id,
+            date,
+            time,
+            if self.ok { "ok" } else { "failed" },
+            self.bytes,
+            self.source,
+            self.backend
+        )
+    }
+}
+
+/// The job log and saved output, in the state directory.
+#[derive(Debug)]
+pub struct History {
+    dir: PathBuf,
+}
+
+impl History {
+    pub fn open() -> Option<Self> {
+        state_dir().map(|dir| History {
+            dir: dir.join("history"),
+        })
+    }
+
+    fn log_path(&self) -> PathBuf {
+        self.dir.join("jobs.log")
+    }
+
+    fn output_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{}.bin", id))
+    }
+
+    /// Every logged job, oldest first.
+    pub fn jobs(&self) -> Vec<Job> {
+        fs::read_to_string(self.log_path())
+            .unwrap_or_default()
+            .lines()
+            .filter_map(Job::from_line)
+            .collect()
+    }
+
+    /// Output of a past job, while it's still kept.
+    pub fn output(&self, id: u64) -> io::Result<Vec<u8>> {
+        fs::read(self.output_path(id))
+    }
+
+    /// The latest successful job with the same output, printed less than
+    /// `window` seconds ago.
+    pub fn duplicate(&self, output: &[u8], window: u64) -> Option<Job> {
+        let hash = fnv1a(output);
+        let since = now().saturating_sub(window);
+
+        self.jobs()
+            .into_iter()
+            .rev()
+            .take_while(|job| job.timestamp >= since)
+            .find(|job| job.ok && job.hash == hash && job.bytes == output.len())
+    }
+
+    /// Logs a job and keeps its output, dropping the output of old jobs.
+    pub fn record(&self, source: &str, backend: &str, output: &[u8], ok: bool) -> io::Result<Job> {
+        fs::create_dir_all(&self.dir)?;
+        let job = Job {
+            id: self.jobs().last().map_or(1, |job| job.id + 1),
+            timestamp: now(),
+            source: source.to_string(),
+            bytes: output.len(),
+            backend: backend.to_string(),
+            ok,
+            hash: fnv1a(output),
+        };
+
+        fs::write(self.output_path(job.id), output)?;
+        if job.id > KEPT_JOBS {
+            let _ = fs::remove_file(self.output_path(job.id - KEPT_JOBS));
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_path())?
+            .write_all(job.to_line().as_bytes())?;
+
+        Ok(job)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Reads a duration like `90`, `30s`, `10m`, `2h` or `1d` as seconds.
+pub fn parse_duration(s: &str) -> Result<u64, String> {
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| format!("{} is not a duration like 30s or 10m", s))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3_600,
+        "d" => 86_400,
+        _ => return Err(format!("unknown unit {}, expected s, m, h or d", unit)),
+    };
+
+    Ok(number * seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_lines_round_trip() {
+        let job = Job {
+            id: 7,
+            timestamp: 951_782_400,
+            source: String::from("notes\tdraft.md"),
+            bytes: 120,
+            backend: String::from("/dev/usb/lp0"),
+            ok: false,
+            hash: 0xdead_beef,
+        };
+        let parsed = Job::from_line(job.to_line().trim_end()).unwrap();
+
+        assert_eq!(parsed.source, "notes draft.md");
+        assert_eq!(
+            Job {
+                source: job.source.replace('\t', " "),
+                ..job
+            },
+            parsed
+        );
+    }
+
+    #[test]
+    fn records_and_reads_back_jobs() {
+        let dir = std::env::temp_dir().join(format!("mdtoepson-history-{}", std::process::id()));
+        let history = History { dir: dir.clone() };
+
+        history.record("a.md", "stdout", b"one", true).unwrap();
+        let second = history.record("b.md", "stdout", b"two", false).unwrap();
+
+        assert_eq!(second.id, 2);
+        assert_eq!(history.jobs().len(), 2);
+        assert_eq!(history.output(2).unwrap(), b"two");
+        assert_eq!(history.duplicate(b"one", 60).map(|job| job.id), Some(1));
+        assert_eq!(history.duplicate(b"two", 60), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn durations_take_a_unit() {
+        assert_eq!(parse_duration("90"), Ok(90));
+        assert_eq!(parse_duration("10m"), Ok(600));
+        assert!(parse_duration("1w").is_err());
+    }
+}
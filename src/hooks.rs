@@ -0,0 +1,77 @@
+use std::process::Command;
+
+/// What a hook is told about the job it follows.
+#[derive(Debug)]
+pub struct Finished<'a> {
+    pub source: &'a str,
+    pub backend: &'a str,
+    pub bytes: usize,
+    /// Id in the job history, when the job was recorded there
+    pub job_id: Option<u64>,
+    pub error: Option<&'a str>,
+}
+
+/// Runs a shell command after a job, with the job described in `MDTOEPSON_*`
+/// variables. A hook that fails is reported, but doesn't fail the job.
+pub fn run(command: &str, job: &Finished) {
+    let mut shell = match cfg!(windows) {
+        true => {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C");
+            shell
+        }
+        false => {
+            let mut shell = Command::new("sh");
+            shell.arg("-c");
+            shell
+        }
+    };
+    shell
+        .arg(command)
+        .env("MDTOEPSON_SOURCE", job.source)
+        .env("MDTOEPSON_BACKEND", job.backend)
+        .env("MDTOEPSON_BYTES", job.bytes.to_string())
+        .env(
+            "MDTOEPSON_STATUS",
+            if job.error.is_some() { "failed" } else { "ok" },
+        )
+        .env("MDTOEPSON_ERROR", job.error.unwrap_or_default());
+    if let Some(id) = job.job_id {
+        shell.env("MDTOEPSON_JOB_ID", id.to_string());
+    }
+
+    match shell.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!("warning: hook `{}` failed: {}", command, status),
+        Err(e) => eprintln!("warning: could not run hook `{}`: {}", command, e),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hooks_see_the_job() {
+        let dir = std::env::temp_dir().join(format!("mdtoepson-hook-{}", std::process::id()));
+        let job = Finished {
+            source: "note.md",
+            backend: "stdout",
+            bytes: 12,
+            job_id: Some(7),
+            error: Some("printer on fire"),
+        };
+
+        run(
+            &format!(
+                "echo \"$MDTOEPSON_SOURCE $MDTOEPSON_BYTES $MDTOEPSON_JOB_ID $MDTOEPSON_STATUS: $MDTOEPSON_ERROR\" > {}",
+                dir.display()
+            ),
+            &job,
+        );
+
+        let seen = std::fs::read_to_string(&dir).unwrap();
+        let _ = std::fs::remove_file(&dir);
+        assert_eq!(seen, "note.md 12 7 failed: printer on fire\n");
+    }
+}
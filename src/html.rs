@@ -0,0 +1,59 @@
+use alloc::format;
+use alloc::vec::Vec;
+
+/// Column count used when the layout leaves the width open.
+const DEFAULT_COLUMNS: usize = 48;
+
+/// Wraps a body rendered with the HTML dialect in a page styled like a receipt.
+pub fn page(body: &[u8], columns: Option<usize>) -> Vec<u8> {
+    let columns = columns.unwrap_or(DEFAULT_COLUMNS);
+    let mut res = Vec::new();
+
+    res.extend_from_slice(
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Receipt preview</title>
+<style>
+body {{ background: #d8d8d8; margin: 2em 0; }}
+pre {{
+  width: {columns}ch;
+  margin: 0 auto;
+  padding: 2ch;
+  background: #fffff8;
+  box-shadow: 0 2px 6px rgba(0, 0, 0, 0.3);
+  font-family: "Courier New", Courier, monospace;
+  white-space: pre;
+}}
+.double {{ font-size: 200%; line-height: 1; }}
+.inverse {{ background: #000; color: #fffff8; }}
+.red {{ color: #c00; }}
+.tall {{ display: inline-block; transform: scaleY(2); margin: 0.5em 0; }}
+</style>
+</head>
+<body>
+<pre>"#
+        )
+        .as_bytes(),
+    );
+    res.extend_from_slice(body);
+    res.extend_from_slice(b"</pre>\n</body>\n</html>\n");
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+
+    #[test]
+    fn page_sizes_paper_to_the_column_count() {
+        let html = String::from_utf8(page(b"<b>hi</b>\n", Some(32))).unwrap();
+
+        assert!(html.contains("width: 32ch;"));
+        assert!(html.contains("<pre><b>hi</b>\n</pre>"));
+    }
+}
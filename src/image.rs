@@ -0,0 +1,461 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use crate::dialect::Dialect;
+
+/// How grey levels are reduced to the printer's black and white dots.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(clap::ValueEnum))]
+pub enum Dither {
+    /// Error diffusion, best for photos
+    #[default]
+    FloydSteinberg,
+    /// 8x8 Bayer matrix, a regular pattern that survives smudgy heads
+    Ordered,
+    /// Plain cut-off at mid grey, best for line art
+    Threshold,
+}
+
+/// Tone adjustments and dithering applied before an image is printed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageOptions {
+    pub dither: Dither,
+    /// Added to every grey level, from -255 to 255
+    pub brightness: i32,
+    /// Multiplier for the distance of every grey level from mid grey
+    pub contrast: f32,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        ImageOptions {
+            dither: Dither::default(),
+            brightness: 0,
+            contrast: 1.0,
+        }
+    }
+}
+
+/// An 8-bit greyscale image, 0 being black.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Gray {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+/// A 1-bit image, `true` being a printed dot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pub dots: Vec<bool>,
+}
+
+impl Gray {
+    /// Reads a netpbm (PBM, PGM or PPM) image, in either plain or raw form.
+    #[cfg(feature = "std")]
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::decode(&fs::read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Images that ship with the program, named like `mdtoepson:gradient`.
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "mdtoepson:gradient" => {
+                let (width, height) = (256, 48);
+                let pixels = (0..width * height).map(|i| (i % width) as u8).collect();
+                Some(Gray {
+                    width,
+                    height,
+                    pixels,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let invalid = |message: &str| message.to_string();
+
+        let mut pos = 0;
+        let magic = next_field(data, &mut pos).ok_or_else(|| invalid("empty image"))?;
+        let kind = match magic {
+            b"P1" | b"P2" | b"P3" | b"P4" | b"P5" | b"P6" => magic[1] - b'0',
+            _ => return Err(invalid("not a netpbm image")),
+        };
+        let mut header = |name: &str| {
+            next_field(data, &mut pos)
+                .and_then(|field| core::str::from_utf8(field).ok()?.parse::<usize>().ok())
+                .ok_or_else(|| invalid(&format!("missing {}", name)))
+        };
+        let width = header("width")?;
+        let height = header("height")?;
+        let max = if kind == 1 || kind == 4 {
+            1
+        } else {
+            header("maximum value")?
+        };
+        if max == 0 || max > 255 {
+            return Err(invalid("unsupported maximum value"));
+        }
+        let channels = if kind == 3 || kind == 6 { 3 } else { 1 };
+        let count = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(channels))
+            .ok_or_else(|| invalid("image is too large"))?;
+        let raster = |len: Option<usize>| {
+            len.and_then(|len| data.get(pos + 1..(pos + 1).checked_add(len)?))
+                .ok_or_else(|| invalid("truncated image"))
+        };
+
+        let samples: Vec<usize> = match kind {
+            1..=3 => (0..count)
+                .map(|_| match kind {
+                    // Plain bitmaps may omit the whitespace between bits
+                    1 => next_bit(data, &mut pos),
+                    _ => next_field(data, &mut pos)
+                        .and_then(|field| core::str::from_utf8(field).ok()?.parse().ok())
+                        .map(|sample: usize| sample.min(max)),
+                })
+                .collect::<Option<_>>()
+                .ok_or_else(|| invalid("truncated image"))?,
+            4 => {
+                let row_bytes = width.div_ceil(8);
+                let raster = raster(row_bytes.checked_mul(height))?;
+                (0..count)
+                    .map(|i| {
+                        let (y, x) = (i / width, i % width);
+                        (raster[y * row_bytes + x / 8] >> (7 - x % 8) & 1) as usize
+                    })
+                    .collect()
+            }
+            _ => {
+                let raster = raster(Some(count))?;
+                raster.iter().map(|&b| b as usize).collect()
+            }
+        };
+
+        let pixels = samples
+            .chunks(channels)
+            .map(|sample| {
+                let level = match kind {
+                    // In bitmaps a set bit is black
+                    1 | 4 => (1 - sample[0].min(1)) * 255,
+                    _ if channels == 3 => {
+                        (sample[0] * 299 + sample[1] * 587 + sample[2] * 114) / 1000 * 255 / max
+                    }
+                    _ => sample[0] * 255 / max,
+                };
+                level.min(255) as u8
+            })
+            .collect();
+
+        Ok(Gray {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Shrinks the image to at most `max_width` pixels across, keeping its aspect.
+    pub fn fit(self, max_width: usize) -> Self {
+        if self.width <= max_width || max_width == 0 {
+            return self;
+        }
+
+        let height = (self.height * max_width / self.width).max(1);
+        let mut pixels = Vec::with_capacity(max_width * height);
+        for y in 0..height {
+            let (y0, y1) = (
+                y * self.height / height,
+                ((y + 1) * self.height / height).max(y * self.height / height + 1),
+            );
+            for x in 0..max_width {
+                let (x0, x1) = (
+                    x * self.width / max_width,
+                    ((x + 1) * self.width / max_width).max(x * self.width / max_width + 1),
+                );
+                let mut sum = 0;
+                for sy in y0..y1 {
+                    for sx in x0..x1 {
+                        sum += self.pixels[sy * self.width + sx] as usize;
+                    }
+                }
+                pixels.push((sum / ((y1 - y0) * (x1 - x0))) as u8);
+            }
+        }
+
+        Gray {
+            width: max_width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn adjust(&mut self, brightness: i32, contrast: f32) {
+        for pixel in self.pixels.iter_mut() {
+            let level = (*pixel as f32 - 128.0) * contrast + 128.0 + brightness as f32;
+            // Rounded by hand, as `f32::round` needs std
+            *pixel = (level + 0.5).clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    pub fn dither(&self, method: Dither) -> Bitmap {
+        let dots = match method {
+            Dither::Threshold => self.pixels.iter().map(|&p| p < 128).collect(),
+            Dither::Ordered => self
+                .pixels
+                .iter()
+                .enumerate()
+                .map(|(i, &p)| {
+                    let (y, x) = (i / self.width, i % self.width);
+                    let threshold = BAYER[y % 8][x % 8] as u32 * 4 + 2;
+                    (p as u32) < threshold
+                })
+                .collect(),
+            Dither::FloydSteinberg => {
+                let mut levels: Vec<i32> = self.pixels.iter().map(|&p| p as i32).collect();
+                let mut dots = vec![false; levels.len()];
+                for y in 0..self.height {
+                    for x in 0..self.width {
+                        let i = y * self.width + x;
+                        let black = levels[i] < 128;
+                        let error = levels[i] - if black { 0 } else { 255 };
+                        dots[i] = black;
+
+                        let mut spread = |dx: isize, dy: usize, weight: i32| {
+                            let nx = x as isize + dx;
+                            if nx >= 0 && (nx as usize) < self.width && y + dy < self.height {
+                                levels[(y + dy) * self.width + nx as usize] += error * weight / 16;
+                            }
+                        };
+                        spread(1, 0, 7);
+                        spread(-1, 1, 3);
+                        spread(0, 1, 5);
+                        spread(1, 1, 1);
+                    }
+                }
+                dots
+            }
+        };
+
+        Bitmap {
+            width: self.width,
+            height: self.height,
+            dots,
+        }
+    }
+}
+
+const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+impl Bitmap {
+    fn dot(&self, x: usize, y: usize) -> bool {
+        y < self.height && self.dots[y * self.width + x]
+    }
+
+    /// Graphics commands printing the bitmap on its own lines.
+    pub fn encode(&self, dialect: Dialect) -> Vec<u8> {
+        let mut res = Vec::new();
+        match dialect {
+            Dialect::EscPos => {
+                // GS v 0: one raster block, eight dots to a byte
+                let row_bytes = self.width.div_ceil(8);
+                res.extend_from_slice(b"\x1Dv0\x00");
+                res.extend_from_slice(&(row_bytes as u16).to_le_bytes());
+                res.extend_from_slice(&(self.height as u16).to_le_bytes());
+                for y in 0..self.height {
+                    for byte in 0..row_bytes {
+                        res.push(pack((0..8).map(|bit| {
+                            let x = byte * 8 + bit;
+                            x < self.width && self.dot(x, y)
+                        })));
+                    }
+                }
+            }
+            Dialect::EscP => {
+                // ESC * 39: 24 pin columns at 180 dpi, with 24/180" line feeds between bands
+                res.extend_from_slice(b"\x1B3\x18");
+                for band in (0..self.height).step_by(24) {
+                    res.extend_from_slice(b"\x1B*\x27");
+                    res.extend_from_slice(&(self.width as u16).to_le_bytes());
+                    for x in 0..self.width {
+                        for row in (0..24).step_by(8) {
+                            res.push(pack((0..8).map(|bit| self.dot(x, band + row + bit))));
+                        }
+                    }
+                    res.extend_from_slice(b"\r\n");
+                }
+                res.extend_from_slice(b"\x1B2");
+            }
+            Dialect::Plain | Dialect::Html | Dialect::Pdf => {}
+        }
+
+        res
+    }
+
+    /// Offsets of the line feeds `encode` ends each band with, where a page
+    /// can end without cutting through the image.
+    pub fn feeds(&self, dialect: Dialect) -> Vec<usize> {
+        match dialect {
+            // ESC 3, then ESC * with its width, three bytes a column and CR LF
+            Dialect::EscP => {
+                let band = 5 + self.width * 3 + 2;
+                (1..=self.height.div_ceil(24))
+                    .map(|n| 3 + n * band - 1)
+                    .collect()
+            }
+            // One raster block, which the printer won't split
+            Dialect::EscPos | Dialect::Plain | Dialect::Html | Dialect::Pdf => Vec::new(),
+        }
+    }
+}
+
+/// Packs eight dots into a byte, the first being the most significant bit.
+fn pack(bits: impl Iterator<Item = bool>) -> u8 {
+    bits.fold(0, |byte, dot| byte << 1 | dot as u8)
+}
+
+/// Next whitespace separated header field, skipping `#` comments.
+fn next_field<'a>(data: &'a [u8], pos: &mut usize) -> Option<&'a [u8]> {
+    loop {
+        while data.get(*pos)?.is_ascii_whitespace() {
+            *pos += 1;
+        }
+        if data[*pos] != b'#' {
+            break;
+        }
+        while *data.get(*pos)? != b'\n' {
+            *pos += 1;
+        }
+    }
+
+    let start = *pos;
+    while data.get(*pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+        *pos += 1;
+    }
+    Some(&data[start..*pos])
+}
+
+fn next_bit(data: &[u8], pos: &mut usize) -> Option<usize> {
+    while data.get(*pos)?.is_ascii_whitespace() {
+        *pos += 1;
+    }
+    let bit = match data[*pos] {
+        b'0' => 0,
+        b'1' => 1,
+        _ => return None,
+    };
+    *pos += 1;
+    Some(bit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRADIENT: &[u8] = b"P2\n# ramp\n4 1\n255\n0 100 150 255\n";
+
+    #[test]
+    fn decodes_plain_greymap() {
+        let image = Gray::decode(GRADIENT).unwrap();
+
+        assert_eq!(image.pixels, vec![0, 100, 150, 255]);
+    }
+
+    #[test]
+    fn oversized_headers_are_refused() {
+        let huge = b"P6\n4294967296 4294967296\n255\n\0\0\0";
+        assert_eq!(Gray::decode(huge).unwrap_err(), "image is too large");
+        let wide = b"P5\n18446744073709551615 1\n255\n\0";
+        assert_eq!(Gray::decode(wide).unwrap_err(), "truncated image");
+        let bright = b"P2\n1 1\n255\n18446744073709551615\n";
+        assert_eq!(Gray::decode(bright).unwrap().pixels, vec![255]);
+    }
+
+    #[test]
+    fn raw_bitmap_sets_black_for_one_bits() {
+        let image = Gray::decode(b"P4\n3 1\n\xA0").unwrap();
+
+        assert_eq!(image.pixels, vec![0, 255, 0]);
+    }
+
+    #[test]
+    fn threshold_cuts_at_mid_grey() {
+        let bitmap = Gray::decode(GRADIENT).unwrap().dither(Dither::Threshold);
+
+        assert_eq!(bitmap.dots, vec![true, true, false, false]);
+    }
+
+    #[test]
+    fn floyd_steinberg_keeps_average_tone() {
+        let image = Gray {
+            width: 16,
+            height: 16,
+            pixels: vec![128; 256],
+        };
+        let black = image
+            .dither(Dither::FloydSteinberg)
+            .dots
+            .iter()
+            .filter(|&&d| d)
+            .count();
+
+        assert!((112..=144).contains(&black), "{} black dots", black);
+    }
+
+    #[test]
+    fn brightness_and_contrast_clamp() {
+        let mut image = Gray::decode(GRADIENT).unwrap();
+        image.adjust(50, 2.0);
+
+        assert_eq!(image.pixels, vec![0, 122, 222, 255]);
+    }
+
+    #[test]
+    fn escpos_raster_packs_rows() {
+        let bitmap = Bitmap {
+            width: 9,
+            height: 1,
+            dots: vec![true, false, false, false, false, false, false, false, true],
+        };
+
+        assert_eq!(
+            bitmap.encode(Dialect::EscPos),
+            b"\x1Dv0\x00\x02\x00\x01\x00\x80\x80"
+        );
+    }
+
+    #[test]
+    fn bands_end_in_the_line_feeds_between_them() {
+        // A width of 10 puts a 0x0A byte in each ESC * command
+        let bitmap = Bitmap {
+            width: 10,
+            height: 30,
+            dots: vec![true; 300],
+        };
+        let encoded = bitmap.encode(Dialect::EscP);
+        let feeds = bitmap.feeds(Dialect::EscP);
+
+        assert_eq!(feeds.len(), 2);
+        assert!(feeds.iter().all(|&at| &encoded[at - 1..=at] == b"\r\n"));
+        assert_eq!(feeds.last(), Some(&(encoded.len() - 3)));
+    }
+}
@@ -0,0 +1,27 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod barcode;
+pub mod codepage;
+pub mod dialect;
+pub mod events;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod glyphs;
+pub mod highlight;
+pub mod html;
+pub mod image;
+pub mod lists;
+pub mod locale;
+pub mod paginate;
+pub mod paper;
+pub mod render;
+pub mod spacing;
+pub mod stats;
+pub mod table;
+pub mod template;
+pub mod toml;
+pub mod transliterate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,156 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+
+use crate::toml::Table;
+
+/// How list items are printed, in place of their markdown markers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListStyle {
+    /// Bullet for each nesting level, the last repeating for deeper ones
+    pub bullets: Vec<String>,
+    /// Columns each nesting level is indented by
+    pub indent: usize,
+    /// How numbered items are numbered, when not left to the locale
+    pub numbering: Option<Numbering>,
+}
+
+/// Markers of numbered list items.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Numbering {
+    /// `1.`
+    #[default]
+    Period,
+    /// `1)`
+    Parenthesis,
+    /// `a.`, running on to `aa.` after `z.`
+    Letter,
+}
+
+impl Numbering {
+    /// Reads a marker written the way it prints, like `1)`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim() {
+            "1." => Ok(Numbering::Period),
+            "1)" => Ok(Numbering::Parenthesis),
+            "a." => Ok(Numbering::Letter),
+            _ => Err(format!("{} is not a numbering, like 1. or 1) or a.", s)),
+        }
+    }
+
+    pub fn marker(self, number: usize) -> String {
+        match self {
+            Numbering::Period => format!("{}.", number),
+            Numbering::Parenthesis => format!("{})", number),
+            Numbering::Letter => {
+                let mut letters = Vec::new();
+                let mut n = number.max(1);
+                while n > 0 {
+                    n -= 1;
+                    letters.push(char::from(b'a' + (n % 26) as u8));
+                    n /= 26;
+                }
+                letters.iter().rev().chain(['.'].iter()).collect()
+            }
+        }
+    }
+}
+
+impl Default for ListStyle {
+    fn default() -> Self {
+        ListStyle {
+            bullets: vec![String::from("-")],
+            indent: 2,
+            numbering: None,
+        }
+    }
+}
+
+impl ListStyle {
+    /// Reads a profile's `[lists]` table, like `bullets = "•,◦,-"`, `indent = 4`
+    /// and `numbering = "1)"`.
+    pub fn from_table(table: &Table) -> Result<Self, String> {
+        let mut style = ListStyle::default();
+        if let Some(bullets) = table.string("bullets") {
+            style.bullets = parse_bullets(bullets)?;
+        }
+        if let Some(indent) = table.integer("indent") {
+            style.indent = usize::try_from(indent)
+                .map_err(|_| format!("list indent {} is negative", indent))?;
+        }
+        if let Some(numbering) = table.string("numbering") {
+            style.numbering = Some(Numbering::parse(numbering)?);
+        }
+
+        Ok(style)
+    }
+
+    pub fn bullet(&self, level: usize) -> &str {
+        let last = self.bullets.len().saturating_sub(1);
+
+        self.bullets
+            .get(level.min(last))
+            .map_or("-", String::as_str)
+    }
+}
+
+/// Splits comma separated bullets, one per nesting level.
+pub fn parse_bullets(s: &str) -> Result<Vec<String>, String> {
+    let bullets = s
+        .split(',')
+        .map(|bullet| bullet.trim().to_string())
+        .collect::<Vec<_>>();
+    if bullets.iter().any(String::is_empty) {
+        return Err(format!("{} has an empty bullet", s));
+    }
+
+    Ok(bullets)
+}
+
+/// Nesting level of a list item indented `indent` columns, given the
+/// indents of the items above it, which it updates.
+pub fn level(indents: &mut Vec<usize>, indent: usize) -> usize {
+    while indents.last().is_some_and(|&last| last > indent) {
+        indents.pop();
+    }
+    if indents.last() != Some(&indent) {
+        indents.push(indent);
+    }
+
+    indents.len() - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toml;
+
+    #[test]
+    fn deeper_levels_reuse_the_last_bullet() {
+        let tables = toml::parse("[lists]\nbullets = \"•, ◦\"\nindent = 4").unwrap();
+        let style = ListStyle::from_table(&tables[0]).unwrap();
+
+        assert_eq!(style.indent, 4);
+        assert_eq!([0, 1, 2].map(|level| style.bullet(level)), ["•", "◦", "◦"]);
+        assert!(parse_bullets("-,,*").is_err());
+    }
+
+    #[test]
+    fn numbering_markers() {
+        assert_eq!(Numbering::Period.marker(3), "3.");
+        assert_eq!(Numbering::Parenthesis.marker(3), "3)");
+        assert_eq!(
+            [1, 26, 27, 53].map(|n| Numbering::Letter.marker(n)),
+            ["a.", "z.", "aa.", "ba."]
+        );
+        assert!(Numbering::parse("i.").is_err());
+    }
+
+    #[test]
+    fn levels_follow_source_indentation() {
+        let mut indents = Vec::new();
+        let levels = [0, 4, 8, 4, 0, 2].map(|indent| level(&mut indents, indent));
+
+        assert_eq!(levels, [0, 1, 2, 1, 0, 1]);
+    }
+}
@@ -0,0 +1,200 @@
+use alloc::format;
+use alloc::string::String;
+
+use crate::lists::Numbering;
+
+/// Order of the parts of a date.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DateOrder {
+    YearMonthDay,
+    DayMonthYear,
+    MonthDayYear,
+}
+
+/// Regional conventions for dates, times, numbers and numbered lists.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Locale {
+    pub tag: &'static str,
+    pub date_order: DateOrder,
+    pub date_separator: char,
+    /// Hours from 1 to 12 with AM or PM, rather than from 0 to 23
+    pub twelve_hour: bool,
+    pub decimal: char,
+    pub thousands: char,
+    /// Currency symbols go after the amount, a space apart
+    pub symbol_after: bool,
+    pub numbering: Numbering,
+}
+
+/// ISO 8601 dates, a 24 hour clock and numbers like 1,234.5, which is what
+/// printed without a locale.
+impl Default for Locale {
+    fn default() -> Self {
+        Locale {
+            tag: "",
+            date_order: DateOrder::YearMonthDay,
+            date_separator: '-',
+            twelve_hour: false,
+            decimal: '.',
+            thousands: ',',
+            symbol_after: false,
+            numbering: Numbering::Period,
+        }
+    }
+}
+
+const fn locale(
+    tag: &'static str,
+    date_order: DateOrder,
+    date_separator: char,
+    decimal: char,
+    thousands: char,
+) -> Locale {
+    Locale {
+        tag,
+        date_order,
+        date_separator,
+        twelve_hour: false,
+        decimal,
+        thousands,
+        symbol_after: decimal == ',',
+        numbering: Numbering::Period,
+    }
+}
+
+/// Every locale `--locale` knows, the first of each language standing in for
+/// the language alone.
+pub const LOCALES: &[Locale] = &[
+    Locale {
+        twelve_hour: true,
+        ..locale("en-US", DateOrder::MonthDayYear, '/', '.', ',')
+    },
+    locale("en-GB", DateOrder::DayMonthYear, '/', '.', ','),
+    locale("de-DE", DateOrder::DayMonthYear, '.', ',', '.'),
+    locale("fr-FR", DateOrder::DayMonthYear, '/', ',', ' '),
+    locale("es-ES", DateOrder::DayMonthYear, '/', ',', '.'),
+    locale("it-IT", DateOrder::DayMonthYear, '/', ',', '.'),
+    Locale {
+        symbol_after: false,
+        ..locale("nl-NL", DateOrder::DayMonthYear, '-', ',', '.')
+    },
+    locale("pt-BR", DateOrder::DayMonthYear, '/', ',', '.'),
+    Locale {
+        numbering: Numbering::Parenthesis,
+        ..locale("sv-SE", DateOrder::YearMonthDay, '-', ',', ' ')
+    },
+    locale("ja-JP", DateOrder::YearMonthDay, '/', '.', ','),
+];
+
+impl Locale {
+    /// Finds a locale by a tag like `de-DE`, `de_DE.UTF-8` or just `de`,
+    /// ignoring case.
+    pub fn find(tag: &str) -> Result<Self, String> {
+        let wanted = tag.split(['.', '@']).next().unwrap_or_default();
+        let matches = |known: &str| {
+            known.len() == wanted.len()
+                && known
+                    .bytes()
+                    .zip(wanted.bytes())
+                    .all(|(a, b)| a.eq_ignore_ascii_case(&b) || a == b'-' && b == b'_')
+        };
+        let language = |known: &str| known.split('-').next().is_some_and(matches);
+
+        LOCALES
+            .iter()
+            .find(|locale| matches(locale.tag))
+            .or_else(|| LOCALES.iter().find(|locale| language(locale.tag)))
+            .copied()
+            .ok_or_else(|| format!("unknown locale {}", tag))
+    }
+
+    pub fn date(&self, year: i64, month: i64, day: i64) -> String {
+        let separator = self.date_separator;
+        match self.date_order {
+            DateOrder::YearMonthDay => {
+                format!(
+                    "{:04}{}{:02}{}{:02}",
+                    year, separator, month, separator, day
+                )
+            }
+            DateOrder::DayMonthYear => {
+                format!(
+                    "{:02}{}{:02}{}{:04}",
+                    day, separator, month, separator, year
+                )
+            }
+            DateOrder::MonthDayYear => {
+                format!(
+                    "{:02}{}{:02}{}{:04}",
+                    month, separator, day, separator, year
+                )
+            }
+        }
+    }
+
+    pub fn time(&self, hour: u64, minute: u64) -> String {
+        match self.twelve_hour {
+            true => {
+                let half = if hour < 12 { "AM" } else { "PM" };
+                let hour = match hour % 12 {
+                    0 => 12,
+                    hour => hour,
+                };
+                format!("{}:{:02} {}", hour, minute, half)
+            }
+            false => format!("{:02}:{:02}", hour, minute),
+        }
+    }
+
+    /// Writes a plain decimal number like `-1234.5` with this locale's
+    /// separators.
+    pub fn number(&self, text: &str) -> String {
+        let (sign, digits) = match text.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", text),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+
+        let mut res = String::from(sign);
+        for (i, c) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i).is_multiple_of(3) {
+                res.push(self.thousands);
+            }
+            res.push(c);
+        }
+        if !fraction.is_empty() {
+            res.push(self.decimal);
+            res.push_str(fraction);
+        }
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_match_loosely() {
+        assert_eq!(Locale::find("de_DE.UTF-8").unwrap().tag, "de-DE");
+        assert_eq!(Locale::find("EN-gb").unwrap().tag, "en-GB");
+        assert_eq!(Locale::find("fr").unwrap().tag, "fr-FR");
+        assert!(Locale::find("xx-YY").is_err());
+    }
+
+    #[test]
+    fn dates_times_and_numbers_follow_the_locale() {
+        let us = Locale::find("en-US").unwrap();
+        let de = Locale::find("de-DE").unwrap();
+
+        assert_eq!(us.date(2026, 3, 7), "03/07/2026");
+        assert_eq!(de.date(2026, 3, 7), "07.03.2026");
+        assert_eq!(Locale::default().date(2026, 3, 7), "2026-03-07");
+        assert_eq!(us.time(0, 5), "12:05 AM");
+        assert_eq!(us.time(13, 30), "1:30 PM");
+        assert_eq!(de.time(13, 30), "13:30");
+        assert_eq!(de.number("-1234567.50"), "-1.234.567,50");
+        assert_eq!(Locale::default().number("999"), "999");
+    }
+}
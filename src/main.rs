@@ -1,265 +1,1784 @@
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::thread;
 
-use clap::Parser;
-use logos::Logos;
+use clap::{Parser, Subcommand, ValueEnum};
 
-macro_rules! def_wrap_env {
-    ($name:ident, $fname:ident, $pre:tt, $post:tt) => {
-        fn $name<'a>(state: &mut State) -> &'a [u8] {
-            let res = if !state.$fname { $pre } else { $post };
-            state.$fname = !state.$fname;
-            return res;
+use ansi::Ansi;
+use checksum::Checksum;
+use encoding::Encoding;
+use filters::Filter;
+use frame::Frame;
+use history::History;
+use mdtoepson::dialect::Dialect;
+use mdtoepson::events::Style;
+use mdtoepson::glyphs::Glyphs;
+use mdtoepson::image::{Dither, ImageOptions};
+use mdtoepson::lists::{self, ListStyle};
+use mdtoepson::locale::Locale;
+use mdtoepson::paper::Paper;
+use mdtoepson::render::{self, is_blank, render_markdown, HeadingCase, Options, StyleMap};
+use mdtoepson::spacing::{self, Spacing};
+use mdtoepson::{html, template};
+use printers::{Model, Profile};
+use server::Sink;
+use spool::{Cancelled, Spool};
+use substitute::Placeholders;
+
+mod ansi;
+mod checksum;
+mod encoding;
+mod filters;
+mod frame;
+mod history;
+mod hooks;
+mod mqtt;
+#[cfg(feature = "pdf")]
+mod pdf;
+mod printers;
+mod query;
+mod server;
+mod service;
+#[cfg(unix)]
+mod socket;
+mod spool;
+mod substitute;
+mod websocket;
+#[cfg(windows)]
+mod winspool;
+
+#[derive(Parser, Clone, Debug)]
+#[command(author, version, about, long_about = None)]
+struct CliArgs {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Markdown files to print; several are sent over one connection, cut apart
+    #[arg(value_name = "FILE")]
+    files: Vec<PathBuf>,
+
+    #[arg(short, long)]
+    message: Option<String>,
+
+    /// Print each line added to the file, or read from stdin without one, as
+    /// it arrives, like `tail -f`
+    #[arg(long, conflicts_with = "message")]
+    follow: bool,
+
+    #[arg(short, long, value_name = "FILE")]
+    destination: Option<PathBuf>,
+
+    /// Windows printer queue to send the job to unchanged, e.g. "EPSON TM-T20II"
+    #[arg(long, value_name = "NAME", conflicts_with = "destination")]
+    printer: Option<String>,
+
+    /// Network printer to send the job to on its raw port, e.g. 192.168.1.50:9100
+    #[arg(long, value_name = "HOST:PORT", conflicts_with_all = ["destination", "printer"])]
+    tcp: Option<String>,
+
+    /// Initialize the printer before the job and reset its styles after (the default)
+    #[arg(long, overrides_with = "no_init")]
+    init: bool,
+
+    /// Send the job without initializing the printer or resetting it afterwards
+    #[arg(long)]
+    no_init: bool,
+
+    /// Whether an earlier document of the batch already set the printer up
+    #[arg(skip)]
+    continued: bool,
+
+    /// Spell characters the printer has no codepage for in ASCII, like ß as ss, instead of ?
+    #[arg(long)]
+    transliterate: bool,
+
+    /// Pass control characters in the markdown, like ESC, to the printer instead of removing them
+    #[arg(long)]
+    allow_raw: bool,
+
+    /// Character encoding of input files
+    #[arg(long, value_enum, value_name = "ENCODING", default_value_t = Encoding::Auto)]
+    input_encoding: Encoding,
+
+    /// What to do with ANSI colour and style sequences in the input
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = Ansi::Strip)]
+    ansi: Ansi,
+
+    /// Skip the job if identical output was printed within this long, e.g. 10m
+    #[arg(long, value_name = "WINDOW", value_parser = history::parse_duration)]
+    dedupe: Option<u64>,
+
+    /// Print even if --dedupe finds a duplicate
+    #[arg(long)]
+    force: bool,
+
+    /// Report the rendered line count and estimated paper length
+    #[arg(long)]
+    stats: bool,
+
+    /// Warn when the job would feed more than this many millimetres of paper
+    #[arg(long, value_name = "MM")]
+    max_length: Option<f64>,
+
+    /// Render and report the size and estimated printing time, without printing
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Paper feed speed for --dry-run estimates [default: by dialect]
+    #[arg(long, value_name = "MM_PER_S")]
+    speed: Option<f64>,
+
+    /// Shell command run after a job prints, with MDTOEPSON_SOURCE, MDTOEPSON_BACKEND,
+    /// MDTOEPSON_BYTES, MDTOEPSON_JOB_ID and MDTOEPSON_STATUS (ok) set
+    #[arg(long, value_name = "CMD")]
+    on_success: Option<String>,
+
+    /// Shell command run after a job fails to print, with the same variables,
+    /// MDTOEPSON_STATUS set to failed and MDTOEPSON_ERROR to why
+    #[arg(long, value_name = "CMD")]
+    on_failure: Option<String>,
+
+    /// Print documents with nothing but whitespace in them, instead of skipping them
+    #[arg(long)]
+    allow_empty: bool,
+
+    /// Leave this job out of the job history
+    #[arg(long)]
+    no_history: bool,
+
+    /// Sync the destination to the device after writing, not just flush it
+    #[arg(long)]
+    sync: bool,
+
+    /// Keep a SHA-256 of what's written to a file destination, to check it
+    /// later with `verify`
+    #[arg(long, value_enum, value_name = "WHERE")]
+    checksum: Option<Checksum>,
+
+    /// Pass the rendered job through a filter before it's sent, in the order
+    /// given: optimize, sanitize, hexdump, or a shell command reading the job
+    /// on stdin and writing it to stdout
+    #[arg(long, value_name = "FILTER")]
+    filter: Vec<String>,
+
+    /// Framing bytes to wrap each job in, for print controllers that need
+    /// packets around the raw data. Jobs streamed to `serve` get STX as they
+    /// start and ETX as they end, or are held back until they end to be
+    /// length-prefixed; --follow takes only stx-etx
+    #[arg(long, value_enum, value_name = "FRAMING", default_value_t = Frame::None)]
+    frame: Frame,
+
+    /// Flow control to configure on a serial destination before writing
+    #[arg(long, value_enum, value_name = "MODE")]
+    flow_control: Option<FlowControl>,
+
+    /// Printer model from the built-in database, e.g. tm-t20, setting dialect, paper and capabilities
+    #[arg(long, value_name = "MODEL")]
+    model: Option<String>,
+
+    /// Printer profile overriding the model's settings and defining [glyphs] for extra characters
+    #[arg(long, value_name = "FILE")]
+    profile: Option<PathBuf>,
+
+    /// Printer command set to emit [default: escp]
+    #[arg(long, value_enum)]
+    dialect: Option<Dialect>,
+
+    /// Kind of output to produce
+    #[arg(long, value_enum, default_value_t = Format::Printer)]
+    format: Format,
+
+    /// Paper preset setting width, font, margins and page length
+    #[arg(long, value_enum)]
+    paper: Option<Paper>,
+
+    /// Ask an ESC/POS printer on a device or --tcp which roll it has, and
+    /// set the paper from that
+    #[arg(long, conflicts_with = "paper")]
+    auto_width: bool,
+
+    /// Print on labels, feeding to the next gap or black mark between
+    /// documents and wherever the paper would be cut
+    #[arg(long)]
+    label: bool,
+
+    /// Height of each label, to warn about documents that don't fit
+    #[arg(long, value_name = "MM", requires = "label")]
+    label_height: Option<usize>,
+
+    /// Characters per line, overriding the paper preset
+    #[arg(short, long, value_name = "COLUMNS")]
+    width: Option<usize>,
+
+    /// Print darkness from -6 to 6 on printers that can set it, overriding the profile
+    #[arg(
+        long,
+        value_name = "N",
+        allow_negative_numbers = true,
+        value_parser = clap::value_parser!(i8).range(-6..=6)
+    )]
+    density: Option<i8>,
+
+    /// Blank lines fed after the document so it clears the tear bar,
+    /// overriding the profile [default: 0]
+    #[arg(long, value_name = "N")]
+    feed_after: Option<usize>,
+
+    /// How images are reduced to black and white dots
+    #[arg(long, value_enum, default_value_t = Dither::FloydSteinberg)]
+    dither: Dither,
+
+    /// Brightness added to images, from -255 to 255
+    #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+    brightness: i32,
+
+    /// Contrast multiplier for images
+    #[arg(long, default_value_t = 1.0)]
+    contrast: f32,
+
+    /// Print code blocks without syntax highlighting
+    #[arg(long)]
+    no_highlight: bool,
+
+    /// Number the lines of code blocks
+    #[arg(long)]
+    line_numbers: bool,
+
+    /// Draw frames around tables and code blocks, in box drawing characters
+    /// where the printer has them
+    #[arg(long)]
+    frames: bool,
+
+    /// Print single newlines as line breaks instead of joining the lines
+    #[arg(long)]
+    keep_linebreaks: bool,
+
+    /// List bullets by nesting level, comma separated, e.g. "•,◦,-" [default: -]
+    #[arg(long, value_name = "BULLETS")]
+    bullets: Option<String>,
+
+    /// Columns to indent each level of nested lists by [default: 2]
+    #[arg(long, value_name = "COLUMNS")]
+    list_indent: Option<usize>,
+
+    /// Regional conventions for {{date}}, {{time}}, number filters and
+    /// numbered lists, e.g. de-DE [default: ISO dates and 1,234.5]
+    #[arg(long, value_name = "TAG")]
+    locale: Option<String>,
+
+    /// Print headings in bold as well as their usual style
+    #[arg(long)]
+    force_bold_headings: bool,
+
+    /// Print *italic* text plain, for printers that garble italics
+    #[arg(long)]
+    no_italic: bool,
+
+    /// Print one style as another, or plain, e.g. italic=underline or
+    /// underline=none; may be repeated
+    #[arg(long, value_name = "FROM=TO")]
+    map: Vec<String>,
+
+    /// Letter case to print headings in
+    #[arg(long, value_enum, default_value_t = HeadingCase::None)]
+    heading_case: HeadingCase,
+
+    /// Markdown printed before the document; {{date}}, {{time}} (UTC) and {{job_number}} expand
+    #[arg(long, value_name = "MARKDOWN")]
+    header: Option<String>,
+
+    /// Markdown printed after the document, with the same placeholders as --header
+    #[arg(long, value_name = "MARKDOWN")]
+    footer: Option<String>,
+
+    /// Print a footer with the page number on each page, given a page length
+    #[arg(long)]
+    page_numbers: bool,
+
+    /// Cut the paper before every heading of this level, one ticket per section
+    #[arg(long, value_name = "LEVEL")]
+    cut_per_heading: Option<usize>,
+
+    /// Page footer text; {{page}} and {{pages}} expand
+    #[arg(
+        long,
+        value_name = "TEXT",
+        default_value = "Page {{page}} of {{pages}}"
+    )]
+    page_footer: String,
+
+    /// CSV file printed as a table after the document; may be repeated
+    #[arg(long, value_name = "FILE")]
+    embed_csv: Vec<PathBuf>,
+
+    /// Watch this directory, printing markdown files dropped into it, or into
+    /// its urgent/ folder ahead of the rest, and moving them to done/ or failed/
+    #[arg(long, value_name = "DIR")]
+    spool_dir: Option<PathBuf>,
+
+    /// Listen on this Unix socket for jobs: a line of markdown each, or a zero
+    /// byte, a 4 byte big-endian length and that much markdown
+    #[arg(long, value_name = "PATH")]
+    listen_socket: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum Command {
+    /// Print a built-in page using every style, to check the printer setup
+    TestPage,
+    /// Fill a markdown template from JSON or CSV data, then print it
+    Render {
+        /// Mustache style template: {{name}}, {{#items}}…{{/items}} and {{^paid}}…{{/paid}}
+        template: PathBuf,
+        /// JSON document, or CSV whose rows are {{#rows}} with a field per column
+        data: PathBuf,
+    },
+    /// Print the markdown payload of every message published on an MQTT topic
+    Mqtt {
+        /// Topic filter to subscribe to, e.g. printer/receipts or printer/#
+        topic: String,
+        /// Broker to connect to over plain TCP
+        #[arg(long, value_name = "HOST:PORT", default_value = "localhost:1883")]
+        broker: String,
+        /// Client identifier, unique per broker [default: mdtoepson-<pid>]
+        #[arg(long, value_name = "ID")]
+        client_id: Option<String>,
+        #[arg(long)]
+        username: Option<String>,
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Print markdown streamed over a WebSocket at /ws, a line at a time
+    Serve {
+        /// Address to listen on
+        #[arg(long, value_name = "HOST:PORT", default_value = "127.0.0.1:8080")]
+        listen: String,
+    },
+    /// List past jobs, or print one of them again
+    History {
+        /// Job to send to the destination again
+        #[arg(long, value_name = "ID")]
+        reprint: Option<u64>,
+    },
+    /// Check an archived job against the checksum kept with --checksum
+    Verify { file: PathBuf },
+    /// Take a job out of the --spool-dir queue, or stop it between lines if
+    /// it's printing
+    Cancel {
+        /// File name of the job in the spool directory, e.g. ticket-42.md
+        job: String,
+    },
+}
+
+/// The printer a job goes to, as --profile and --model describe it. Both
+/// are read from files or the built-in database, so a job loads them once
+/// and passes this along.
+struct Printer {
+    model: Option<Model>,
+    profile: Option<Profile>,
+}
+
+impl CliArgs {
+    fn printer(&self) -> Printer {
+        let profile = self
+            .profile
+            .as_ref()
+            .map(|path| Profile::load(path, self.built_in_model()));
+        let model = match &profile {
+            Some(profile) => Some(profile.model.clone()),
+            None => self.built_in_model(),
+        };
+
+        Printer { model, profile }
+    }
+
+    fn built_in_model(&self) -> Option<Model> {
+        let id = self.model.as_deref()?;
+        let model = printers::find(id).unwrap_or_else(|| {
+            let known = printers::models()
+                .into_iter()
+                .map(|model| model.id)
+                .collect::<Vec<_>>();
+            panic!(
+                "Unknown printer model {}; known models: {}",
+                id,
+                known.join(", ")
+            )
+        });
+
+        Some(model)
+    }
+
+    fn paper(&self, printer: &Printer) -> Option<Paper> {
+        self.paper
+            .or_else(|| printer.model.as_ref().and_then(|model| model.paper))
+    }
+
+    /// File the markdown comes from, if any.
+    fn input_path(&self) -> Option<&Path> {
+        match &self.command {
+            Some(Command::Render { template, .. }) => Some(template),
+            _ => self.files.first().map(PathBuf::as_path),
         }
-    };
+    }
+
+    fn list_style(&self, printer: &Printer) -> ListStyle {
+        let mut style = printer
+            .profile
+            .as_ref()
+            .map_or_else(ListStyle::default, |profile| profile.lists.clone());
+        if let Some(bullets) = &self.bullets {
+            style.bullets = lists::parse_bullets(bullets)
+                .unwrap_or_else(|e| panic!("Invalid --bullets: {}", e));
+        }
+        if let Some(indent) = self.list_indent {
+            style.indent = indent;
+        }
+        style.numbering.get_or_insert(self.locale().numbering);
+
+        style
+    }
+
+    fn styles(&self) -> StyleMap {
+        let mut styles = StyleMap::default();
+        if self.no_italic {
+            styles.set(Style::Italic, None);
+        }
+        for mapping in &self.map {
+            if let Err(e) = styles.parse_mapping(mapping) {
+                panic!("Invalid --map: {}", e);
+            }
+        }
+
+        styles
+    }
+
+    fn locale(&self) -> Locale {
+        match &self.locale {
+            Some(tag) => Locale::find(tag).unwrap_or_else(|e| panic!("Invalid --locale: {}", e)),
+            None => Locale::default(),
+        }
+    }
+
+    fn options(&self) -> Options {
+        self.options_for(&self.printer())
+    }
+
+    fn options_for(&self, printer: &Printer) -> Options {
+        let model = printer.model.clone();
+        let profile = printer.profile.as_ref();
+        let mut layout = self.paper(printer).map(Paper::layout).unwrap_or_default();
+        if let Some(width) = self.width {
+            layout.width = Some(width);
+        }
+        layout.label_height = self.label_height;
+
+        let dialect = match self.format {
+            Format::Printer => self
+                .dialect
+                .or(model.as_ref().map(|model| model.dialect))
+                .unwrap_or_default(),
+            Format::Plain => Dialect::Plain,
+            Format::Html => Dialect::Html,
+            Format::Pdf => Dialect::Pdf,
+        };
+
+        Options {
+            dialect,
+            layout,
+            image: ImageOptions {
+                dither: self.dither,
+                brightness: self.brightness.clamp(-255, 255),
+                contrast: self.contrast,
+            },
+            base_dir: self
+                .input_path()
+                .and_then(|file| file.parent())
+                .map(Path::to_path_buf),
+            highlight: !self.no_highlight,
+            line_numbers: self.line_numbers,
+            frames: self.frames,
+            keep_linebreaks: self.keep_linebreaks,
+            lists: self.list_style(printer),
+            spacing: profile.map_or_else(Spacing::default, |profile| profile.spacing),
+            heading_case: self.heading_case,
+            bold_headings: self.force_bold_headings,
+            styles: self.styles(),
+            page_footer: self.page_numbers.then(|| self.page_footer.clone()),
+            cut_level: self.cut_per_heading,
+            init: !self.no_init,
+            continued: self.continued,
+            labels: self.label,
+            feed_after: self
+                .feed_after
+                .or_else(|| model.as_ref().and_then(|model| model.feed_after))
+                .unwrap_or(0),
+            density: self
+                .density
+                .or_else(|| model.as_ref().and_then(|model| model.density)),
+            transliterate: self.transliterate,
+            allow_raw: self.allow_raw,
+            glyphs: profile.map_or_else(Glyphs::default, |profile| profile.glyphs.clone()),
+            capabilities: model
+                .filter(|model| dialect == model.dialect)
+                .map(|model| model.capabilities),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// Escape codes for the selected dialect
+    Printer,
+    /// Wrapped text with every markdown marker removed
+    Plain,
+    /// Standalone HTML page previewing the printout
+    Html,
+    /// PDF facsimile at the width of the paper (requires the `pdf` feature)
+    Pdf,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum FlowControl {
+    None,
+    XonXoff,
+    RtsCts,
 }
-macro_rules! def_open_env {
-    ($name:ident, $fname:ident, $pre:tt) => {
-        fn $name<'a>(state: &mut State) -> &'a [u8] {
-            if !state.$fname {
-                state.$fname = true;
-                $pre
-            } else {
-                &[]
+
+impl FlowControl {
+    fn stty_flags(self) -> &'static [&'static str] {
+        match self {
+            FlowControl::None => &["-ixon", "-ixoff", "-crtscts"],
+            FlowControl::XonXoff => &["ixon", "ixoff", "-crtscts"],
+            FlowControl::RtsCts => &["-ixon", "-ixoff", "crtscts"],
+        }
+    }
+}
+
+fn main() {
+    let mut args = CliArgs::parse();
+    if args.auto_width && !args.dry_run {
+        match detect_paper(&args) {
+            Ok(paper) => args.paper = Some(paper),
+            Err(message) => eprintln!("warning: could not detect the paper width: {}", message),
+        }
+    }
+    if let Some(Command::History { reprint }) = args.command {
+        return history(&args, reprint);
+    }
+    if let Some(Command::Cancel { job }) = &args.command {
+        return cancel(&args, job);
+    }
+    if let Some(Command::Verify { file }) = &args.command {
+        match checksum::verify(file) {
+            Ok(_) => return println!("{}: OK", file.display()),
+            Err(message) => panic!("{}", message),
+        }
+    }
+    if let Some(Command::Mqtt {
+        topic,
+        broker,
+        client_id,
+        username,
+        password,
+    }) = &args.command
+    {
+        let subscription = mqtt::Subscription {
+            broker: broker.clone(),
+            topic: topic.clone(),
+            client_id: client_id
+                .clone()
+                .unwrap_or_else(|| format!("mdtoepson-{}", std::process::id())),
+            username: username.clone(),
+            password: password.clone(),
+        };
+        mqtt::subscribe(&subscription, |message| {
+            let input = String::from_utf8_lossy(&message.payload).into_owned();
+            if let Err(e) = print_document(&args, input) {
+                eprintln!("warning: mqtt:{}: {}", message.topic, e);
             }
+        });
+    }
+    if let Some(Command::Serve { listen }) = &args.command {
+        return serve(&args, listen);
+    }
+    if let Some(path) = &args.listen_socket {
+        return listen_socket(&args, path);
+    }
+    if let Some(dir) = &args.spool_dir {
+        let spool = match Spool::open(dir) {
+            Ok(spool) => spool,
+            Err(e) => panic!("Could not set up spool directory {}: {}", dir.display(), e),
+        };
+        spool.watch(|path, cancelled| {
+            let input = encoding::read(path, args.input_encoding)
+                .map_err(|e| format!("Cannot read from input {}: {}", path.display(), e))?;
+            let args = spooled_job(&args, dir, path);
+            let printer = args.printer();
+            match prepare_document(&args, &printer, input) {
+                Some(bytes) => {
+                    print_cancellable(&args, &args.options_for(&printer), &bytes, cancelled)
+                }
+                None => Ok(()),
+            }
+        });
+    }
+    if args.follow {
+        if let Err(message) = follow(&args) {
+            panic!("{}", message);
         }
+        return;
+    }
+    if args.command.is_none() && args.files.len() > 1 {
+        if let Err(message) = print_batch(&args) {
+            panic!("{}", message);
+        }
+        return;
+    }
+
+    let input = match &args.command {
+        Some(Command::TestPage) => test_page(&args),
+        Some(Command::Render { template, data }) => {
+            render_template(template, data, args.input_encoding, &args.locale())
+        }
+        _ => read_input(args.clone()),
     };
+    if let Err(message) = print_document(&args, input) {
+        panic!("{}", message);
+    }
 }
-macro_rules! def_close_env {
-    ($name:ident, $fname:ident, $post:tt) => {
-        fn $name<'a>(state: &mut State) -> &'a [u8] {
-            if !state.$fname {
-                &[]
-            } else {
-                state.$fname = false;
-                $post
-            }
+
+/// Renders markdown in the chosen format and prints it, reporting warnings.
+fn print_document(args: &CliArgs, input: String) -> Result<(), String> {
+    match prepare_document(args, &args.printer(), input) {
+        Some(bytes) => print_job(args, &source_name(args), &bytes),
+        None => Ok(()),
+    }
+}
+
+/// Renders markdown in the chosen format, reporting warnings, or nothing
+/// when it duplicates a recent job.
+fn prepare_document(args: &CliArgs, printer: &Printer, input: String) -> Option<Vec<u8>> {
+    let input = with_embedded_csv(args, ansi::convert(&input, args.ansi));
+    if !args.allow_empty && is_blank(&input) {
+        eprintln!(
+            "Skipping {}: there is nothing to print; use --allow-empty to print it anyway",
+            source_name(args)
+        );
+        return None;
+    }
+    let input = Placeholders::new(args.locale()).expand(&with_header_footer(args, input));
+
+    let options = args.options_for(printer);
+    let rendered = render_markdown(&input, &options);
+    let source = source_name(args);
+    for diagnostic in &rendered.diagnostics {
+        eprintln!("warning: {}:{}", source, diagnostic);
+    }
+    if args.stats {
+        eprintln!("{}: {}", source, rendered.stats);
+    }
+    if let Some(max) = args.max_length {
+        let length = rendered.stats.paper_mm();
+        if length > max {
+            eprintln!(
+                "warning: {}: job is about {:.0} mm long, over the {:.0} mm maximum",
+                source, length, max
+            );
         }
+    }
+
+    let bytes = match args.format {
+        Format::Html => html::page(&rendered.bytes, options.layout.text_width()),
+        Format::Pdf => pdf_document(args.paper(printer), &options, &rendered.bytes),
+        _ => rendered.bytes,
     };
+    let bytes = match filter(args, bytes, rendered.stats.dialect) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Skipping {}: {}", source, e);
+            return None;
+        }
+    };
+    if args.dry_run {
+        let speed = args
+            .speed
+            .unwrap_or_else(|| rendered.stats.dialect.print_speed_mm_s());
+        println!(
+            "{}: {} bytes, {}, about {:.1} s to print",
+            source,
+            bytes.len(),
+            rendered.stats,
+            rendered.stats.print_seconds(speed)
+        );
+        return None;
+    }
+
+    let duplicate = args
+        .dedupe
+        .filter(|_| !args.force)
+        .and_then(|window| History::open()?.duplicate(&bytes, window));
+    if let Some(job) = duplicate {
+        eprintln!(
+            "Skipping duplicate of job {} ({}); use --force to print it again",
+            job.id, job.source
+        );
+        return None;
+    }
+
+    Some(bytes)
 }
 
-#[derive(Logos, Debug)]
-enum Token {
-    #[token("**")]
-    Bold,
+/// Runs a rendered job through each `--filter` in turn.
+fn filter(args: &CliArgs, bytes: Vec<u8>, dialect: Dialect) -> Result<Vec<u8>, String> {
+    args.filter
+        .iter()
+        .map(|filter| Filter::parse(filter))
+        .try_fold(bytes, |bytes, filter| filter.apply(bytes, dialect))
+}
 
-    #[token("*")]
-    Italic,
+/// Sends a job to the destination and logs it in the job history.
+fn print_job(args: &CliArgs, source: &str, bytes: &[u8]) -> Result<(), String> {
+    let written = write_output(args, bytes);
+    record_job(args, source, bytes, &written);
 
-    #[token("__")]
-    Underline,
+    written
+}
 
-    #[regex(r"#( +)?")]
-    TopHeader,
+/// Logs a finished job in the history, then runs the hook for how it went.
+fn record_job(args: &CliArgs, source: &str, bytes: &[u8], result: &Result<(), String>) {
+    if args.dry_run {
+        return;
+    }
 
-    #[regex(r"#{2,}( +)?")]
-    LowerHeader,
+    let backend = backend_name(args);
+    let mut job_id = None;
+    if let Some(history) = History::open().filter(|_| !args.no_history) {
+        match history.record(source, &backend, bytes, result.is_ok()) {
+            Ok(job) => job_id = Some(job.id),
+            Err(e) => eprintln!("warning: could not record job history: {}", e),
+        }
+    }
 
-    #[regex(r"( ?)\{#*.*\}", priority = 98)]
-    Tag,
+    let hook = match result {
+        Ok(()) => &args.on_success,
+        Err(_) => &args.on_failure,
+    };
+    if let Some(command) = hook {
+        let job = hooks::Finished {
+            source,
+            backend: &backend,
+            bytes: bytes.len(),
+            job_id,
+            error: result.as_ref().err().map(String::as_str),
+        };
+        hooks::run(command, &job);
+    }
+}
 
-    #[token("\n")]
-    RemovableNewline,
+/// Prints each file as its own job, back to back over one connection with a
+/// cut, or a feed to the next label, between them, since reconnecting is slow
+/// and resets some printers. Only the first sets the printer up, which is
+/// what takes the time on a serial line. Files that fail are reported and
+/// skipped.
+fn print_batch(args: &CliArgs) -> Result<(), String> {
+    if matches!(args.format, Format::Html | Format::Pdf) {
+        return Err(String::from(
+            "batches print only in the printer and plain formats",
+        ));
+    }
 
-    #[regex(r"\n{2,}|\\\\")]
-    ActiveNewline,
+    let printer = args.printer();
+    let options = args.options_for(&printer);
+    let sequences = options.dialect.sequences();
+    let cut = if options.labels {
+        sequences.next_label
+    } else if options.capabilities().has_cutter && !sequences.cut.is_empty() {
+        sequences.cut
+    } else {
+        sequences.eject
+    };
+    let mut out = open_output(args)?;
+    let mut printed = 0;
+    let mut failed = 0;
+    let mut sent = Vec::new();
+    for path in &args.files {
+        let args = CliArgs {
+            files: vec![path.clone()],
+            continued: printed > 0,
+            ..args.clone()
+        };
+        let input = match encoding::read(path, args.input_encoding) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("warning: cannot read {}: {}", path.display(), e);
+                failed += 1;
+                continue;
+            }
+        };
+        let Some(bytes) = prepare_document(&args, &printer, input) else {
+            continue;
+        };
 
-    #[regex(r"[^(\*\*)\*(__)#\n\r\t\f]")]
-    Text,
+        // The cut belongs to the job after it, inside the same frame
+        let separator = if printed > 0 { cut } else { &[] };
+        let written = args
+            .frame
+            .wrap(&[separator, &bytes].concat())
+            .and_then(|job| {
+                out.write_all(&job)
+                    .and_then(|_| out.flush())
+                    .map_err(|e| format!("Could not write to {}: {}", backend_name(&args), e))?;
+                sent.extend_from_slice(&job);
+                Ok(())
+            });
+        record_job(&args, &source_name(&args), &bytes, &written);
+        // The connection is gone, so the rest can't be printed either
+        written?;
+        printed += 1;
+    }
+    drop(out);
+    seal_output(args, &sent)?;
 
-    #[regex(r"[\-\*+] .+(\n)")]
-    UnorderedList,
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!(
+            "{} of {} files could not be read",
+            failed,
+            args.files.len()
+        )),
+    }
+}
 
-    // #[regex(r"[0-9]\. .+(\n)")]
-    // OrderedList,
-    #[regex(r"\[[^\[\]]+\]\([^\(\)]+\)", priority = 99)]
-    Link,
+/// Prints markdown streamed over WebSockets, on the socket systemd passed us
+/// or one bound to `address`, until SIGTERM.
+fn serve(args: &CliArgs, address: &str) {
+    service::handle_sigterm();
+    let listener = match service::inherited() {
+        Some(listener) => Ok(listener),
+        None => TcpListener::bind(address),
+    };
+    let served =
+        listener.and_then(|listener| server::serve(listener, |source| LiveJob::open(args, source)));
+    if let Err(e) = served {
+        panic!("Could not listen on {}: {}", address, e);
+    }
+}
 
-    #[regex(r"(\n)?`{3}[^`]*`{3}(\n)?", priority = 100)]
-    Codeblock,
+#[cfg(unix)]
+fn listen_socket(args: &CliArgs, path: &Path) {
+    service::handle_sigterm();
+    let listener = match service::inherited() {
+        Some(listener) => Ok(listener),
+        None => socket::bind(path),
+    };
+    let listened = listener.and_then(|listener| {
+        socket::listen(listener, |input| print_document(args, input.to_string()))
+    });
+    if let Err(e) = listened {
+        panic!("Could not listen on {}: {}", path.display(), e);
+    }
 }
 
-#[derive(Parser, Clone, Debug)]
-#[command(author, version, about, long_about = None)]
-struct CliArgs {
-    file: Option<PathBuf>,
+#[cfg(not(unix))]
+fn listen_socket(_args: &CliArgs, path: &Path) {
+    panic!(
+        "Cannot listen on {}: Unix sockets are not supported on this platform",
+        path.display()
+    );
+}
 
-    #[arg(short, long)]
-    message: Option<String>,
+/// A job printed a line at a time as its markdown arrives, for clients that
+/// stream output to paper.
+struct LiveJob<'a> {
+    args: &'a CliArgs,
+    options: Options,
+    source: String,
+    out: Box<dyn Write>,
+    /// Markdown short of a full line, or inside an unclosed code block
+    pending: Vec<u8>,
+    /// Everything rendered so far, for the job history and for a frame
+    /// that needs the whole job
+    printed: Vec<u8>,
+    /// Blank lines fed once the whole job has printed
+    feed_after: usize,
+}
 
-    #[arg(short, long, value_name = "FILE")]
-    destination: Option<PathBuf>,
+impl<'a> LiveJob<'a> {
+    fn open(args: &'a CliArgs, source: String) -> Result<Self, String> {
+        if matches!(args.format, Format::Html | Format::Pdf) {
+            return Err(String::from(
+                "streamed jobs print only in the printer and plain formats",
+            ));
+        }
+
+        let options = args.options();
+        let mut job = LiveJob {
+            args,
+            // Each piece carries on the job the setup began, and only the
+            // job as a whole is followed by the feed and reset
+            options: Options {
+                init: false,
+                continued: true,
+                feed_after: 0,
+                ..options.clone()
+            },
+            source,
+            out: open_output(args)?,
+            pending: Vec::new(),
+            printed: Vec::new(),
+            feed_after: options.feed_after,
+        };
+        job.send(args.frame.open())?;
+        job.write(&render::setup(&options))?;
+
+        Ok(job)
+    }
+
+    /// Sends rendered bytes on, unless the frame needs the whole job first.
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.printed.extend_from_slice(bytes);
+        match self.args.frame.needs_whole_job() {
+            true => Ok(()),
+            false => self.send(bytes),
+        }
+    }
+
+    fn send(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.out
+            .write_all(bytes)
+            .and_then(|_| self.out.flush())
+            .map_err(|e| format!("Could not write to {}: {}", backend_name(self.args), e))
+    }
+
+    fn print(&mut self, markdown: &str) -> Result<(), String> {
+        let markdown = ansi::convert(markdown, self.args.ansi);
+        let rendered = render_markdown(&markdown, &self.options);
+        for diagnostic in &rendered.diagnostics {
+            eprintln!("warning: {}:{}", self.source, diagnostic);
+        }
+
+        self.write(&rendered.bytes)
+    }
 }
 
-#[derive(Debug, Default)]
-struct State {
-    top_header: bool,
-    lower_header: bool,
-    bold: bool,
-    italic: bool,
-    underline: bool,
+impl server::Sink for LiveJob<'_> {
+    fn push(&mut self, fragment: &[u8]) -> Result<(), String> {
+        self.pending.extend_from_slice(fragment);
+        let Some(end) = self.pending.iter().rposition(|&b| b == b'\n') else {
+            return Ok(());
+        };
+        let text = String::from_utf8_lossy(&self.pending[..end]).into_owned();
+        if text.matches("```").count() % 2 == 1 {
+            return Ok(());
+        }
+
+        self.pending.drain(..=end);
+        self.print(&text)
+    }
+
+    fn finish(mut self) -> Result<(), String> {
+        let rest = String::from_utf8_lossy(&std::mem::take(&mut self.pending)).into_owned();
+        let mut result = match rest.is_empty() {
+            true => Ok(()),
+            false => self.print(&rest),
+        };
+        result = result.and_then(|_| self.write(&b"\n".repeat(self.feed_after)));
+        if !self.args.no_init {
+            result = result.and(self.write(self.options.dialect.sequences().reset));
+        }
+        let frame = self.args.frame;
+        result = result.and_then(|_| match frame.needs_whole_job() {
+            true => frame.wrap(&self.printed).and_then(|job| self.send(&job)),
+            false => self.send(frame.close()),
+        });
+
+        record_job(self.args, &self.source, &self.printed, &result);
+
+        result
+    }
 }
 
-def_wrap_env!(wrap_bold, bold, b"\x1BE", b"\x1BF");
-def_wrap_env!(wrap_italic, italic, b"\x1B4", b"\x1B5");
-def_wrap_env!(wrap_underline, underline, b"\x1B-1", b"\x1B-0");
-def_open_env!(open_top_header, top_header, b"\n\n\x1BE\x1Bw1\x1BW1");
-def_close_env!(close_top_header, top_header, b"\x1BF\x1Bw0\x1BW0\n");
-def_open_env!(open_lower_header, lower_header, b"\n\n\x1Bw1");
-def_close_env!(close_lower_header, lower_header, b"\x1Bw0\n");
+/// Prints lines appended to the input file, or read from stdin, one at a
+/// time as they arrive, until SIGTERM or the end of stdin. Each line is
+/// rendered on its own, so a style left open can't run on into the next.
+fn follow(args: &CliArgs) -> Result<(), String> {
+    if args.frame.needs_whole_job() {
+        return Err(String::from(
+            "--follow can't send length-prefixed frames, which need the whole job first",
+        ));
+    }
+    service::handle_sigterm();
+    let args = CliArgs {
+        keep_linebreaks: true,
+        ..args.clone()
+    };
+    let source = match args.files.first() {
+        Some(path) => path.display().to_string(),
+        None => String::from("<stdin>"),
+    };
+    let mut job = LiveJob::open(&args, source)?;
+    let read = |e: std::io::Error| format!("Cannot read from input: {}", e);
+    let mut buf = [0; 4096];
+    let push_lines = |job: &mut LiveJob, bytes: &[u8]| {
+        bytes
+            .split_inclusive(|&b| b == b'\n')
+            .try_for_each(|line| job.push(line))
+    };
 
-fn main() {
-    let args = CliArgs::parse();
-    let input = read_input(args.clone());
-
-    let res = transpile_markdown(&input);
-
-    write_output(args, res.as_slice());
-}
-
-fn transpile_markdown(input: &str) -> Vec<u8> {
-    let mut lex = Token::lexer(&input);
-    let mut state = State::default();
-
-    let mut res = Vec::<u8>::new();
-    while let Some(r) = lex.next() {
-        if let Ok(variant) = r {
-            match variant {
-                Token::Bold => res.extend_from_slice(wrap_bold(&mut state)),
-                Token::Italic => res.extend_from_slice(wrap_italic(&mut state)),
-                Token::Underline => res.extend_from_slice(wrap_underline(&mut state)),
-                Token::TopHeader => res.extend_from_slice(open_top_header(&mut state)),
-                // TODO: lower header formatting (font size)
-                Token::LowerHeader => res.extend_from_slice(open_lower_header(&mut state)),
-                Token::RemovableNewline => {
-                    res.append(&mut new_line(&mut state, Token::RemovableNewline))
+    let Some(path) = args.files.first() else {
+        let mut stdin = std::io::stdin().lock();
+        loop {
+            match stdin.read(&mut buf).map_err(read)? {
+                0 => return job.finish(),
+                n => push_lines(&mut job, &buf[..n])?,
+            }
+        }
+    };
+
+    // Only lines written from now on, like `tail -f -n 0`
+    let mut file = File::open(path).map_err(read)?;
+    let mut position = file.seek(SeekFrom::End(0)).map_err(read)?;
+    while !service::terminating() {
+        let n = file.read(&mut buf).map_err(read)?;
+        if n > 0 {
+            push_lines(&mut job, &buf[..n])?;
+            position += n as u64;
+            continue;
+        }
+
+        // A file that got shorter was truncated or rotated, so start over
+        if fs::metadata(path).is_ok_and(|metadata| metadata.len() < position) {
+            file = File::open(path).map_err(read)?;
+            position = 0;
+        }
+        thread::sleep(service::POLL_INTERVAL);
+    }
+
+    job.finish()
+}
+
+/// Lists logged jobs, or prints one of them again.
+fn history(args: &CliArgs, reprint: Option<u64>) {
+    let Some(history) = History::open() else {
+        panic!("No directory for the job history; set XDG_STATE_HOME or HOME.");
+    };
+
+    match reprint {
+        Some(id) => match history.output(id) {
+            Ok(bytes) => {
+                if let Err(message) = print_job(args, &format!("job {}", id), &bytes) {
+                    panic!("{}", message);
                 }
-                Token::ActiveNewline => res.append(&mut new_line(&mut state, Token::ActiveNewline)),
-                Token::Tag => {}
-                _ => res.extend_from_slice(lex.slice().as_bytes()),
+            }
+            Err(e) => panic!("Could not read the output of job {}: {}", id, e),
+        },
+        None => {
+            for job in history.jobs() {
+                println!("{}", job);
+            }
+        }
+    }
+}
+
+/// Asks the printer the job goes to which paper it has loaded.
+fn detect_paper(args: &CliArgs) -> Result<Paper, String> {
+    if args.options().dialect != Dialect::EscPos {
+        return Err(String::from("only ESC/POS printers can be asked"));
+    }
+
+    if let Some(address) = &args.tcp {
+        let stream = TcpStream::connect(address)
+            .map_err(|e| format!("could not connect to {}: {}", address, e))?;
+        let reader = stream
+            .try_clone()
+            .map_err(|e| format!("could not read from {}: {}", address, e))?;
+        return query::paper_width(stream, reader);
+    }
+    let Some(path) = &args.destination else {
+        return Err(String::from("it can only be asked over --tcp or a device"));
+    };
+    if fs::metadata(path).is_ok_and(|meta| meta.is_file()) {
+        return Err(format!("{} is a file, not a printer", path.display()));
+    }
+    let device = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(|e| format!("could not open {}: {}", path.display(), e))?;
+    let reader = device
+        .try_clone()
+        .map_err(|e| format!("could not read from {}: {}", path.display(), e))?;
+
+    query::paper_width(device, reader)
+}
+
+/// Arguments for a job taken from the spool in `dir`. Images and tables
+/// named relative to it are beside it in the drop folder, not in `printing/`.
+fn spooled_job(args: &CliArgs, dir: &Path, path: &Path) -> CliArgs {
+    CliArgs {
+        files: vec![dir.join(path.file_name().unwrap_or_default())],
+        ..args.clone()
+    }
+}
+
+fn cancel(args: &CliArgs, job: &str) {
+    let Some(dir) = &args.spool_dir else {
+        panic!("cancel needs the queue's --spool-dir.");
+    };
+    let spool = match Spool::open(dir) {
+        Ok(spool) => spool,
+        Err(e) => panic!("Could not open spool directory {}: {}", dir.display(), e),
+    };
+
+    match spool.cancel(job) {
+        Ok(Cancelled::Queued(path)) => eprintln!("Cancelled {}, moved to {}", job, path.display()),
+        Ok(Cancelled::Printing) => eprintln!("Stopping {} at the next line", job),
+        Err(e) => panic!("Could not cancel {}: {}", job, e),
+    }
+}
+
+/// Prints a spooled job a line at a time, so a cancelled job stops between
+/// lines rather than inside an escape sequence, then turns off any style it
+/// left on and cuts or ejects what did print.
+fn print_cancellable(
+    args: &CliArgs,
+    options: &Options,
+    bytes: &[u8],
+    cancelled: &dyn Fn() -> bool,
+) -> Result<(), String> {
+    // Half an HTML page, PDF or frame is no use to anyone
+    if matches!(args.format, Format::Html | Format::Pdf) || args.frame != Frame::None {
+        if cancelled() {
+            let result = Err(String::from("cancelled"));
+            record_job(args, &source_name(args), bytes, &result);
+            return result;
+        }
+        return print_job(args, &source_name(args), bytes);
+    }
+
+    let write = |out: &mut dyn Write, chunk: &[u8]| {
+        out.write_all(chunk)
+            .and_then(|_| out.flush())
+            .map_err(|e| format!("Could not write to {}: {}", backend_name(args), e))
+    };
+    let mut out = open_output(args)?;
+    let mut result = Ok(());
+    let mut sent = Vec::new();
+    for line in filters::lines(bytes, options.dialect) {
+        if cancelled() {
+            let sequences = options.dialect.sequences();
+            let cut = match options.capabilities().has_cutter && !sequences.cut.is_empty() {
+                true => sequences.cut,
+                false => sequences.eject,
             };
+            result = write(&mut *out, &[b"\n", sequences.reset, cut].concat())
+                .and(Err(String::from("cancelled")));
+            break;
         }
+        if let Err(e) = write(&mut *out, line) {
+            result = Err(e);
+            break;
+        }
+        sent.extend_from_slice(line);
+    }
+    drop(out);
+    if result.is_ok() {
+        result = seal_output(args, &sent);
     }
-    res.push(b'\n');
+    record_job(args, &source_name(args), bytes, &result);
 
-    res
+    result
 }
 
-fn new_line(state: &mut State, variant: Token) -> Vec<u8> {
-    let mut res = Vec::<u8>::new();
+#[cfg(feature = "pdf")]
+fn pdf_document(paper: Option<Paper>, options: &Options, body: &[u8]) -> Vec<u8> {
+    pdf::document(body, &options.layout, paper.map(Paper::width_mm))
+}
 
-    res.extend_from_slice(close_top_header(state));
-    res.extend_from_slice(close_lower_header(state));
+#[cfg(not(feature = "pdf"))]
+fn pdf_document(_paper: Option<Paper>, _options: &Options, _body: &[u8]) -> Vec<u8> {
+    panic!("PDF output requires building with `--features pdf`.");
+}
 
-    if matches!(variant, Token::ActiveNewline) {
-        res.push(b'\n');
-    } else {
-        res.push(b' ')
+fn with_header_footer(args: &CliArgs, input: String) -> String {
+    // Front matter has to stay at the very start
+    let body_start = spacing::front_matter(&input).map_or(0, |(_, start)| start);
+    let (front_matter, input) = input.split_at(body_start);
+    let mut res = String::from(front_matter);
+    if let Some(header) = &args.header {
+        res.push_str(header);
+        res.push_str("\n\n");
+    }
+    res.push_str(input);
+    if let Some(footer) = &args.footer {
+        res.push_str("\n\n");
+        res.push_str(footer);
     }
 
     res
 }
 
-fn read_input(args: CliArgs) -> String {
-    let mut input: String = String::new();
-    if let Some(filebuf) = args.file {
-        let display_path = filebuf.display();
-        let mut file = match File::open(&filebuf) {
-            Ok(file) => file,
-            Err(e) => panic!("Could not open {}: {}", display_path, e),
+/// Appends each `--embed-csv` file as a csv block.
+fn with_embedded_csv(args: &CliArgs, mut input: String) -> String {
+    for path in &args.embed_csv {
+        let csv = match encoding::read(path, args.input_encoding) {
+            Ok(csv) => csv,
+            Err(e) => panic!("Could not open {}: {}", path.display(), e),
         };
-        if let Err(e) = file.read_to_string(&mut input) {
-            panic!("Cannot read from input {}: {}", display_path, e);
+        input.push_str("\n\n```csv\n");
+        input.push_str(csv.trim_end());
+        input.push_str("\n```\n");
+    }
+
+    input
+}
+
+fn source_name(args: &CliArgs) -> String {
+    match (&args.command, args.input_path()) {
+        (Some(Command::TestPage), _) => String::from("<test page>"),
+        (Some(Command::Mqtt { topic, .. }), _) => format!("mqtt:{}", topic),
+        (_, Some(filebuf)) => filebuf.display().to_string(),
+        (_, None) => match &args.listen_socket {
+            Some(path) => format!("socket {}", path.display()),
+            None => String::from("<message>"),
+        },
+    }
+}
+
+/// Expands a template with the data in a JSON or CSV file.
+fn render_template(
+    template: &Path,
+    data: &Path,
+    input_encoding: Encoding,
+    locale: &Locale,
+) -> String {
+    let read = |path: &Path| match encoding::read(path, input_encoding) {
+        Ok(text) => text,
+        Err(e) => panic!("Could not open {}: {}", path.display(), e),
+    };
+
+    let text = read(data);
+    let is_csv = data
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+    let value = match is_csv {
+        true => template::parse_csv(&text),
+        false => template::parse_json(&text),
+    };
+    let value = match value {
+        Ok(value) => value,
+        Err(e) => panic!("Could not parse {}: {}", data.display(), e),
+    };
+
+    match template::render(&read(template), &value, locale) {
+        Ok(markdown) => markdown,
+        Err(e) => panic!("Could not expand {}: {}", template.display(), e),
+    }
+}
+
+/// The test page, with a ruler as wide as the selected paper.
+fn test_page(args: &CliArgs) -> String {
+    let printer = args.printer();
+    let options = args.options_for(&printer);
+    let columns = options
+        .layout
+        .text_width()
+        .or(options.capabilities().max_width)
+        .unwrap_or(32);
+    let ruler = "1234567890"
+        .chars()
+        .cycle()
+        .take(columns)
+        .collect::<String>();
+    let printer = match printer.model {
+        Some(model) => model.name,
+        None => format!("{:?}", options.dialect),
+    };
+
+    include_str!("test_page.md")
+        .replace("{{version}}", env!("CARGO_PKG_VERSION"))
+        .replace("{{printer}}", &printer)
+        .replace("{{ruler}}", &ruler)
+}
+
+fn read_input(args: CliArgs) -> String {
+    if let Some(filebuf) = args.files.first() {
+        match encoding::read(filebuf, args.input_encoding) {
+            Ok(text) => text,
+            Err(e) => panic!("Could not open {}: {}", filebuf.display(), e),
         }
     } else if let Some(string) = args.message {
-        input = string;
+        string
     } else {
         panic!("Must provide an input source.");
     }
+}
 
-    input
+fn backend_name(args: &CliArgs) -> String {
+    if let Some(address) = &args.tcp {
+        return address.clone();
+    }
+
+    match (&args.printer, &args.destination) {
+        (Some(name), _) => name.clone(),
+        (None, Some(filebuf)) => filebuf.display().to_string(),
+        (None, None) => String::from("stdout"),
+    }
+}
+
+fn write_output(args: &CliArgs, slice: &[u8]) -> Result<(), String> {
+    let job = args.frame.wrap(slice)?;
+    let mut out = open_output(args)?;
+    out.write_all(&job)
+        .and_then(|_| out.flush())
+        .map_err(|e| format!("Could not write to {}: {}", backend_name(args), e))?;
+    drop(out);
+
+    seal_output(args, &job)
+}
+
+/// Keeps the checksum of what was written to a file destination, once it's
+/// all there. Devices like /dev/usb/lp0 aren't archives, so they're left alone.
+fn seal_output(args: &CliArgs, written: &[u8]) -> Result<(), String> {
+    let (Some(checksum), Some(path), false) = (args.checksum, &args.destination, args.dry_run)
+    else {
+        return Ok(());
+    };
+    if !fs::metadata(path).is_ok_and(|meta| meta.is_file()) {
+        return Ok(());
+    }
+
+    checksum::seal(path, checksum, written)
+        .map_err(|e| format!("Could not keep the checksum of {}: {}", path.display(), e))
+}
+
+/// A destination whose flush also waits for the data to reach the device.
+struct SyncedFile(File);
+
+impl Write for SyncedFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()?;
+        match self.0.sync_all() {
+            // Serial ports and other character devices can't be synced
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => Ok(()),
+            result => result,
+        }
+    }
 }
 
-fn write_output(args: CliArgs, slice: &[u8]) {
-    let mut file: Box<dyn Write> = if let Some(filebuf) = args.destination {
+fn open_output(args: &CliArgs) -> Result<Box<dyn Write>, String> {
+    if args.dry_run {
+        return Ok(Box::new(std::io::sink()));
+    }
+    if let Some(name) = &args.printer {
+        return open_printer(name);
+    }
+    if let Some(address) = &args.tcp {
+        return match TcpStream::connect(address) {
+            Ok(stream) => Ok(Box::new(stream)),
+            Err(e) => Err(format!("Could not connect to {}: {}", address, e)),
+        };
+    }
+
+    let file: Box<dyn Write> = if let Some(filebuf) = &args.destination {
+        if let Some(flow) = args.flow_control {
+            configure_flow_control(filebuf, flow);
+        }
+
         let display_path = filebuf.display();
-        let local_file = match OpenOptions::new().write(true).create(true).open(&filebuf) {
+        let local_file = match OpenOptions::new()
+            .write(true)
+            .create(true)
+            // An archive holds this job alone, or its checksum can't match
+            .truncate(args.checksum.is_some())
+            .open(filebuf)
+        {
             Ok(file) => file,
-            Err(e) => panic!("Could not open {} for writing: {}", display_path, e),
+            Err(e) => {
+                return Err(format!(
+                    "Could not open {} for writing: {}",
+                    display_path, e
+                ))
+            }
         };
-        Box::new(local_file)
+        match args.sync {
+            true => Box::new(SyncedFile(local_file)),
+            false => Box::new(local_file),
+        }
     } else {
         Box::new(std::io::stdout())
     };
 
-    file.write_all(slice).unwrap();
+    Ok(file)
+}
+
+#[cfg(windows)]
+fn open_printer(name: &str) -> Result<Box<dyn Write>, String> {
+    match winspool::Printer::open(name, env!("CARGO_PKG_NAME")) {
+        Ok(printer) => Ok(Box::new(printer)),
+        Err(e) => Err(format!("Could not open printer {}: {}", name, e)),
+    }
+}
+
+#[cfg(not(windows))]
+fn open_printer(name: &str) -> Result<Box<dyn Write>, String> {
+    Err(format!(
+        "Cannot print to {}: printer queues are only supported on Windows; use --destination",
+        name
+    ))
+}
+
+#[cfg(unix)]
+fn configure_flow_control(path: &Path, flow: FlowControl) {
+    use std::os::unix::fs::FileTypeExt;
+    use std::process::Command;
+
+    let display_path = path.display();
+    let is_serial = match std::fs::metadata(path) {
+        Ok(meta) => meta.file_type().is_char_device(),
+        Err(_) => false,
+    };
+    if !is_serial {
+        eprintln!(
+            "Ignoring flow control: {} is not a serial device",
+            display_path
+        );
+        return;
+    }
+
+    let device_flag = if cfg!(target_os = "linux") {
+        "-F"
+    } else {
+        "-f"
+    };
+    match Command::new("stty")
+        .arg(device_flag)
+        .arg(path)
+        .args(flow.stty_flags())
+        .status()
+    {
+        Ok(status) if status.success() => {}
+        Ok(status) => panic!(
+            "Could not set flow control on {}: stty {}",
+            display_path, status
+        ),
+        Err(e) => panic!("Could not run stty for {}: {}", display_path, e),
+    }
+}
+
+#[cfg(not(unix))]
+fn configure_flow_control(path: &Path, _flow: FlowControl) {
+    eprintln!(
+        "Ignoring flow control: not supported for {} on this platform",
+        path.display()
+    );
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[test]
+    fn flow_control_maps_to_exclusive_stty_flags() {
+        assert_eq!(
+            FlowControl::XonXoff.stty_flags(),
+            &["ixon", "ixoff", "-crtscts"]
+        );
+        assert_eq!(
+            FlowControl::RtsCts.stty_flags(),
+            &["-ixon", "-ixoff", "crtscts"]
+        );
+    }
+
+    #[test]
+    fn test_page_renders_cleanly() {
+        for dialect in ["escp", "escpos"] {
+            let args = CliArgs::parse_from(["mdtoepson", "--dialect", dialect, "test-page"]);
+            let rendered = render_markdown(&test_page(&args), &args.options());
+
+            assert_eq!(rendered.diagnostics, Vec::new(), "{}", dialect);
+        }
+    }
+
+    #[test]
+    fn last_init_flag_wins() {
+        let args = CliArgs::parse_from(["mdtoepson", "--init", "--no-init", "-m", "x"]);
+        assert!(!args.options().init);
+
+        let args = CliArgs::parse_from(["mdtoepson", "--no-init", "--init", "-m", "x"]);
+        assert!(args.options().init);
+    }
+
+    #[test]
+    fn profiles_are_read_once_per_job() {
+        let path = std::env::temp_dir().join(format!("mdtoepson-profile-{}", std::process::id()));
+        std::fs::write(
+            &path,
+            "model = \"tm-t20\"\ndensity = 3\n\n[lists]\nindent = 4\n\n[spacing]\nlist_before = 2\n",
+        )
+        .unwrap();
+        let args =
+            CliArgs::parse_from(["mdtoepson".as_ref(), "--profile".as_ref(), path.as_os_str()]);
+        let printer = args.printer();
+        std::fs::remove_file(&path).unwrap();
+
+        // Everything comes from what was loaded, with the file long gone
+        let options = args.options_for(&printer);
+        assert_eq!(options.dialect, Dialect::EscPos);
+        assert_eq!(options.density, Some(3));
+        assert_eq!(options.lists.indent, 4);
+        assert_eq!(options.spacing.list.before, 2);
+    }
+
+    #[test]
+    fn spooled_jobs_find_their_images_in_the_drop_folder() {
+        let dir = Path::new("spool");
+        let args = CliArgs::parse_from(["mdtoepson", "--spool-dir", "spool"]);
+        let job = spooled_job(&args, dir, &dir.join("printing").join("ticket.md"));
+
+        assert_eq!(job.files, vec![dir.join("ticket.md")]);
+        assert_eq!(job.options().base_dir, Some(dir.to_path_buf()));
+    }
 
     #[test]
-    fn new_line_removes_single_newlines() {
-        let mut state = State::default();
+    fn printer_queues_take_the_job_by_name() {
+        let queue = "EPSON TM-T20II";
+        let args = CliArgs::parse_from(["mdtoepson", "--printer", queue, "-m", "x"]);
+        assert_eq!(backend_name(&args), queue);
+        assert!(CliArgs::try_parse_from(["mdtoepson", "--printer", queue, "-d", "out"]).is_err());
 
-        let res = new_line(&mut state, Token::RemovableNewline);
+        // No queue has this name, and there are no queues off Windows
+        let args = CliArgs::parse_from(["mdtoepson", "--printer", "mdtoepson-missing"]);
+        let message = write_output(&args, b"x").unwrap_err();
+        assert!(message.contains("mdtoepson-missing"), "{}", message);
+        #[cfg(not(windows))]
+        assert!(message.contains("only supported on Windows"), "{}", message);
+    }
+
+    #[test]
+    fn synced_jobs_reach_files_and_devices() {
+        let out = std::env::temp_dir().join(format!("mdtoepson-sync-{}", std::process::id()));
+        let args = CliArgs::parse_from([
+            "mdtoepson".as_ref(),
+            "--sync".as_ref(),
+            "-d".as_ref(),
+            out.as_os_str(),
+        ]);
+        write_output(&args, b"last line\n").unwrap();
+        assert_eq!(std::fs::read(&out).unwrap(), b"last line\n");
+        std::fs::remove_file(out).unwrap();
 
-        assert_eq!(res, &[b' ']);
+        // Character devices can't be synced, which mustn't fail the job
+        #[cfg(unix)]
+        {
+            let args = CliArgs::parse_from(["mdtoepson", "--sync", "-d", "/dev/null"]);
+            write_output(&args, b"x").unwrap();
+        }
     }
 
     #[test]
-    fn new_line_collapses_multiple_newlines() {
-        let mut state = State::default();
+    fn dry_runs_render_without_touching_the_output() {
+        let dir = std::env::temp_dir().join(format!("mdtoepson-dry-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (doc, out) = (dir.join("doc.md"), dir.join("out"));
+        std::fs::write(&doc, "# Title\n\ntext").unwrap();
+        let args = CliArgs::parse_from([
+            "mdtoepson".as_ref(),
+            "--dry-run".as_ref(),
+            "--no-history".as_ref(),
+            "-d".as_ref(),
+            out.as_os_str(),
+            doc.as_os_str(),
+        ]);
 
-        let res = new_line(&mut state, Token::ActiveNewline);
+        print_document(&args, read_input(args.clone())).unwrap();
+        print_batch(&args).unwrap();
 
-        assert_eq!(res, &[b'\n']);
+        assert!(!out.exists());
+        std::fs::remove_dir_all(dir).unwrap();
     }
 
     #[test]
-    fn bold_transpiles() {
-        let input = "**bold text**";
-        let expected_output = b"\x1BEbold text\x1BF\n";
-        let res = transpile_markdown(&input);
+    fn followed_lines_print_whole_with_their_styles_closed() {
+        let out = std::env::temp_dir().join(format!("mdtoepson-follow-{}", std::process::id()));
+        let args = |frame: &str| {
+            CliArgs::parse_from([
+                "mdtoepson".as_ref(),
+                "--follow".as_ref(),
+                "--keep-linebreaks".as_ref(),
+                "--dialect".as_ref(),
+                "escpos".as_ref(),
+                "--no-init".as_ref(),
+                "--no-history".as_ref(),
+                "--frame".as_ref(),
+                frame.as_ref(),
+                "-d".as_ref(),
+                out.as_os_str(),
+            ])
+        };
+        assert!(follow(&args("length-prefixed")).is_err());
 
-        assert_eq!(res.as_slice(), expected_output)
+        // Lines arrive in pieces, as reads from a growing file do
+        let args = args("none");
+        let mut job = LiveJob::open(&args, String::from("log")).unwrap();
+        for piece in ["**disk", " full\nre", "tried\n"] {
+            job.push(piece.as_bytes()).unwrap();
+        }
+        job.finish().unwrap();
+
+        assert_eq!(
+            std::fs::read(&out).unwrap(),
+            b"\x1BE\x01disk full\x1BE\x00\nretried\n"
+        );
+        std::fs::remove_file(out).unwrap();
     }
 
     #[test]
-    fn italic_transpiles() {
-        let input = "*italic text*";
-        let expected_output = b"\x1B4italic text\x1B5\n";
-        let res = transpile_markdown(&input);
+    fn checksummed_files_hold_only_the_last_job() {
+        let dir = std::env::temp_dir().join(format!("mdtoepson-seal-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("out.bin");
+        let args = CliArgs::parse_from([
+            "mdtoepson".as_ref(),
+            "--checksum".as_ref(),
+            "trailer".as_ref(),
+            "-d".as_ref(),
+            out.as_os_str(),
+        ]);
+
+        write_output(&args, b"a much longer first job\n").unwrap();
+        write_output(&args, b"second\n").unwrap();
 
-        assert_eq!(res.as_slice(), expected_output)
+        let bytes = std::fs::read(&out).unwrap();
+        assert!(bytes.starts_with(b"second\n\nSHA-256 "));
+        assert_eq!(bytes.len(), b"second\n\nSHA-256 \n".len() + 64);
+        assert_eq!(checksum::verify(&out), Ok(Checksum::Trailer));
+        std::fs::remove_dir_all(dir).unwrap();
     }
 
     #[test]
-    fn underlined_transpiles() {
-        let input = "__underlined text__";
-        let expected_output = b"\x1B-1underlined text\x1B-0\n";
-        let res = transpile_markdown(&input);
+    fn streamed_jobs_set_the_printer_up_once() {
+        let out = std::env::temp_dir().join(format!("mdtoepson-stream-{}", std::process::id()));
+        let args = CliArgs::parse_from([
+            "mdtoepson".as_ref(),
+            "--dialect".as_ref(),
+            "escpos".as_ref(),
+            "--paper".as_ref(),
+            "58mm".as_ref(),
+            "--density".as_ref(),
+            "2".as_ref(),
+            "--feed-after".as_ref(),
+            "2".as_ref(),
+            "--no-history".as_ref(),
+            "-d".as_ref(),
+            out.as_os_str(),
+        ]);
+        let options = args.options();
+        let setup = render::setup(&options);
+        let reset = options.dialect.sequences().reset;
+
+        let mut job = LiveJob::open(&args, String::from("log")).unwrap();
+        job.push(b"one\ntwo\n").unwrap();
+        job.push(b"three\n").unwrap();
+        job.finish().unwrap();
 
-        assert_eq!(res.as_slice(), expected_output)
+        let expected = [&setup[..], b"one two\nthree\n\n\n", reset].concat();
+        assert_eq!(std::fs::read(&out).unwrap(), expected);
+        std::fs::remove_file(out).unwrap();
     }
 
     #[test]
-    fn top_header_transpiles() {
-        let input = "# Header text\n";
-        let expected_output = b"\n\n\x1BE\x1Bw1\x1BW1Header text\x1BF\x1Bw0\x1BW0\n\n";
-        let res = transpile_markdown(&input);
+    fn cancelled_jobs_stop_between_lines_not_inside_images() {
+        let out = std::env::temp_dir().join(format!("mdtoepson-cancel-{}", std::process::id()));
+        let args = CliArgs::parse_from([
+            "mdtoepson".as_ref(),
+            "--dialect".as_ref(),
+            "escpos".as_ref(),
+            "--no-history".as_ref(),
+            "-d".as_ref(),
+            out.as_os_str(),
+        ]);
+        // The raster's rows are 0x0A bytes, which mustn't end a line
+        let image = b"\x1Dv0\x00\x01\x00\x02\x00\x0A\x0A\n";
+        let job = [&b"a\n"[..], image, b"b\n"].concat();
+        let checks = std::cell::Cell::new(0);
+        let cancelled = || {
+            checks.set(checks.get() + 1);
+            checks.get() > 2
+        };
 
-        assert_eq!(res.as_slice(), expected_output)
+        assert!(print_cancellable(&args, &args.options(), &job, &cancelled).is_err());
+        let written = std::fs::read(&out).unwrap();
+        assert!(written.starts_with(&[&b"a\n"[..], image, b"\n"].concat()));
+        std::fs::remove_file(out).unwrap();
     }
 
     #[test]
-    fn lower_header_transpiles() {
-        let input = "## Header text\n";
-        let expected_output = b"\n\n\x1Bw1Header text\x1Bw0\n";
-        let res = transpile_markdown(&input);
+    fn batches_share_one_output_with_cuts_between() {
+        let dir = std::env::temp_dir().join(format!("mdtoepson-batch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (one, two, out) = (dir.join("one.md"), dir.join("two.md"), dir.join("out"));
+        std::fs::write(&one, "one").unwrap();
+        std::fs::write(&two, "two").unwrap();
+
+        let args = CliArgs::parse_from([
+            "mdtoepson".as_ref(),
+            "--dialect".as_ref(),
+            "escpos".as_ref(),
+            "--no-init".as_ref(),
+            "--no-history".as_ref(),
+            "-d".as_ref(),
+            out.as_os_str(),
+            one.as_os_str(),
+            two.as_os_str(),
+        ]);
+        print_batch(&args).unwrap();
+
+        assert_eq!(std::fs::read(&out).unwrap(), b"one\n\x1DVA\x03two\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn each_job_is_one_frame() {
+        let dir = std::env::temp_dir().join(format!("mdtoepson-frame-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (one, two, out) = (dir.join("one.md"), dir.join("two.md"), dir.join("out"));
+        std::fs::write(&one, "one").unwrap();
+        std::fs::write(&two, "two").unwrap();
+        let args = |frame: &str| {
+            CliArgs::parse_from([
+                "mdtoepson".as_ref(),
+                "--dialect".as_ref(),
+                "escpos".as_ref(),
+                "--no-init".as_ref(),
+                "--no-history".as_ref(),
+                "--frame".as_ref(),
+                frame.as_ref(),
+                "-d".as_ref(),
+                out.as_os_str(),
+                one.as_os_str(),
+                two.as_os_str(),
+            ])
+        };
+
+        print_batch(&args("length-prefixed")).unwrap();
+        assert_eq!(
+            std::fs::read(&out).unwrap(),
+            b"\0\0\0\x04one\n\0\0\0\x08\x1DVA\x03two\n"
+        );
 
-        assert_eq!(res.as_slice(), expected_output)
+        std::fs::remove_file(&out).unwrap();
+        let args = args("stx-etx");
+        let mut job = LiveJob::open(&args, String::from("test")).unwrap();
+        job.push(b"one\ntwo\n").unwrap();
+        job.finish().unwrap();
+        assert_eq!(std::fs::read(&out).unwrap(), b"\x02one two\n\x03");
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
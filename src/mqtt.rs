@@ -0,0 +1,264 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+/// Seconds the broker waits without hearing from us before dropping us
+const KEEP_ALIVE: u16 = 60;
+/// Pause before connecting again after losing the broker
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const SUBSCRIBE: u8 = 0x82;
+const SUBACK: u8 = 0x90;
+const PINGREQ: u8 = 0xC0;
+
+/// Where to subscribe, as MQTT 3.1.1 over plain TCP.
+#[derive(Clone, Debug)]
+pub struct Subscription {
+    /// `host:port` of the broker
+    pub broker: String,
+    /// Topic filter, which may use `+` and `#` wildcards
+    pub topic: String,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// A message received on the subscription.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Message {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    /// Set for QoS 1 messages, which must be acknowledged
+    id: Option<u16>,
+}
+
+/// Passes every message on the topic to `handle`, reconnecting whenever the
+/// broker goes away. Messages are acknowledged once handled.
+pub fn subscribe(subscription: &Subscription, mut handle: impl FnMut(&Message)) -> ! {
+    loop {
+        if let Err(e) = session(subscription, &mut handle) {
+            eprintln!(
+                "warning: lost MQTT broker {}: {}; retrying in {}s",
+                subscription.broker,
+                e,
+                RETRY_DELAY.as_secs()
+            );
+        }
+        thread::sleep(RETRY_DELAY);
+    }
+}
+
+fn session(subscription: &Subscription, handle: &mut impl FnMut(&Message)) -> io::Result<()> {
+    let mut stream = TcpStream::connect(&subscription.broker)?;
+    stream.write_all(&connect_packet(subscription))?;
+    let (kind, body) = read_packet(&mut stream)?;
+    match (kind & 0xF0, body.get(1)) {
+        (CONNACK, Some(0)) => {}
+        (CONNACK, Some(&code)) => return Err(io::Error::other(refusal(code))),
+        _ => return Err(io::Error::other("expected CONNACK")),
+    }
+
+    stream.write_all(&subscribe_packet(1, &subscription.topic))?;
+    loop {
+        // Ping whenever the broker is quiet between packets
+        stream.set_read_timeout(Some(Duration::from_secs(u64::from(KEEP_ALIVE) / 2)))?;
+        match stream.peek(&mut [0]) {
+            Ok(0) => return Err(io::Error::other("connection closed")),
+            Ok(_) => {}
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                stream.write_all(&[PINGREQ, 0])?;
+                continue;
+            }
+            Err(e) => return Err(e),
+        }
+        // Once a packet has started, a stall ends the session rather than
+        // leaving the rest of it to be read as the next packet
+        stream.set_read_timeout(Some(Duration::from_secs(u64::from(KEEP_ALIVE))))?;
+        let (kind, body) = match read_packet(&mut stream) {
+            Ok(packet) => packet,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                return Err(io::Error::other("connection closed"))
+            }
+            Err(e) => return Err(e),
+        };
+        match kind & 0xF0 {
+            PUBLISH => {
+                let message = parse_publish(kind, &body)?;
+                handle(&message);
+                if let Some(id) = message.id {
+                    let [high, low] = id.to_be_bytes();
+                    stream.write_all(&[PUBACK, 2, high, low])?;
+                }
+            }
+            SUBACK if body.last() == Some(&0x80) => {
+                let message = format!("broker refused subscription to {}", subscription.topic);
+                return Err(io::Error::other(message));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn refusal(code: u8) -> String {
+    let reason = match code {
+        1 => "unacceptable protocol version",
+        2 => "client id rejected",
+        3 => "server unavailable",
+        4 => "bad user name or password",
+        5 => "not authorized",
+        _ => "unknown reason",
+    };
+
+    format!("connection refused: {}", reason)
+}
+
+fn connect_packet(subscription: &Subscription) -> Vec<u8> {
+    let mut flags = 0x02; // clean session
+    let mut body = Vec::new();
+    push_string(&mut body, "MQTT");
+    body.push(4); // protocol level 3.1.1
+    if subscription.username.is_some() {
+        flags |= 0x80;
+    }
+    if subscription.password.is_some() {
+        flags |= 0x40;
+    }
+    body.push(flags);
+    body.extend_from_slice(&KEEP_ALIVE.to_be_bytes());
+    push_string(&mut body, &subscription.client_id);
+    for field in [&subscription.username, &subscription.password]
+        .into_iter()
+        .flatten()
+    {
+        push_string(&mut body, field);
+    }
+
+    packet(CONNECT, &body)
+}
+
+/// Subscribes at QoS 1, so messages sent while printing aren't lost.
+fn subscribe_packet(id: u16, topic: &str) -> Vec<u8> {
+    let mut body = id.to_be_bytes().to_vec();
+    push_string(&mut body, topic);
+    body.push(1);
+
+    packet(SUBSCRIBE, &body)
+}
+
+fn parse_publish(kind: u8, body: &[u8]) -> io::Result<Message> {
+    let malformed = || io::Error::other("malformed PUBLISH");
+    let length = usize::from(u16::from_be_bytes([
+        *body.first().ok_or_else(malformed)?,
+        *body.get(1).ok_or_else(malformed)?,
+    ]));
+    let topic = body.get(2..2 + length).ok_or_else(malformed)?;
+    let mut rest = &body[2 + length..];
+
+    let id = match (kind >> 1) & 0x03 {
+        0 => None,
+        _ => {
+            let id = rest.get(..2).ok_or_else(malformed)?;
+            let id = u16::from_be_bytes([id[0], id[1]]);
+            rest = &rest[2..];
+            Some(id)
+        }
+    };
+
+    Ok(Message {
+        topic: String::from_utf8_lossy(topic).into_owned(),
+        payload: rest.to_vec(),
+        id,
+    })
+}
+
+fn read_packet(stream: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut byte = [0];
+    stream.read_exact(&mut byte)?;
+    let kind = byte[0];
+
+    let mut length = 0;
+    for shift in (0..28).step_by(7) {
+        stream.read_exact(&mut byte)?;
+        length |= usize::from(byte[0] & 0x7F) << shift;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body)?;
+
+    Ok((kind, body))
+}
+
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut res = vec![kind];
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        res.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    res.extend_from_slice(body);
+
+    res
+}
+
+fn push_string(res: &mut Vec<u8>, s: &str) {
+    res.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    res.extend_from_slice(s.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_packets_use_multibyte_lengths() {
+        let body = vec![0; 321];
+        let encoded = packet(PUBLISH, &body);
+
+        assert_eq!(&encoded[..3], &[PUBLISH, 0xC1, 0x02]);
+        assert_eq!(read_packet(&mut &encoded[..]).unwrap(), (PUBLISH, body));
+    }
+
+    #[test]
+    fn connect_carries_credentials() {
+        let subscription = Subscription {
+            broker: String::from("localhost:1883"),
+            topic: String::from("printer/#"),
+            client_id: String::from("p"),
+            username: Some(String::from("u")),
+            password: None,
+        };
+
+        assert_eq!(
+            connect_packet(&subscription),
+            b"\x10\x10\x00\x04MQTT\x04\x82\x00\x3C\x00\x01p\x00\x01u"
+        );
+    }
+
+    #[test]
+    fn publish_at_qos_1_has_an_id() {
+        let message = parse_publish(0x32, b"\x00\x03a/b\x00\x07# hi").unwrap();
+
+        assert_eq!(
+            message,
+            Message {
+                topic: String::from("a/b"),
+                payload: b"# hi".to_vec(),
+                id: Some(7),
+            }
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// Line printed at the bottom of every page.
+#[derive(Debug)]
+pub struct Footer<'a> {
+    /// Footer text, in which `{{page}}` and `{{pages}}` expand
+    pub template: &'a str,
+    /// Width the footer is centred in
+    pub width: Option<usize>,
+    pub page_length: usize,
+}
+
+/// Lines a footer takes, including the blank line above it.
+const FOOTER_LINES: usize = 2;
+
+/// Splits rendered output into pages at its line feeds, ending each page with
+/// a numbered footer and a form feed. `breaks` are the sorted offsets of the
+/// line feeds in `bytes`; the total page count is only known once they have
+/// all been counted, which is why this runs after rendering.
+pub fn paginate(bytes: &[u8], breaks: &[usize], footer: &Footer) -> Vec<u8> {
+    let body = footer.page_length.saturating_sub(FOOTER_LINES).max(1);
+    let pages = breaks.len().div_ceil(body).max(1);
+
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut start = 0;
+    for page in 1..=pages {
+        let last = breaks.get(page * body - 1).copied();
+        let end = last.map_or(bytes.len(), |at| at + 1);
+        res.extend_from_slice(&bytes[start..end]);
+        start = end;
+
+        if last.is_none() {
+            // Fill the last page so its footer lines up with the others
+            let used = breaks.len() - (page - 1) * body;
+            res.extend(core::iter::repeat_n(b'\n', body - used));
+        }
+        res.push(b'\n');
+        res.extend_from_slice(footer.line(page, pages).as_bytes());
+        res.push(0x0C);
+    }
+
+    res
+}
+
+impl Footer<'_> {
+    fn line(&self, page: usize, pages: usize) -> String {
+        let text = self
+            .template
+            .replace("{{page}}", &page.to_string())
+            .replace("{{pages}}", &pages.to_string());
+        let indent = self
+            .width
+            .map_or(0, |width| width.saturating_sub(text.chars().count()) / 2);
+
+        format!("{}{}", " ".repeat(indent), text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_documents_are_one_padded_page() {
+        let footer = Footer {
+            template: "p{{page}}",
+            width: None,
+            page_length: 5,
+        };
+
+        assert_eq!(paginate(b"a\n", &[1], &footer), b"a\n\n\n\np1\x0C");
+    }
+}
@@ -0,0 +1,142 @@
+use alloc::vec::Vec;
+
+use crate::dialect::{Dialect, Font};
+
+/// Common paper sizes, each standing in for a full set of character metrics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(clap::ValueEnum))]
+pub enum Paper {
+    /// 58 mm receipt roll, 32 columns of Font A
+    #[cfg_attr(feature = "std", value(name = "58mm"))]
+    Mm58,
+    /// 80 mm receipt roll, 48 columns of Font A
+    #[cfg_attr(feature = "std", value(name = "80mm"))]
+    Mm80,
+    /// A4 cut sheet at 10 characters per inch
+    #[cfg_attr(feature = "std", value(name = "a4"))]
+    A4,
+}
+
+/// Line metrics used for wrapping and job setup.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Layout {
+    /// Total characters across the paper, before margins
+    pub width: Option<usize>,
+    pub font: Option<Font>,
+    pub left_margin: usize,
+    pub right_margin: usize,
+    /// Lines per page, for page printers
+    pub page_length: Option<usize>,
+    /// Height of each label in millimetres, when printing on labels
+    pub label_height: Option<usize>,
+}
+
+impl Paper {
+    pub fn layout(self) -> Layout {
+        match self {
+            Paper::Mm58 => Layout {
+                width: Some(32),
+                font: Some(Font::A),
+                left_margin: 0,
+                right_margin: 0,
+                page_length: None,
+                label_height: None,
+            },
+            Paper::Mm80 => Layout {
+                width: Some(48),
+                font: Some(Font::A),
+                left_margin: 0,
+                right_margin: 0,
+                page_length: None,
+                label_height: None,
+            },
+            Paper::A4 => Layout {
+                width: Some(82),
+                font: Some(Font::A),
+                left_margin: 5,
+                right_margin: 5,
+                page_length: Some(70),
+                label_height: None,
+            },
+        }
+    }
+}
+
+#[cfg(feature = "pdf")]
+impl Paper {
+    /// Physical width of the paper.
+    pub fn width_mm(self) -> f64 {
+        match self {
+            Paper::Mm58 => 58.0,
+            Paper::Mm80 => 80.0,
+            Paper::A4 => 210.0,
+        }
+    }
+}
+
+impl Layout {
+    /// Characters available for text between the margins.
+    pub fn text_width(&self) -> Option<usize> {
+        self.width
+            .map(|width| width.saturating_sub(self.left_margin + self.right_margin))
+            .filter(|&width| width > 0)
+    }
+
+    /// Commands that put the printer into this layout at the start of a job.
+    pub fn setup(&self, dialect: Dialect) -> Vec<u8> {
+        let mut res = Vec::new();
+        let font = self.font.unwrap_or(Font::A);
+
+        if let Some(font) = self.font {
+            res.extend_from_slice(dialect.font(font));
+        }
+        if let Some(width) = self.width {
+            if self.left_margin > 0 || self.right_margin > 0 {
+                res.append(&mut dialect.margins(font, width, self.left_margin, self.right_margin));
+            }
+        }
+        if let Some(lines) = self.page_length {
+            res.append(&mut dialect.page_length(lines));
+        }
+        if let Some(mm) = self.label_height {
+            res.append(&mut dialect.label_length(mm));
+        }
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a4_wraps_inside_margins() {
+        assert_eq!(Paper::A4.layout().text_width(), Some(72));
+    }
+
+    #[test]
+    fn a4_setup_sets_pitch_margins_and_page_length() {
+        let setup = Paper::A4.layout().setup(Dialect::EscP);
+
+        assert_eq!(setup, b"\x1BP\x1Bl\x05\x1BQ\x4D\x1BC\x46");
+    }
+
+    #[test]
+    fn receipt_setup_only_selects_font() {
+        let setup = Paper::Mm58.layout().setup(Dialect::EscPos);
+
+        assert_eq!(setup, b"\x1BM\x00");
+    }
+
+    #[test]
+    fn label_height_sets_page_length_in_360ths() {
+        let layout = Layout {
+            label_height: Some(25),
+            ..Default::default()
+        };
+
+        assert_eq!(layout.setup(Dialect::EscP), b"\x1B(C\x02\x00\x62\x01");
+        assert_eq!(layout.setup(Dialect::EscPos), b"");
+    }
+}
@@ -0,0 +1,250 @@
+use std::fmt::Write;
+
+use mdtoepson::dialect::marker::*;
+use mdtoepson::paper::Layout;
+
+const PT_PER_MM: f64 = 72.0 / 25.4;
+/// Advance of a Courier glyph as a fraction of the font size
+const COURIER_ADVANCE: f64 = 0.6;
+/// Blank paper above and below the text
+const MARGIN_MM: f64 = 5.0;
+/// Width assumed for a layout without a column count
+const DEFAULT_COLUMNS: usize = 80;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Style {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    double: bool,
+    tall: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Run {
+    style: Style,
+    text: String,
+}
+
+/// Lays out a body rendered with the PDF dialect on paper `paper_mm` wide.
+pub fn document(body: &[u8], layout: &Layout, paper_mm: Option<f64>) -> Vec<u8> {
+    let columns = layout.width.unwrap_or(DEFAULT_COLUMNS);
+    // Without a physical width, assume 10 characters per inch
+    let paper_mm = paper_mm.unwrap_or(columns as f64 * 2.54);
+    let advance = paper_mm / columns as f64 * PT_PER_MM;
+    let size = advance / COURIER_ADVANCE;
+    let leading = size;
+    let left = layout.left_margin as f64 * advance;
+    let margin = MARGIN_MM * PT_PER_MM;
+
+    let lines = parse(body);
+    let per_page = layout.page_length.unwrap_or(lines.len()).max(1);
+    let width = paper_mm * PT_PER_MM;
+    let height = per_page as f64 * leading + 2.0 * margin;
+
+    let pages = lines
+        .chunks(per_page)
+        .map(|page| {
+            let mut content = String::new();
+            let mut y = height - margin;
+            for line in page {
+                y -= leading;
+                let mut x = left;
+                for run in line {
+                    x += draw_run(&mut content, run, x, y, size, advance);
+                }
+            }
+            content
+        })
+        .collect::<Vec<_>>();
+
+    write_document(&pages, width, height)
+}
+
+/// Splits the body into lines of uniformly styled runs.
+fn parse(body: &[u8]) -> Vec<Vec<Run>> {
+    let text = String::from_utf8_lossy(body);
+    let mut style = Style::default();
+    let mut lines: Vec<Vec<Run>> = vec![Vec::new()];
+
+    for c in text.chars() {
+        let before = style;
+        match c as u32 as u8 {
+            _ if !c.is_ascii() => {}
+            BOLD_ON => style.bold = true,
+            BOLD_OFF => style.bold = false,
+            ITALIC_ON => style.italic = true,
+            ITALIC_OFF => style.italic = false,
+            UNDERLINE_ON => style.underline = true,
+            UNDERLINE_OFF => style.underline = false,
+            DOUBLE_ON => style.double = true,
+            DOUBLE_OFF => style.double = false,
+            TALL_ON => style.tall = true,
+            TALL_OFF => style.tall = false,
+            b'\n' => {
+                lines.push(Vec::new());
+                continue;
+            }
+            _ => {}
+        }
+        if style != before || (c.is_ascii_control() && c != '\n') {
+            continue;
+        }
+
+        let line = lines.last_mut().unwrap();
+        match line.last_mut() {
+            Some(run) if run.style == style => run.text.push(c),
+            _ => line.push(Run {
+                style,
+                text: c.to_string(),
+            }),
+        }
+    }
+    if lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+
+    lines
+}
+
+/// Draws a run at the baseline, returning the horizontal space it took.
+fn draw_run(content: &mut String, run: &Run, x: f64, y: f64, size: f64, advance: f64) -> f64 {
+    let font = match (run.style.bold, run.style.italic) {
+        (false, false) => "F1",
+        (true, false) => "F2",
+        (false, true) => "F3",
+        (true, true) => "F4",
+    };
+    let (font_size, scale) = match (run.style.double, run.style.tall) {
+        (true, _) => (size * 2.0, 100),
+        (false, true) => (size * 2.0, 50),
+        (false, false) => (size, 100),
+    };
+    let width =
+        run.text.chars().count() as f64 * advance * if run.style.double { 2.0 } else { 1.0 };
+
+    let _ = write!(
+        content,
+        "BT /{} {:.2} Tf {} Tz {:.2} {:.2} Td (",
+        font, font_size, scale, x, y
+    );
+    for c in run.text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                content.push('\\');
+                content.push(c);
+            }
+            c if c.is_ascii() => content.push(c),
+            c if (c as u32) < 0x100 => {
+                let _ = write!(content, "\\{:03o}", c as u32);
+            }
+            _ => content.push('?'),
+        }
+    }
+    content.push_str(") Tj ET\n");
+
+    if run.style.underline {
+        let _ = writeln!(
+            content,
+            "{:.2} w {:.2} {:.2} m {:.2} {:.2} l S",
+            size / 15.0,
+            x,
+            y - size / 8.0,
+            x + width,
+            y - size / 8.0
+        );
+    }
+
+    width
+}
+
+fn write_document(pages: &[String], width: f64, height: f64) -> Vec<u8> {
+    let fonts = [
+        "Courier",
+        "Courier-Bold",
+        "Courier-Oblique",
+        "Courier-BoldOblique",
+    ];
+    let first_page = 3 + fonts.len();
+    let mut objects = vec![
+        String::from("<< /Type /Catalog /Pages 2 0 R >>"),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            (0..pages.len())
+                .map(|i| format!("{} 0 R", first_page + 2 * i))
+                .collect::<Vec<_>>()
+                .join(" "),
+            pages.len()
+        ),
+    ];
+    for font in fonts {
+        objects.push(format!(
+            "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+            font
+        ));
+    }
+    for (i, content) in pages.iter().enumerate() {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] \
+             /Resources << /Font << /F1 3 0 R /F2 4 0 R /F3 5 0 R /F4 6 0 R >> >> \
+             /Contents {} 0 R >>",
+            width,
+            height,
+            first_page + 2 * i + 1
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}endstream",
+            content.len(),
+            content
+        ));
+    }
+
+    let mut res = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(res.len());
+        let _ = write!(res, "{} 0 obj\n{}\nendobj\n", i + 1, object);
+    }
+    let xref = res.len();
+    let _ = write!(res, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(res, "{:010} 00000 n ", offset);
+    }
+    let _ = write!(
+        res,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref
+    );
+
+    res.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_splits_styled_runs_and_lines() {
+        let lines = parse(b"a\x02b\x03\nc");
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0][1].text, "b");
+        assert!(lines[0][1].style.bold);
+        assert_eq!(lines[1][0].text, "c");
+    }
+
+    #[test]
+    fn document_paginates_by_page_length() {
+        let layout = Layout {
+            width: Some(10),
+            page_length: Some(2),
+            ..Default::default()
+        };
+        let pdf = String::from_utf8(document(b"1\n2\n3\n", &layout, Some(58.0))).unwrap();
+
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.contains("/Count 2"));
+        assert!(pdf.ends_with("%%EOF\n"));
+    }
+}
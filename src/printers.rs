@@ -0,0 +1,281 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+use mdtoepson::dialect::{Capabilities, Dialect};
+use mdtoepson::glyphs::Glyphs;
+use mdtoepson::lists::ListStyle;
+use mdtoepson::paper::Paper;
+use mdtoepson::spacing::Spacing;
+use mdtoepson::toml::{self, Table};
+
+const PRINTERS: &str = include_str!("../printers.toml");
+
+/// A printer model from the built-in database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Model {
+    pub id: String,
+    pub name: String,
+    pub dialect: Dialect,
+    pub paper: Option<Paper>,
+    pub capabilities: Capabilities,
+    /// Print darkness the printer is set to before each job
+    pub density: Option<i8>,
+    /// Blank lines fed after a job, so its end clears the tear bar
+    pub feed_after: Option<usize>,
+}
+
+impl Model {
+    /// A printer known only by its dialect.
+    fn generic(dialect: Dialect) -> Self {
+        Model {
+            id: String::new(),
+            name: format!("{:?}", dialect),
+            dialect,
+            paper: None,
+            capabilities: dialect.capabilities(),
+            density: None,
+            feed_after: None,
+        }
+    }
+
+    /// Overrides whatever the table sets. Naming a dialect starts over from
+    /// its capabilities.
+    fn apply(&mut self, table: &Table) -> Result<(), String> {
+        if let Some(name) = table.string("name") {
+            self.name = name.to_string();
+        }
+        if let Some(name) = table.string("dialect") {
+            self.dialect = Dialect::from_str(name, true)?;
+            self.capabilities = self.dialect.capabilities();
+        }
+        if let Some(name) = table.string("paper") {
+            self.paper = Some(Paper::from_str(name, true)?);
+        }
+        if let Some(density) = table.integer("density") {
+            if !(-6..=6).contains(&density) {
+                return Err(format!("density {} is not between -6 and 6", density));
+            }
+            self.density = Some(density as i8);
+        }
+        if let Some(lines) = table.integer("feed_after") {
+            let lines =
+                usize::try_from(lines).map_err(|_| format!("feed_after {} is negative", lines))?;
+            self.feed_after = Some(lines);
+        }
+
+        let capabilities = &mut self.capabilities;
+        if let Some(cutter) = table.boolean("cutter") {
+            capabilities.has_cutter = cutter;
+        }
+        if let Some(qr) = table.boolean("qr") {
+            capabilities.has_qr = qr;
+        }
+        if let Some(barcode) = table.boolean("barcode") {
+            capabilities.has_barcode = barcode;
+        }
+        if let Some(double_height) = table.boolean("double_height") {
+            capabilities.has_double_height = double_height;
+        }
+        if let Some(scripts) = table.boolean("scripts") {
+            capabilities.has_scripts = scripts;
+        }
+        if let Some(box_drawing) = table.boolean("box_drawing") {
+            capabilities.has_box_drawing = box_drawing;
+        }
+        if let Some(colors) = table.integer("colors") {
+            capabilities.colors = colors.clamp(1, 255) as u8;
+        }
+        if let Some(max_width) = table.integer("max_width") {
+            capabilities.max_width = Some(max_width.max(1) as usize);
+        }
+
+        Ok(())
+    }
+}
+
+/// Every model in the built-in database.
+pub fn models() -> Vec<Model> {
+    let tables = toml::parse(PRINTERS)
+        .unwrap_or_else(|e| panic!("Built-in printers.toml is invalid: {}", e));
+
+    tables
+        .iter()
+        .map(|table| {
+            let mut model = Model::generic(Dialect::default());
+            model.id = table.name.clone();
+            model.name = table.name.clone();
+            model
+                .apply(table)
+                .unwrap_or_else(|e| panic!("Built-in printer {} is invalid: {}", table.name, e));
+            model
+        })
+        .collect()
+}
+
+/// Looks a model up by id, ignoring case.
+pub fn find(id: &str) -> Option<Model> {
+    models()
+        .into_iter()
+        .find(|model| model.id.eq_ignore_ascii_case(id))
+}
+
+/// A user's own printer description: settings at the top level, applied
+/// over the model it names, a `[glyphs]` table of custom characters, a
+/// `[lists]` table of bullets and a `[spacing]` table of blank lines. With
+/// `inherits = "escpos-base"` it starts from `escpos-base.toml` beside it and
+/// sets only what differs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Profile {
+    pub model: Model,
+    pub glyphs: Glyphs,
+    pub lists: ListStyle,
+    pub spacing: Spacing,
+}
+
+impl Profile {
+    /// Reads a profile, starting from the model `base` when one is given.
+    pub fn load(path: &Path, base: Option<Model>) -> Self {
+        let tables = read_tables(path, &mut Vec::new())
+            .unwrap_or_else(|e| panic!("Invalid profile {}: {}", path.display(), e));
+
+        match Self::from_tables(&tables, base) {
+            Ok(profile) => profile,
+            Err(e) => panic!("Invalid profile {}: {}", path.display(), e),
+        }
+    }
+
+    #[cfg(test)]
+    fn parse(text: &str, base: Option<Model>) -> Result<Self, String> {
+        Self::from_tables(&toml::parse(text)?, base)
+    }
+
+    fn from_tables(tables: &[Table], base: Option<Model>) -> Result<Self, String> {
+        let empty = Table::default();
+        let settings = tables.iter().find(|t| t.name.is_empty()).unwrap_or(&empty);
+
+        let mut model = match (base, settings.string("model")) {
+            (Some(model), _) => model,
+            (None, Some(id)) => find(id).ok_or_else(|| format!("unknown printer model {}", id))?,
+            (None, None) => Model::generic(Dialect::default()),
+        };
+        model.apply(settings)?;
+
+        let glyphs = match tables.iter().find(|t| t.name == "glyphs") {
+            Some(table) => Glyphs::from_table(table)?,
+            None => Glyphs::default(),
+        };
+
+        let lists = match tables.iter().find(|t| t.name == "lists") {
+            Some(table) => ListStyle::from_table(table)?,
+            None => ListStyle::default(),
+        };
+
+        let mut spacing = Spacing::default();
+        if let Some(table) = tables.iter().find(|t| t.name == "spacing") {
+            spacing.apply(table)?;
+        }
+
+        Ok(Profile {
+            model,
+            glyphs,
+            lists,
+            spacing,
+        })
+    }
+}
+
+/// A profile's tables laid over those of the profiles it inherits from.
+/// `seen` holds the files already on the way down, to catch loops.
+fn read_tables(path: &Path, seen: &mut Vec<PathBuf>) -> Result<Vec<Table>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("could not open {}: {}", path.display(), e))?;
+    let tables = toml::parse(&text)?;
+    let parent = tables
+        .iter()
+        .find(|t| t.name.is_empty())
+        .and_then(|t| t.string("inherits"));
+    let Some(parent) = parent else {
+        return Ok(tables);
+    };
+
+    let mut parent_path = path.with_file_name(parent);
+    if parent_path.extension().is_none() {
+        parent_path.set_extension("toml");
+    }
+    seen.push(path.to_path_buf());
+    if seen.contains(&parent_path) {
+        return Err(format!("{} inherits from itself", parent_path.display()));
+    }
+    let base = read_tables(&parent_path, seen)
+        .map_err(|e| format!("in {}: {}", parent_path.display(), e))?;
+
+    Ok(toml::overlay(base, &tables))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_models_are_valid() {
+        let models = models();
+
+        assert!(models.len() >= 3);
+        assert!(models.iter().all(|model| model.paper.is_some()));
+    }
+
+    #[test]
+    fn finds_models_by_id() {
+        let model = find("TM-T20").unwrap();
+
+        assert_eq!(model.dialect, Dialect::EscPos);
+        assert_eq!(model.paper, Some(Paper::Mm80));
+        assert!(model.capabilities.has_cutter);
+    }
+
+    #[test]
+    fn profiles_override_their_model() {
+        let profile = Profile::parse(
+            "model = \"tm-t20\"\ncutter = false\ndensity = 3\nfeed_after = 4\n\n[glyphs]\n\"❤\" = \"#\"\n",
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(profile.model.dialect, Dialect::EscPos);
+        assert!(!profile.model.capabilities.has_cutter);
+        assert_eq!(profile.model.density, Some(3));
+        assert_eq!(profile.model.feed_after, Some(4));
+        assert_eq!(profile.glyphs.code('❤'), Some(0x7E));
+    }
+
+    #[test]
+    fn profiles_inherit_what_they_dont_set() {
+        let dir = std::env::temp_dir().join(format!("mdtoepson-profiles-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("escpos-base.toml"),
+            "model = \"tm-t20\"\ndensity = 2\n\n[spacing]\nlist_after = 1\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("bar.toml"),
+            "inherits = \"escpos-base\"\ncutter = false\n\n[spacing]\nlist_before = 2\n",
+        )
+        .unwrap();
+        fs::write(dir.join("loop.toml"), "inherits = \"loop.toml\"\n").unwrap();
+
+        let tables = read_tables(&dir.join("bar.toml"), &mut Vec::new()).unwrap();
+        let profile = Profile::from_tables(&tables, None).unwrap();
+        let looped = read_tables(&dir.join("loop.toml"), &mut Vec::new());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(profile.model.dialect, Dialect::EscPos);
+        assert!(!profile.model.capabilities.has_cutter);
+        assert_eq!(profile.model.density, Some(2));
+        assert_eq!(profile.spacing.list.before, 2);
+        assert_eq!(profile.spacing.list.after, 1);
+        assert!(looped.is_err());
+    }
+}
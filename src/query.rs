@@ -0,0 +1,94 @@
+use std::io::{Read, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use mdtoepson::paper::Paper;
+
+/// How long a printer gets to answer before we give up on it
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// `GS ( E` function 6: transmit customized setting 3, the paper width.
+const PAPER_WIDTH: &[u8] = b"\x1D(E\x02\x00\x06\x03";
+
+/// Asks an ESC/POS printer which roll it's set up for. `reader` is the same
+/// connection as `writer`, cloned, since the reply may never come.
+pub fn paper_width(
+    mut writer: impl Write,
+    reader: impl Read + Send + 'static,
+) -> Result<Paper, String> {
+    writer
+        .write_all(PAPER_WIDTH)
+        .and_then(|_| writer.flush())
+        .map_err(|e| format!("could not ask the printer: {}", e))?;
+
+    let reply = reply(reader)?;
+    match setting(&reply) {
+        Some(2) => Ok(Paper::Mm58),
+        Some(6) => Ok(Paper::Mm80),
+        Some(n) => Err(format!("the printer reports paper width setting {}", n)),
+        None => Err(String::from("the printer's reply was not a setting")),
+    }
+}
+
+/// Reads a reply up to its closing NUL.
+fn reply(mut reader: impl Read + Send + 'static) -> Result<Vec<u8>, String> {
+    let (send, receive) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reply = Vec::new();
+        let mut byte = [0];
+        while let Ok(1) = reader.read(&mut byte) {
+            if byte[0] == 0 {
+                break;
+            }
+            reply.push(byte[0]);
+        }
+        let _ = send.send(reply);
+    });
+
+    receive
+        .recv_timeout(TIMEOUT)
+        .map_err(|_| format!("the printer didn't answer within {:?}", TIMEOUT))
+}
+
+/// Value in a customized setting reply: header `0x37 0x27`, the setting
+/// number, `0x1F`, then the value in ASCII digits.
+fn setting(reply: &[u8]) -> Option<u32> {
+    let start = reply.windows(2).position(|w| w == [0x37, 0x27])?;
+    let separator = reply[start..].iter().position(|&b| b == 0x1F)?;
+    let value = &reply[start + separator + 1..];
+
+    std::str::from_utf8(value).ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn settings_are_read_from_the_reply() {
+        assert_eq!(setting(b"\x37\x273\x1F6"), Some(6));
+        assert_eq!(setting(b"\x00\x37\x27\x033\x1F2"), Some(2));
+        assert_eq!(setting(b"\x37\x273"), None);
+    }
+
+    #[test]
+    fn printers_are_asked_over_their_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let printer = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut query = [0; 7];
+            stream.read_exact(&mut query).unwrap();
+            stream.write_all(b"\x37\x273\x1F2\x00").unwrap();
+            query
+        });
+
+        let stream = TcpStream::connect(address).unwrap();
+        let reader = stream.try_clone().unwrap();
+
+        assert_eq!(paper_width(stream, reader), Ok(Paper::Mm58));
+        assert_eq!(printer.join().unwrap(), PAPER_WIDTH);
+    }
+}
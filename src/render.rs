@@ -0,0 +1,2142 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+
+use crate::barcode::{SymbolOptions, Symbology};
+use crate::dialect::{Capabilities, Dialect, Font};
+use crate::events::{Event, Events, Fence, Style};
+use crate::glyphs::{self, Glyphs};
+use crate::highlight::{self, Class};
+use crate::image::{Gray, ImageOptions};
+use crate::lists::{self, ListStyle};
+use crate::paginate::{self, Footer};
+use crate::paper::Layout;
+use crate::spacing::{self, Spacing};
+use crate::stats::Stats;
+use crate::table::{self, Frame, Row};
+use crate::{template, toml, transliterate};
+
+macro_rules! def_wrap_env {
+    ($name:ident, $fname:ident, $pre:ident, $post:ident) => {
+        fn $name(state: &mut State) -> &'static [u8] {
+            let sequences = state.dialect.sequences();
+            let res = if !state.$fname {
+                sequences.$pre
+            } else {
+                sequences.$post
+            };
+            state.$fname = !state.$fname;
+            return res;
+        }
+    };
+}
+macro_rules! def_open_env {
+    ($name:ident, $fname:ident, $pre:ident) => {
+        fn $name(state: &mut State) -> &'static [u8] {
+            if !state.$fname {
+                state.$fname = true;
+                state.dialect.sequences().$pre
+            } else {
+                &[]
+            }
+        }
+    };
+}
+macro_rules! def_close_env {
+    ($name:ident, $fname:ident, $post:ident) => {
+        fn $name(state: &mut State) -> &'static [u8] {
+            if !state.$fname {
+                &[]
+            } else {
+                state.$fname = false;
+                state.dialect.sequences().$post
+            }
+        }
+    };
+}
+
+/// Letter case headings are printed in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(clap::ValueEnum))]
+pub enum HeadingCase {
+    /// As written
+    #[default]
+    None,
+    /// ALL CAPITALS, the usual style for receipt headers
+    Upper,
+    /// First Letter Of Every Word Capitalized
+    Title,
+}
+
+/// Everything that shapes the rendered bytes besides the markdown itself.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    pub dialect: Dialect,
+    pub layout: Layout,
+    pub image: ImageOptions,
+    /// Directory that relative image paths are resolved against
+    #[cfg(feature = "std")]
+    pub base_dir: Option<PathBuf>,
+    /// Style code blocks in languages we can classify
+    pub highlight: bool,
+    pub line_numbers: bool,
+    /// Draw frames around tables and code blocks without line numbers
+    pub frames: bool,
+    /// Break lines wherever the source does, for poetry and addresses
+    pub keep_linebreaks: bool,
+    pub lists: ListStyle,
+    /// Blank lines around blocks, unless the document's front matter changes them
+    pub spacing: Spacing,
+    pub heading_case: HeadingCase,
+    /// Print headings in bold on top of their usual style
+    pub bold_headings: bool,
+    /// Styles printed for markdown's emphasis
+    pub styles: StyleMap,
+    /// Footer printed at the bottom of every page, when paginating
+    pub page_footer: Option<String>,
+    /// Heading level that starts a new ticket
+    pub cut_level: Option<usize>,
+    /// Initialize the printer first and reset its styles at the end
+    pub init: bool,
+    /// Follows another document sent to the printer in the same job, which
+    /// already set it up, so only the document itself is sent
+    pub continued: bool,
+    /// Print darkness, from -6 to 6, for faded thermal heads
+    pub density: Option<i8>,
+    /// Blank lines fed after the last line, so it clears the tear bar
+    pub feed_after: usize,
+    /// Print on labels, feeding to the next one wherever the paper would be cut
+    pub labels: bool,
+    /// Characters downloaded to the printer for ones its codepage lacks
+    pub glyphs: Glyphs,
+    /// Spell characters no codepage has in ASCII instead of printing `?`
+    pub transliterate: bool,
+    /// Send control characters in the text as they are, where they can
+    /// reprogram the printer
+    pub allow_raw: bool,
+    /// What the printer supports, when it differs from the dialect's usual
+    pub capabilities: Option<Capabilities>,
+}
+
+impl Options {
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+            .unwrap_or_else(|| self.dialect.capabilities())
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    top_header: bool,
+    lower_header: bool,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    bold_at: usize,
+    italic_at: usize,
+    underline_at: usize,
+    diagnostics: Vec<Diagnostic>,
+    dialect: Dialect,
+    capabilities: Capabilities,
+    glyphs: Glyphs,
+    /// Index of the selected codepage in the dialect's list
+    codepage: usize,
+    transliterate: bool,
+    allow_raw: bool,
+    /// Control characters dropped from the text
+    stripped: usize,
+    keep_linebreaks: bool,
+    width: Option<usize>,
+    column: usize,
+    /// Byte offset of the last breakable space on this line and the column after it
+    last_space: Option<(usize, usize)>,
+    /// Section numbers of headings tagged `{#id}`, by id
+    anchors: BTreeMap<String, String>,
+    /// Abbreviations and what they stand for, in the order they're defined
+    abbreviations: Vec<(String, String)>,
+    /// Source indents of the enclosing list items
+    list_indents: Vec<usize>,
+    /// Number last given at each nesting level, where that item was numbered
+    list_numbers: Vec<Option<usize>>,
+    in_list: bool,
+    spacing: Spacing,
+    /// Borders for tables and code blocks, when they're framed
+    frame: Option<Frame>,
+    /// Character to underline the open heading with, on printers that can't
+    /// print it taller
+    heading_rule: Option<char>,
+    /// The open heading was made bold by `bold_headings`
+    heading_bold: bool,
+    /// Break the line at the next newline even if it's a single one
+    line_ends: bool,
+    heading_case: HeadingCase,
+    /// The last heading character was part of a word, for title case
+    in_word: bool,
+    /// Byte offsets of every line feed ending a line of text, for pagination
+    breaks: Vec<usize>,
+    /// Byte offsets of the line feeds between image bands, where pages can
+    /// also end
+    bands: Vec<usize>,
+    /// Height of printed images, in dots
+    image_dots: usize,
+    labels: bool,
+    label_height: Option<usize>,
+    /// Lines and image dots printed before the current label
+    label_start: (usize, usize),
+    /// Labels with more on them than fits
+    overfull_labels: usize,
+}
+
+/// A warning about the markdown source, positioned at 1-based line and column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn at(input: &str, offset: usize, message: impl Into<String>) -> Self {
+        let before = &input[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let column = before[line_start..].chars().count() + 1;
+
+        Diagnostic {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// What each kind of emphasis prints as, for printers that garble a style.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StyleMap {
+    /// Style printed for `**bold**`, or none to print it plain
+    pub bold: Option<Style>,
+    pub italic: Option<Style>,
+    pub underline: Option<Style>,
+}
+
+impl Default for StyleMap {
+    fn default() -> Self {
+        StyleMap {
+            bold: Some(Style::Bold),
+            italic: Some(Style::Italic),
+            underline: Some(Style::Underline),
+        }
+    }
+}
+
+impl StyleMap {
+    /// Prints `from` as `to`, or plain when `to` is `None`.
+    pub fn set(&mut self, from: Style, to: Option<Style>) {
+        match from {
+            Style::Bold => self.bold = to,
+            Style::Italic => self.italic = to,
+            Style::Underline => self.underline = to,
+        }
+    }
+
+    /// Applies a mapping like `italic=underline` or `italic=none`.
+    pub fn parse_mapping(&mut self, mapping: &str) -> Result<(), String> {
+        let style = |name: &str| match name.trim().to_ascii_lowercase().as_str() {
+            "bold" => Ok(Some(Style::Bold)),
+            "italic" => Ok(Some(Style::Italic)),
+            "underline" => Ok(Some(Style::Underline)),
+            "none" => Ok(None),
+            _ => Err(format!(
+                "{} is not bold, italic, underline or none",
+                name.trim()
+            )),
+        };
+        let Some((from, to)) = mapping.split_once('=') else {
+            return Err(format!(
+                "{} is not a mapping like italic=underline",
+                mapping
+            ));
+        };
+        let from = style(from)?.ok_or("none can't be mapped to a style")?;
+        self.set(from, style(to)?);
+
+        Ok(())
+    }
+
+    /// The event a style change prints as, if any.
+    fn map<'a>(&self, event: Event<'a>) -> Option<Event<'a>> {
+        let get = |style| match style {
+            Style::Bold => self.bold,
+            Style::Italic => self.italic,
+            Style::Underline => self.underline,
+        };
+        match event {
+            Event::StyleOn(style) => get(style).map(Event::StyleOn),
+            Event::StyleOff(style) => get(style).map(Event::StyleOff),
+            event => Some(event),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Rendered {
+    pub bytes: Vec<u8>,
+    pub diagnostics: Vec<Diagnostic>,
+    pub stats: Stats,
+}
+
+def_wrap_env!(wrap_bold, bold, bold_on, bold_off);
+def_wrap_env!(wrap_italic, italic, italic_on, italic_off);
+def_wrap_env!(wrap_underline, underline, underline_on, underline_off);
+def_open_env!(open_top_header, top_header, top_header_on);
+def_close_env!(close_top_header, top_header, top_header_off);
+def_open_env!(open_lower_header, lower_header, lower_header_on);
+def_close_env!(close_lower_header, lower_header, lower_header_off);
+
+/// Renders markdown as ESC/P with the default options.
+pub fn transpile_markdown(input: &str) -> Vec<u8> {
+    render_markdown(input, &Options::default()).bytes
+}
+
+/// Whether a document would print nothing but blank lines, having only
+/// whitespace, comments and tags.
+pub fn is_blank(input: &str) -> bool {
+    let start = spacing::front_matter(input).map_or(0, |(_, start)| start);
+
+    Events::starting_at(input, start).all(|event| match event {
+        Event::Text(text) | Event::Malformed(text) => text.trim().is_empty(),
+        Event::SoftBreak
+        | Event::HardBreak
+        | Event::Comment(_)
+        | Event::Tag(_)
+        | Event::Abbreviation { .. } => true,
+        _ => false,
+    })
+}
+
+/// Commands setting the printer up for a job: initializing it when `init`
+/// is on, then its density, layout and downloaded glyphs.
+pub fn setup(options: &Options) -> Vec<u8> {
+    let mut res = Vec::new();
+    if options.init {
+        res.extend_from_slice(options.dialect.sequences().init);
+    }
+    if let Some(density) = options.density {
+        res.extend_from_slice(&options.dialect.density(density));
+    }
+    res.extend_from_slice(&options.layout.setup(options.dialect));
+    res.extend_from_slice(&options.glyphs.define(options.dialect));
+
+    res
+}
+
+pub fn render_markdown(input: &str, options: &Options) -> Rendered {
+    let (front_matter, body_start) = spacing::front_matter(input).unwrap_or(("", 0));
+    let mut events = Events::starting_at(input, body_start);
+    let capabilities = options.capabilities();
+    let mut state = State {
+        dialect: options.dialect,
+        capabilities,
+        glyphs: options.glyphs.clone(),
+        transliterate: options.transliterate,
+        allow_raw: options.allow_raw,
+        keep_linebreaks: options.keep_linebreaks,
+        heading_case: options.heading_case,
+        labels: options.labels,
+        label_height: options.layout.label_height,
+        width: options.layout.text_width(),
+        anchors: heading_anchors(input, body_start),
+        abbreviations: abbreviations(input, body_start),
+        spacing: options.spacing,
+        frame: options
+            .frames
+            .then_some(match capabilities.has_box_drawing {
+                true => table::BOX,
+                false => table::ASCII,
+            }),
+        ..Default::default()
+    };
+    let spacing_table = toml::parse(front_matter).and_then(|tables| {
+        match tables.iter().find(|table| table.name == "spacing") {
+            Some(table) => state.spacing.apply(table),
+            None => Ok(()),
+        }
+    });
+    if let Err(e) = spacing_table {
+        let message = format!("front matter: {}", e);
+        state.diagnostics.push(Diagnostic::at(input, 0, message));
+    }
+    if let (Some(width), Some(max)) = (state.width, capabilities.max_width) {
+        if width > max {
+            state.diagnostics.push(Diagnostic::at(
+                input,
+                0,
+                format!(
+                    "lines are {} columns but the printer prints at most {}",
+                    width, max
+                ),
+            ));
+            state.width = Some(max);
+        }
+    }
+
+    let mut res = match options.continued {
+        true => Vec::new(),
+        false => setup(options),
+    };
+    let setup_len = res.len();
+    while let Some(event) = events.next() {
+        let start = events.span().start;
+        let Some(event) = options.styles.map(event) else {
+            continue;
+        };
+        if state.in_list && !matches!(event, Event::ListItem { .. }) {
+            state.in_list = false;
+            state.list_numbers.clear();
+            let blank = state.spacing.list.after;
+            push_feeds(&mut state, &mut res, blank);
+        }
+        match event {
+            Event::StyleOn(Style::Bold) | Event::StyleOff(Style::Bold) => {
+                res.extend_from_slice(wrap_bold(&mut state));
+                state.bold_at = start;
+            }
+            Event::StyleOn(Style::Italic) | Event::StyleOff(Style::Italic) => {
+                res.extend_from_slice(wrap_italic(&mut state));
+                state.italic_at = start;
+            }
+            Event::StyleOn(Style::Underline) | Event::StyleOff(Style::Underline) => {
+                res.extend_from_slice(wrap_underline(&mut state));
+                state.underline_at = start;
+            }
+            Event::Heading(1) => {
+                if options.cut_level == Some(1) && res.len() > setup_len {
+                    push_cut(&mut state, &mut res);
+                }
+                state.heading_rule = heading_rule(&state, 1);
+                state.in_word = false;
+                let before = state.spacing.top_heading.before;
+                push_feeds(&mut state, &mut res, before + 1);
+                let size = open_top_header(&mut state);
+                if state.heading_rule.is_none() {
+                    res.extend_from_slice(size);
+                }
+                res.extend_from_slice(force_bold(&mut state, options));
+            }
+            // TODO: lower header formatting (font size)
+            Event::Heading(level) => {
+                if options.cut_level == Some(level) && res.len() > setup_len {
+                    push_cut(&mut state, &mut res);
+                }
+                state.heading_rule = heading_rule(&state, level);
+                state.in_word = false;
+                let before = state.spacing.heading.before;
+                push_feeds(&mut state, &mut res, before + 1);
+                let size = open_lower_header(&mut state);
+                if state.heading_rule.is_none() {
+                    res.extend_from_slice(size);
+                }
+                res.extend_from_slice(force_bold(&mut state, options));
+            }
+            Event::SoftBreak | Event::HardBreak => new_line(&mut state, &mut res, event),
+            Event::Tag(_) | Event::Comment(_) | Event::Abbreviation { .. } => {}
+            Event::Malformed(text) => {
+                let message = format!("malformed markup {:?}; printed as written", text.trim());
+                state
+                    .diagnostics
+                    .push(Diagnostic::at(input, start, message));
+                push_text(&mut state, &mut res, text);
+            }
+            Event::CodeBlock(fence) => {
+                push_codeblock(&mut state, &mut res, input, start, &fence, options)
+            }
+            Event::Link { text, target } if target.starts_with('#') => {
+                match state.anchors.get(&target[1..]) {
+                    Some(number) => {
+                        let reference = format!("{} (\u{a7}{})", text, number);
+                        push_text(&mut state, &mut res, &reference);
+                    }
+                    None => {
+                        let message = format!("unknown cross-reference {}", target);
+                        state
+                            .diagnostics
+                            .push(Diagnostic::at(input, start, message));
+                        push_text(&mut state, &mut res, text);
+                    }
+                }
+            }
+            Event::Link { text, target } if state.dialect.strips_markup() => {
+                push_text(&mut state, &mut res, &format!("{} ({})", text, target));
+            }
+            Event::Link { .. } => push_text(&mut state, &mut res, &input[events.span()]),
+            Event::Cut => push_cut(&mut state, &mut res),
+            Event::Beep => res.extend_from_slice(state.dialect.sequences().beep),
+            Event::ListItem {
+                indent,
+                number,
+                text,
+            } => push_list_item(&mut state, &mut res, indent, number, text, options),
+            Event::Image { alt, target } => {
+                push_image(&mut state, &mut res, input, start, alt, target, options)
+            }
+            Event::Leader(value) => push_leader(&mut state, &mut res, value),
+            Event::Superscript(text) => push_script(&mut state, &mut res, text, true, options),
+            Event::Subscript(text) => push_script(&mut state, &mut res, text, false, options),
+            Event::Text(text) if state.top_header || state.lower_header => {
+                let text = heading_case(&mut state, text);
+                push_marked(&mut state, &mut res, &text);
+            }
+            Event::Text(text) => push_marked(&mut state, &mut res, text),
+        }
+    }
+
+    // Reset any style left open so it can't leak into the next job
+    if state.bold {
+        let message = "unclosed bold";
+        state
+            .diagnostics
+            .push(Diagnostic::at(input, state.bold_at, message));
+        res.extend_from_slice(wrap_bold(&mut state));
+    }
+    if state.italic {
+        let message = "unclosed italic";
+        state
+            .diagnostics
+            .push(Diagnostic::at(input, state.italic_at, message));
+        res.extend_from_slice(wrap_italic(&mut state));
+    }
+    if state.underline {
+        let message = "unclosed underline";
+        state
+            .diagnostics
+            .push(Diagnostic::at(input, state.underline_at, message));
+        res.extend_from_slice(wrap_underline(&mut state));
+    }
+    if state.stripped > 0 {
+        let message = format!(
+            "removed {} control characters; use --allow-raw to send them to the printer",
+            state.stripped
+        );
+        state.diagnostics.push(Diagnostic::at(input, 0, message));
+    }
+    close_headers(&mut state, &mut res);
+    if !state.abbreviations.is_empty() {
+        push_glossary(&mut state, &mut res);
+    }
+
+    if res.last() != Some(&b'\n') {
+        push_feeds(&mut state, &mut res, 1);
+    }
+    push_feeds(&mut state, &mut res, options.feed_after);
+    end_label(&mut state);
+    if let (overfull @ 1.., Some(height)) = (state.overfull_labels, state.label_height) {
+        let message = format!(
+            "{} of the labels run past their {} mm and onto the next",
+            overfull, height
+        );
+        state.diagnostics.push(Diagnostic::at(input, 0, message));
+    }
+    if state.codepage != 0 {
+        res.extend_from_slice(options.dialect.sequences().codepages[0].1);
+    }
+
+    if let (Some(template), Some(page_length)) = (&options.page_footer, options.layout.page_length)
+    {
+        let mut breaks = [state.breaks.as_slice(), &state.bands].concat();
+        breaks.sort_unstable();
+        let footer = Footer {
+            template,
+            width: options.layout.text_width(),
+            page_length,
+        };
+        res = paginate::paginate(&res, &breaks, &footer);
+    }
+    if options.init {
+        res.extend_from_slice(options.dialect.sequences().reset);
+    }
+
+    let stats = Stats {
+        lines: state.breaks.len(),
+        image_dots: state.image_dots,
+        dialect: options.dialect,
+        page_length: options.layout.page_length,
+    };
+
+    Rendered {
+        bytes: res,
+        diagnostics: state.diagnostics,
+        stats,
+    }
+}
+
+/// Ends the line with `count` line feeds, counting each for pagination.
+fn push_feeds(state: &mut State, res: &mut Vec<u8>, count: usize) {
+    for _ in 0..count {
+        state.breaks.push(res.len());
+        res.push(b'\n');
+    }
+    if count > 0 {
+        state.column = 0;
+        state.last_space = None;
+    }
+}
+
+/// Feeds the paper past the cutter and cuts it, on a line of its own, or
+/// feeds it out to be torn off when there's no cutter.
+fn push_cut(state: &mut State, res: &mut Vec<u8>) {
+    if state.column > 0 {
+        push_feeds(state, res, 1);
+    }
+    let sequences = state.dialect.sequences();
+    if state.labels {
+        end_label(state);
+        res.extend_from_slice(sequences.next_label);
+        return;
+    }
+    match state.capabilities.has_cutter && !sequences.cut.is_empty() {
+        true => res.extend_from_slice(sequences.cut),
+        false => res.extend_from_slice(sequences.eject),
+    }
+}
+
+/// Counts the label just finished if it's taller than the labels are.
+fn end_label(state: &mut State) {
+    let (lines, image_dots) = state.label_start;
+    let printed = Stats {
+        lines: state.breaks.len() - lines,
+        image_dots: state.image_dots - image_dots,
+        dialect: state.dialect,
+        page_length: None,
+    };
+    if state
+        .label_height
+        .is_some_and(|height| printed.paper_mm() > height as f64)
+    {
+        state.overfull_labels += 1;
+    }
+    state.label_start = (state.breaks.len(), state.image_dots);
+}
+
+/// Appends printable text, breaking at the last space once the line overflows.
+fn push_text(state: &mut State, res: &mut Vec<u8>, text: &str) {
+    let scale = if state.top_header {
+        state.dialect.sequences().top_header_scale
+    } else {
+        1
+    };
+
+    for c in text.chars() {
+        match c {
+            '\n' => {
+                state.breaks.push(res.len());
+                res.push(b'\n');
+                state.column = 0;
+                state.last_space = None;
+                continue;
+            }
+            ' ' => {
+                res.push(b' ');
+                state.column += scale;
+                state.last_space = Some((res.len() - 1, state.column));
+            }
+            _ => state.column += scale * push_char(state, res, c),
+        }
+
+        if let Some(width) = state.width {
+            if state.column > width {
+                if let Some((at, column)) = state.last_space.take() {
+                    res[at] = b'\n';
+                    state.breaks.push(at);
+                    state.column -= column;
+                }
+            }
+        }
+    }
+}
+
+/// Fills the line with dots up to a value printed flush right, ending the
+/// line after it. Without a width the dots are left as they are.
+fn push_leader(state: &mut State, res: &mut Vec<u8>, value: &str) {
+    let dots = match state.width {
+        Some(width) => width
+            .saturating_sub(state.column + value.chars().count() + 2)
+            .max(3),
+        None => 4,
+    };
+    push_text(state, res, &format!(" {} {}", ".".repeat(dots), value));
+    state.line_ends = true;
+}
+
+/// Prints raised or lowered text, or writes it out as `x^2` and `H_2O` on
+/// printers that can't.
+fn push_script(
+    state: &mut State,
+    res: &mut Vec<u8>,
+    text: &str,
+    superscript: bool,
+    options: &Options,
+) {
+    if !state.capabilities.has_scripts {
+        let marker = if superscript { "^" } else { "_" };
+        push_text(state, res, &format!("{}{}", marker, text));
+        return;
+    }
+
+    let sequences = state.dialect.sequences();
+    let (on, off) = match superscript {
+        true => (sequences.superscript_on, sequences.superscript_off),
+        false => (sequences.subscript_on, sequences.subscript_off),
+    };
+    res.extend_from_slice(on);
+    push_text(state, res, text);
+    res.extend_from_slice(off);
+    // Smaller characters are Font B, so switching back loses a Font B layout
+    if options.layout.font == Some(Font::B) {
+        res.extend_from_slice(state.dialect.font(Font::B));
+    }
+}
+
+/// Appends text with the abbreviations in it underlined.
+fn push_marked(state: &mut State, res: &mut Vec<u8>, text: &str) {
+    if state.underline {
+        return push_text(state, res, text);
+    }
+
+    let mut rest = text;
+    while let Some((at, len)) = find_abbreviation(&state.abbreviations, rest) {
+        push_text(state, res, &rest[..at]);
+        let sequences = state.dialect.sequences();
+        res.extend_from_slice(sequences.underline_on);
+        push_text(state, res, &rest[at..at + len]);
+        res.extend_from_slice(sequences.underline_off);
+        rest = &rest[at + len..];
+    }
+    push_text(state, res, rest);
+}
+
+/// Byte offset and length of the first abbreviation in `text` that stands
+/// as a word of its own.
+fn find_abbreviation(abbreviations: &[(String, String)], text: &str) -> Option<(usize, usize)> {
+    let word_edge = |c: Option<char>| !c.is_some_and(char::is_alphanumeric);
+
+    abbreviations
+        .iter()
+        .filter_map(|(abbreviation, _)| {
+            text.match_indices(abbreviation.as_str())
+                .find(|&(at, found)| {
+                    word_edge(text[..at].chars().next_back())
+                        && word_edge(text[at + found.len()..].chars().next())
+                })
+                .map(|(at, found)| (at, found.len()))
+        })
+        .min()
+}
+
+/// Lists the abbreviations after the document, each with what it stands for.
+fn push_glossary(state: &mut State, res: &mut Vec<u8>) {
+    if state.column > 0 {
+        push_feeds(state, res, 1);
+    }
+    push_feeds(state, res, 1);
+
+    let sequences = state.dialect.sequences();
+    for (abbreviation, expansion) in core::mem::take(&mut state.abbreviations) {
+        res.extend_from_slice(sequences.underline_on);
+        push_text(state, res, &abbreviation);
+        res.extend_from_slice(sequences.underline_off);
+        push_text(state, res, &format!(": {}", expansion));
+        push_feeds(state, res, 1);
+    }
+}
+
+/// Appends preformatted text that must not be rewrapped.
+fn push_verbatim(state: &mut State, res: &mut Vec<u8>, text: &str) {
+    for c in text.chars() {
+        if c == '\n' {
+            state.breaks.push(res.len());
+            push_char(state, res, c);
+            state.column = 0;
+            state.last_space = None;
+        } else {
+            state.column += push_char(state, res, c);
+        }
+    }
+}
+
+/// Prints a list item on a line of its own, indented for its nesting level
+/// and with that level's bullet in place of the markdown marker. Numbered
+/// items count up from the first one's number, as markdown has it.
+fn push_list_item(
+    state: &mut State,
+    res: &mut Vec<u8>,
+    indent: usize,
+    number: Option<usize>,
+    text: &str,
+    options: &Options,
+) {
+    let level = lists::level(&mut state.list_indents, indent);
+    state.list_numbers.resize(level + 1, None);
+    let number = number.map(|written| match state.list_numbers[level] {
+        Some(last) => last + 1,
+        None => written,
+    });
+    state.list_numbers[level] = number;
+
+    if state.column > 0 {
+        push_feeds(state, res, 1);
+    }
+    if !state.in_list {
+        state.in_list = true;
+        push_feeds(state, res, state.spacing.list.before);
+    }
+    let style = &options.lists;
+    let marker = match number {
+        Some(number) => style.numbering.unwrap_or_default().marker(number),
+        None => style.bullet(level).to_string(),
+    };
+    let bullet = format!("{}{} ", " ".repeat(level * style.indent), marker);
+    push_text(state, res, &bullet);
+    push_text(state, res, text);
+    push_text(state, res, "\n");
+}
+
+/// Prints a referenced image on its own lines, or its alt text where it can't be.
+fn push_image(
+    state: &mut State,
+    res: &mut Vec<u8>,
+    input: &str,
+    start: usize,
+    alt: &str,
+    target: &str,
+    options: &Options,
+) {
+    if state.dialect.strips_markup() {
+        push_text(state, res, &format!("[image: {}]", alt));
+        return;
+    }
+
+    let loaded = match Gray::builtin(target) {
+        Some(image) => Ok(image),
+        None => load_image(target, options),
+    };
+    let mut image = match loaded {
+        Ok(image) => image.fit(state.dialect.image_width(options.layout.text_width())),
+        Err(message) => {
+            state
+                .diagnostics
+                .push(Diagnostic::at(input, start, message));
+            push_text(state, res, &format!("[image: {}]", alt));
+            return;
+        }
+    };
+    image.adjust(options.image.brightness, options.image.contrast);
+
+    if state.column > 0 {
+        push_feeds(state, res, 1);
+    }
+    let bitmap = image.dither(options.image.dither);
+    state.image_dots += bitmap.height;
+    let feeds = bitmap.feeds(state.dialect);
+    state.bands.extend(feeds.iter().map(|at| res.len() + at));
+    res.append(&mut bitmap.encode(state.dialect));
+    state.column = 0;
+    state.last_space = None;
+}
+
+/// Reads an image file, relative to the document's directory.
+#[cfg(feature = "std")]
+fn load_image(target: &str, options: &Options) -> Result<Gray, String> {
+    let path = match &options.base_dir {
+        Some(dir) => dir.join(target),
+        None => PathBuf::from(target),
+    };
+
+    Gray::load(&path).map_err(|e| format!("could not load image {}: {}", path.display(), e))
+}
+
+#[cfg(not(feature = "std"))]
+fn load_image(target: &str, _options: &Options) -> Result<Gray, String> {
+    Err(format!("could not load image {}: no file system", target))
+}
+
+/// Appends a character in the dialect's encoding, switching to another
+/// codepage when the current one lacks it, then drawing it from a
+/// user-defined glyph, then spelling it in ASCII if asked to, then printing
+/// `?`. Control characters other than line feeds and tabs are dropped unless
+/// raw output is allowed. Returns the columns it took.
+fn push_char(state: &mut State, res: &mut Vec<u8>, c: char) -> usize {
+    if c.is_control() && !matches!(c, '\n' | '\t') && !state.allow_raw {
+        // Carriage returns from CRLF files aren't worth a warning
+        if c != '\r' {
+            state.stripped += 1;
+        }
+        return 0;
+    }
+    if let Some(escaped) = state.dialect.escape(c) {
+        res.extend_from_slice(escaped.as_bytes());
+        return 1;
+    }
+
+    let codepages = state.dialect.sequences().codepages;
+    if codepages.is_empty() {
+        res.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        return 1;
+    }
+    if let Some(byte) = codepages[state.codepage].0.encode(c) {
+        res.push(byte);
+        return 1;
+    }
+
+    let other = codepages
+        .iter()
+        .enumerate()
+        .find_map(|(i, (codepage, select))| Some((i, select, codepage.encode(c)?)));
+    let ascii = state
+        .transliterate
+        .then(|| transliterate::transliterate(c))
+        .flatten();
+    match (other, state.glyphs.code(c), ascii) {
+        (Some((i, select, byte)), _, _) => {
+            res.extend_from_slice(select);
+            res.push(byte);
+            state.codepage = i;
+        }
+        (None, Some(code), _) => res.extend_from_slice(&glyphs::print(code)),
+        (None, None, Some(ascii)) => {
+            res.extend_from_slice(ascii.as_bytes());
+            return ascii.len();
+        }
+        (None, None, None) => res.push(b'?'),
+    }
+
+    1
+}
+
+/// Prints a code block, its fences only where the dialect keeps markup.
+fn push_codeblock(
+    state: &mut State,
+    res: &mut Vec<u8>,
+    input: &str,
+    start: usize,
+    fence: &Fence,
+    options: &Options,
+) {
+    if let Some(symbology) = Symbology::from_language(fence.language) {
+        let symbol = SymbolOptions::parse(fence.attributes)
+            .and_then(|symbol| symbology.validate(&symbol).map(|_| symbol));
+        let symbol = symbol.unwrap_or_else(|message| {
+            let message = format!("{} attributes: {}", fence.language, message);
+            state
+                .diagnostics
+                .push(Diagnostic::at(input, start, message));
+            SymbolOptions::default()
+        });
+        let data = fence.body.trim();
+        return match symbology.check(data) {
+            Ok(encoded) => push_symbol(state, res, symbology, &symbol, data, &encoded),
+            Err(message) => {
+                // A symbol scanners can't read is worse than none
+                let message = format!("{}: {}; printed as text instead", fence.language, message);
+                state
+                    .diagnostics
+                    .push(Diagnostic::at(input, start, message));
+                push_symbol(state, res, symbology, &symbol, data, "")
+            }
+        };
+    }
+    if fence.language.eq_ignore_ascii_case("csv") {
+        return push_table(state, res, input, start, fence.body);
+    }
+    let strip = state.dialect.strips_markup();
+    let (before, after) = (state.spacing.code.before, state.spacing.code.after);
+    let spans = |body| {
+        match options.highlight {
+            true => highlight::highlight(fence.language, body),
+            false => None,
+        }
+        .unwrap_or_else(|| vec![(Class::Plain, body)])
+    };
+
+    if let Some(frame) = state.frame.filter(|_| !options.line_numbers) {
+        if state.column > 0 {
+            push_feeds(state, res, 1);
+        }
+        push_feeds(state, res, before);
+        let body = fence.body.strip_suffix('\n').unwrap_or(fence.body);
+        push_framed(state, res, &spans(body), body, &frame);
+        push_feeds(state, res, after);
+        return;
+    }
+
+    if strip {
+        push_feeds(state, res, 1);
+        push_feeds(state, res, before);
+    } else {
+        // Blank lines go after the newline that ends the line before
+        let head = fence.head.strip_prefix('\n');
+        if head.is_some() {
+            push_verbatim(state, res, "\n");
+        }
+        push_feeds(state, res, before);
+        push_verbatim(state, res, head.unwrap_or(fence.head));
+    }
+
+    let body = match strip {
+        true => fence.body.strip_suffix('\n').unwrap_or(fence.body),
+        false => fence.body,
+    };
+    let spans = spans(body);
+
+    if options.line_numbers {
+        push_numbered(state, res, &spans, body.lines().count(), options);
+    } else {
+        for (class, text) in spans {
+            let (on, off) = class_style(state, class);
+            res.extend_from_slice(on);
+            push_verbatim(state, res, text);
+            res.extend_from_slice(off);
+        }
+    }
+
+    if strip {
+        push_feeds(state, res, 1);
+    } else {
+        push_verbatim(state, res, fence.tail);
+    }
+    push_feeds(state, res, after);
+}
+
+/// Prints code inside a frame whose right edge is after the longest line, or
+/// at the end of the line when that's narrower. Longer lines push it out.
+fn push_framed(
+    state: &mut State,
+    res: &mut Vec<u8>,
+    spans: &[(Class, &str)],
+    body: &str,
+    frame: &Frame,
+) {
+    let longest = body.lines().map(|line| line.chars().count()).max();
+    let inner = match state.width {
+        Some(width) => longest.unwrap_or(0).min(width.saturating_sub(2)),
+        None => longest.unwrap_or(0),
+    };
+    let edge = frame.vertical.to_string();
+    let end_line = |state: &mut State, res: &mut Vec<u8>| {
+        let padding = (inner + 1).saturating_sub(state.column);
+        push_verbatim(state, res, &format!("{}{}\n", " ".repeat(padding), edge));
+    };
+
+    push_verbatim(state, res, &frame.rule(frame.top, &[inner]));
+    push_verbatim(state, res, "\n");
+    push_verbatim(state, res, &edge);
+    for (class, text) in spans {
+        let (on, off) = class_style(state, *class);
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                end_line(state, res);
+                push_verbatim(state, res, &edge);
+            }
+            res.extend_from_slice(on);
+            push_verbatim(state, res, line);
+            res.extend_from_slice(off);
+        }
+    }
+    end_line(state, res);
+    push_verbatim(state, res, &frame.rule(frame.bottom, &[inner]));
+    push_verbatim(state, res, "\n");
+}
+
+/// Prints code with each line prefixed by its number in Font B, wrapping
+/// long lines so they continue under the code rather than the numbers.
+fn push_numbered(
+    state: &mut State,
+    res: &mut Vec<u8>,
+    spans: &[(Class, &str)],
+    lines: usize,
+    options: &Options,
+) {
+    let digits = lines.max(1).to_string().len();
+    let restore_font = state.dialect.font(options.layout.font.unwrap_or(Font::A));
+    let gutter = |state: &mut State, res: &mut Vec<u8>, number: Option<usize>| {
+        res.extend_from_slice(state.dialect.font(Font::B));
+        let label = number.map_or(String::new(), |n| n.to_string());
+        push_verbatim(state, res, &format!("{:>digits$} ", label));
+        res.extend_from_slice(restore_font);
+    };
+
+    let mut number = 0;
+    let mut line_start = true;
+    for (class, text) in spans {
+        let (on, off) = class_style(state, *class);
+        res.extend_from_slice(on);
+        for c in text.chars() {
+            if line_start {
+                number += 1;
+                gutter(state, res, Some(number));
+                line_start = false;
+            }
+            if c == '\n' {
+                push_feeds(state, res, 1);
+                line_start = true;
+                continue;
+            }
+            if state.width.is_some_and(|width| state.column >= width) {
+                push_feeds(state, res, 1);
+                gutter(state, res, None);
+            }
+            push_verbatim(state, res, c.encode_utf8(&mut [0; 4]));
+        }
+        res.extend_from_slice(off);
+    }
+}
+
+/// Prints a QR code or barcode from its `encoded` data, or its data as text
+/// where the printer can't or `encoded` is empty.
+fn push_symbol(
+    state: &mut State,
+    res: &mut Vec<u8>,
+    symbology: Symbology,
+    options: &SymbolOptions,
+    data: &str,
+    encoded: &str,
+) {
+    let supported = match symbology.is_2d() {
+        true => state.capabilities.has_qr,
+        false => state.capabilities.has_barcode,
+    };
+    if state.column > 0 {
+        push_feeds(state, res, 1);
+    }
+    match supported && state.dialect == Dialect::EscPos && !encoded.is_empty() {
+        true => {
+            res.extend_from_slice(&symbology.encode(encoded, options));
+            // Only the line feed printing the symbol ends a line
+            state.breaks.push(res.len() - 1);
+        }
+        false => {
+            push_text(state, res, data);
+            push_feeds(state, res, 1);
+        }
+    }
+}
+
+/// Prints CSV as a table with a bold header, or the CSV itself when it won't parse.
+fn push_table(state: &mut State, res: &mut Vec<u8>, input: &str, start: usize, csv: &str) {
+    let records = match template::csv_records(csv) {
+        Ok(records) => records,
+        Err(e) => {
+            let message = format!("could not parse csv: {}", e);
+            state
+                .diagnostics
+                .push(Diagnostic::at(input, start, message));
+            push_verbatim(state, res, csv);
+            return;
+        }
+    };
+
+    if state.column > 0 {
+        push_feeds(state, res, 1);
+    }
+    let rows = table::layout(&records, state.width, state.frame.as_ref());
+    // Narrowing stops at one character a column, so many columns still overflow
+    let widest = rows
+        .iter()
+        .map(|row| match row {
+            Row::Header(text) | Row::Rule(text) | Row::Body(text) => text.chars().count(),
+        })
+        .max()
+        .unwrap_or(0);
+    if let Some(width) = state.width.filter(|&width| widest > width) {
+        let message = format!(
+            "table is {} characters wide, wider than the paper's {}",
+            widest, width
+        );
+        state
+            .diagnostics
+            .push(Diagnostic::at(input, start, message));
+    }
+    let sequences = state.dialect.sequences();
+    for row in rows {
+        match row {
+            Row::Header(text) => {
+                res.extend_from_slice(sequences.bold_on);
+                push_verbatim(state, res, &text);
+                res.extend_from_slice(sequences.bold_off);
+            }
+            Row::Rule(text) | Row::Body(text) => push_verbatim(state, res, &text),
+        }
+        push_feeds(state, res, 1);
+    }
+}
+
+/// Printer style for a class of highlighted code. Comments are red where the
+/// printer has a second colour, or else inverse, or italic.
+fn class_style(state: &State, class: Class) -> (&'static [u8], &'static [u8]) {
+    let sequences = state.dialect.sequences();
+    match class {
+        Class::Plain => (b"", b""),
+        Class::Keyword => (sequences.bold_on, sequences.bold_off),
+        Class::String => (sequences.underline_on, sequences.underline_off),
+        Class::Comment if state.capabilities.colors > 1 && !sequences.red_on.is_empty() => {
+            (sequences.red_on, sequences.red_off)
+        }
+        Class::Comment if !sequences.inverse_on.is_empty() => {
+            (sequences.inverse_on, sequences.inverse_off)
+        }
+        Class::Comment => (sequences.italic_on, sequences.italic_off),
+    }
+}
+
+/// Numbers headings like 2.1 and maps the `{#id}` tags on them to those numbers.
+fn heading_anchors(input: &str, start: usize) -> BTreeMap<String, String> {
+    let mut counters: Vec<usize> = Vec::new();
+    let mut heading: Option<String> = None;
+    let mut anchors = BTreeMap::new();
+
+    for event in Events::starting_at(input, start) {
+        match event {
+            Event::Heading(level) if heading.is_none() => {
+                counters.resize(level, 0);
+                counters[level - 1] += 1;
+                let number = counters.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+                heading = Some(number.join("."));
+            }
+            Event::Tag(tag) => {
+                if let (Some(number), Some(id)) = (&heading, tag.strip_prefix('#')) {
+                    anchors.insert(id.to_string(), number.clone());
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => heading = None,
+            _ => {}
+        }
+    }
+
+    anchors
+}
+
+/// Abbreviations defined anywhere in the document, the first definition of
+/// each winning.
+fn abbreviations(input: &str, start: usize) -> Vec<(String, String)> {
+    let mut abbreviations: Vec<(String, String)> = Vec::new();
+    for event in Events::starting_at(input, start) {
+        if let Event::Abbreviation {
+            abbreviation,
+            expansion,
+        } = event
+        {
+            if !abbreviations.iter().any(|(known, _)| known == abbreviation) {
+                abbreviations.push((abbreviation.to_string(), expansion.to_string()));
+            }
+        }
+    }
+
+    abbreviations
+}
+
+/// Heading text in the chosen case. Title case carries over styled runs,
+/// so `# the **big** day` is `The Big Day`.
+fn heading_case(state: &mut State, text: &str) -> String {
+    match state.heading_case {
+        HeadingCase::None => text.to_string(),
+        HeadingCase::Upper => text.to_uppercase(),
+        HeadingCase::Title => {
+            let mut res = String::with_capacity(text.len());
+            for c in text.chars() {
+                if c.is_alphanumeric() && !state.in_word {
+                    res.extend(c.to_uppercase());
+                } else {
+                    res.push(c);
+                }
+                // Apostrophes don't start words, as in "Don't"
+                state.in_word = c.is_alphanumeric() || (state.in_word && c == '\'');
+            }
+            res
+        }
+    }
+}
+
+/// Character underlining a heading of this level where it can't be printed
+/// double height: `=` under top headings and `-` under the rest.
+fn heading_rule(state: &State, level: usize) -> Option<char> {
+    match (state.capabilities.has_double_height, level) {
+        (true, _) => None,
+        (false, 1) => Some('='),
+        (false, _) => Some('-'),
+    }
+}
+
+/// Turns bold on for a heading just opened, when headings are forced bold.
+fn force_bold(state: &mut State, options: &Options) -> &'static [u8] {
+    match options.bold_headings && !state.bold {
+        true => {
+            state.heading_bold = true;
+            state.dialect.sequences().bold_on
+        }
+        false => &[],
+    }
+}
+
+/// Ends any open header, first underlining it across the line, or as far
+/// as its text when the width is open. Returns whether there was one.
+fn close_headers(state: &mut State, res: &mut Vec<u8>) -> bool {
+    // Underlined headings were never made taller
+    let sized = state.heading_rule.is_none();
+    if state.top_header || state.lower_header {
+        if let Some(rule) = state.heading_rule.take() {
+            let length = state.width.unwrap_or(state.column);
+            push_feeds(state, res, 1);
+            push_verbatim(state, res, &rule.to_string().repeat(length));
+        }
+    }
+    let after = match (state.top_header, state.lower_header) {
+        (true, _) => Some(state.spacing.top_heading.after),
+        (_, true) => Some(state.spacing.heading.after),
+        _ => None,
+    };
+    if state.heading_bold {
+        state.heading_bold = false;
+        res.extend_from_slice(state.dialect.sequences().bold_off);
+    }
+    let size = [close_top_header(state), close_lower_header(state)].concat();
+    if sized {
+        res.extend_from_slice(&size);
+    }
+    if let Some(after) = after {
+        push_feeds(state, res, after + 1);
+    }
+
+    after.is_some()
+}
+
+fn new_line(state: &mut State, res: &mut Vec<u8>, event: Event) {
+    let line_ends = core::mem::take(&mut state.line_ends);
+
+    // Closing a header already ends the line
+    if close_headers(state, res) {
+        return;
+    }
+
+    if event == Event::HardBreak || state.keep_linebreaks || line_ends {
+        push_feeds(state, res, 1);
+    } else {
+        push_text(state, res, " ");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lists::Numbering;
+    use crate::paper::Paper;
+    use crate::spacing::Around;
+    use crate::toml;
+    #[test]
+    fn new_line_removes_single_newlines() {
+        let mut state = State::default();
+        let mut res = Vec::new();
+
+        new_line(&mut state, &mut res, Event::SoftBreak);
+
+        assert_eq!(res, b" ");
+    }
+
+    #[test]
+    fn new_line_collapses_multiple_newlines() {
+        let mut state = State::default();
+        let mut res = Vec::new();
+
+        new_line(&mut state, &mut res, Event::HardBreak);
+
+        assert_eq!(res, b"\n");
+    }
+
+    #[test]
+    fn single_newlines_can_be_kept() {
+        let options = Options {
+            dialect: Dialect::Plain,
+            keep_linebreaks: true,
+            ..Default::default()
+        };
+        let rendered = render_markdown("Roses are red,\nviolets blue\n- a\ncontinued", &options);
+
+        assert_eq!(
+            String::from_utf8(rendered.bytes).unwrap(),
+            "Roses are red,\nviolets blue\n- a\ncontinued\n"
+        );
+    }
+
+    #[test]
+    fn list_bullets_follow_nesting() {
+        let options = Options {
+            dialect: Dialect::Plain,
+            lists: ListStyle {
+                bullets: vec![String::from("*"), String::from("+")],
+                indent: 3,
+                numbering: None,
+            },
+            ..Default::default()
+        };
+        let rendered = render_markdown("Items:\n- one\n  - two\n    * three\n- four\n", &options);
+
+        assert_eq!(
+            String::from_utf8(rendered.bytes).unwrap(),
+            "Items: \n* one\n   + two\n      + three\n* four\n"
+        );
+    }
+
+    #[test]
+    fn numbered_items_count_up_from_the_first() {
+        let options = |numbering| Options {
+            dialect: Dialect::Plain,
+            lists: ListStyle {
+                numbering,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let input = "3. one\n1. two\n   1. sub\n   1. sub\n- dash\n1) again\n";
+
+        let rendered = render_markdown(input, &options(None));
+        assert_eq!(
+            String::from_utf8(rendered.bytes).unwrap(),
+            "3. one\n4. two\n  1. sub\n  2. sub\n- dash\n1. again\n"
+        );
+        let rendered = render_markdown(input, &options(Some(Numbering::Letter)));
+        assert!(String::from_utf8(rendered.bytes)
+            .unwrap()
+            .starts_with("c. one\nd. two\n  a. sub\n"));
+    }
+
+    #[test]
+    fn styles_can_be_remapped_or_dropped() {
+        let mut styles = StyleMap::default();
+        styles.parse_mapping("italic=underline").unwrap();
+        styles.parse_mapping("bold = none").unwrap();
+        let options = Options {
+            dialect: Dialect::EscPos,
+            styles,
+            ..Default::default()
+        };
+        let rendered = render_markdown("**b** *a*", &options);
+
+        assert_eq!(rendered.bytes, b"b \x1B-\x01a\x1B-\x00\n");
+        assert!(StyleMap::default().parse_mapping("italic").is_err());
+        assert!(StyleMap::default().parse_mapping("none=bold").is_err());
+    }
+
+    #[test]
+    fn forced_bold_headings_end_with_the_heading() {
+        let options = Options {
+            dialect: Dialect::EscP,
+            bold_headings: true,
+            spacing: Spacing {
+                heading: Around::default(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let rendered = render_markdown("## a\nb", &options);
+
+        assert_eq!(rendered.bytes, b"\n\x1Bw1\x1BEa\x1BF\x1Bw0\nb\n");
+    }
+
+    #[test]
+    fn unscannable_barcodes_print_as_text() {
+        let options = Options {
+            dialect: Dialect::EscPos,
+            ..Default::default()
+        };
+        let rendered = render_markdown("```ean13\n4006381333932\n```", &options);
+
+        assert_eq!(rendered.bytes, b"4006381333932\n");
+        assert_eq!(rendered.diagnostics.len(), 1);
+        assert!(rendered.diagnostics[0]
+            .message
+            .contains("check digit should be 1"));
+    }
+
+    #[test]
+    fn malformed_markup_prints_as_written() {
+        let options = Options {
+            dialect: Dialect::Plain,
+            ..Default::default()
+        };
+        let rendered = render_markdown("one\nsee {docs", &options);
+
+        assert_eq!(rendered.bytes, b"one see {docs\n");
+        assert_eq!(
+            (rendered.diagnostics[0].line, rendered.diagnostics[0].column),
+            (2, 4)
+        );
+    }
+
+    #[test]
+    fn control_characters_are_removed_unless_raw() {
+        let mut options = Options::default();
+        let rendered = render_markdown("a\x1B@b\x07", &options);
+
+        assert_eq!(rendered.bytes.as_slice(), b"a@b\n");
+        assert_eq!(rendered.diagnostics.len(), 1);
+
+        options.allow_raw = true;
+        let rendered = render_markdown("a\x1B@b", &options);
+
+        assert_eq!(rendered.bytes.as_slice(), b"a\x1B@b\n");
+    }
+
+    #[test]
+    fn comments_are_dropped_and_carry_directives() {
+        let options = Options {
+            dialect: Dialect::EscPos,
+            ..Default::default()
+        };
+        let input = "one <!-- a\nnote -->two\n<!-- cut -->\n\nthree<!-- BEEP -->";
+        let rendered = render_markdown(input, &options);
+
+        assert_eq!(
+            rendered.bytes.as_slice(),
+            b"one two \n\x1DVA\x03three\x1BB\x02\x02\n"
+        );
+    }
+
+    #[test]
+    fn bold_transpiles() {
+        let input = "**bold text**";
+        let expected_output = b"\x1BEbold text\x1BF\n";
+        let res = transpile_markdown(input);
+
+        assert_eq!(res.as_slice(), expected_output)
+    }
+
+    #[test]
+    fn italic_transpiles() {
+        let input = "*italic text*";
+        let expected_output = b"\x1B4italic text\x1B5\n";
+        let res = transpile_markdown(input);
+
+        assert_eq!(res.as_slice(), expected_output)
+    }
+
+    #[test]
+    fn underlined_transpiles() {
+        let input = "__underlined text__";
+        let expected_output = b"\x1B-1underlined text\x1B-0\n";
+        let res = transpile_markdown(input);
+
+        assert_eq!(res.as_slice(), expected_output)
+    }
+
+    #[test]
+    fn top_header_transpiles() {
+        let input = "# Header text\n";
+        let expected_output = b"\n\n\x1BE\x1Bw1\x1BW1Header text\x1BF\x1Bw0\x1BW0\n\n";
+        let res = transpile_markdown(input);
+
+        assert_eq!(res.as_slice(), expected_output)
+    }
+
+    #[test]
+    fn lower_header_transpiles() {
+        let input = "## Header text\n";
+        let expected_output = b"\n\n\x1Bw1Header text\x1Bw0\n";
+        let res = transpile_markdown(input);
+
+        assert_eq!(res.as_slice(), expected_output)
+    }
+
+    #[test]
+    fn unclosed_bold_is_reported_and_reset() {
+        let input = "plain\nsome **bold";
+        let rendered = render_markdown(input, &Options::default());
+
+        assert_eq!(rendered.bytes.as_slice(), b"plain some \x1BEbold\x1BF\n");
+        assert_eq!(
+            rendered.diagnostics,
+            vec![Diagnostic {
+                line: 2,
+                column: 6,
+                message: String::from("unclosed bold"),
+            }]
+        );
+    }
+
+    #[test]
+    fn closed_styles_produce_no_diagnostics() {
+        let rendered = render_markdown("**bold** and *italic*", &Options::default());
+
+        assert!(rendered.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn long_lines_wrap_at_the_last_space() {
+        let options = Options {
+            layout: Layout {
+                width: Some(10),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let rendered = render_markdown("the quick **brown** fox jumps", &options);
+
+        assert_eq!(
+            rendered.bytes.as_slice(),
+            b"the quick\n\x1BEbrown\x1BF fox\njumps\n"
+        );
+    }
+
+    #[test]
+    fn paper_preset_sets_up_the_job() {
+        let options = Options {
+            dialect: Dialect::EscPos,
+            layout: Paper::Mm58.layout(),
+            ..Default::default()
+        };
+        let rendered = render_markdown("**bold**", &options);
+
+        assert_eq!(
+            rendered.bytes.as_slice(),
+            b"\x1BM\x00\x1BE\x01bold\x1BE\x00\n"
+        );
+    }
+
+    #[test]
+    fn plain_format_strips_markers() {
+        let options = Options {
+            dialect: Dialect::Plain,
+            ..Default::default()
+        };
+        let input = "# Title\n**bold** [site](https://example.com)\n```sh\nls -l\n```";
+        let rendered = render_markdown(input, &options);
+
+        assert_eq!(
+            String::from_utf8(rendered.bytes).unwrap(),
+            "\n\nTitle\n=====\n\nbold site (https://example.com)\nls -l\n"
+        );
+    }
+
+    #[test]
+    fn headings_are_underlined_without_double_height() {
+        let options = Options {
+            dialect: Dialect::EscPos,
+            layout: Layout {
+                width: Some(6),
+                ..Default::default()
+            },
+            capabilities: Some(Capabilities::default()),
+            ..Default::default()
+        };
+        let rendered = render_markdown("## Sub\ntext", &options);
+
+        assert_eq!(rendered.bytes.as_slice(), b"\n\nSub\n------\ntext\n");
+    }
+
+    #[test]
+    fn headings_change_case_but_not_body_text() {
+        let options = |heading_case| Options {
+            dialect: Dialect::Plain,
+            heading_case,
+            ..Default::default()
+        };
+        let input = "# straße **o'neil** café\n\nbody text";
+
+        let lines = |case| {
+            let rendered = render_markdown(input, &options(case));
+            let text = String::from_utf8(rendered.bytes).unwrap();
+            text.lines().map(String::from).collect::<Vec<_>>()
+        };
+
+        let upper = lines(HeadingCase::Upper);
+        assert_eq!(upper[2], "STRASSE O'NEIL CAFÉ");
+        assert_eq!(upper.last().unwrap(), "body text");
+        assert_eq!(lines(HeadingCase::Title)[2], "Straße O'neil Café");
+        assert_eq!(lines(HeadingCase::None)[2], "straße o'neil café");
+    }
+
+    #[test]
+    fn scripts_are_raised_or_written_out() {
+        let input = "x^2^ H~2~O";
+
+        assert_eq!(
+            transpile_markdown(input),
+            b"x\x1BS\x002\x1BT H\x1BS\x012\x1BTO\n"
+        );
+        let options = Options {
+            dialect: Dialect::Plain,
+            ..Default::default()
+        };
+        assert_eq!(render_markdown(input, &options).bytes, b"x^2 H_2O\n");
+        let options = Options {
+            dialect: Dialect::Html,
+            ..Default::default()
+        };
+        assert_eq!(
+            render_markdown(input, &options).bytes,
+            b"x<sup>2</sup> H<sub>2</sub>O\n"
+        );
+    }
+
+    #[test]
+    fn leaders_push_values_flush_right() {
+        let options = Options {
+            dialect: Dialect::Plain,
+            layout: Layout {
+                width: Some(16),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let input = "Coffee ...... $3.50\n**Tea** .... $2\nthanks\nfor coming";
+        let rendered = render_markdown(input, &options);
+
+        assert_eq!(
+            String::from_utf8(rendered.bytes).unwrap(),
+            "Coffee ... $3.50\nTea ......... $2\nthanks for\ncoming\n"
+        );
+    }
+
+    #[test]
+    fn missing_image_falls_back_to_alt_text() {
+        let rendered = render_markdown("![logo](does-not-exist.pgm)", &Options::default());
+
+        assert_eq!(rendered.bytes.as_slice(), b"[image: logo]\n");
+        assert_eq!(rendered.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn code_blocks_are_highlighted_inside_their_fences() {
+        let options = Options {
+            dialect: Dialect::EscPos,
+            highlight: true,
+            ..Default::default()
+        };
+        let rendered = render_markdown("```rust\nfn main() {} // hi\n```", &options);
+
+        assert_eq!(
+            rendered.bytes.as_slice(),
+            b"```rust\n\x1BE\x01fn\x1BE\x00 main() {} \x1DB\x01// hi\x1DB\x00\n```\n"
+        );
+    }
+
+    #[test]
+    fn numbered_code_wraps_under_the_code_column() {
+        let options = Options {
+            dialect: Dialect::Plain,
+            layout: Layout {
+                width: Some(8),
+                ..Default::default()
+            },
+            line_numbers: true,
+            ..Default::default()
+        };
+        let rendered = render_markdown("```\nabcdefghij\nk\n```", &options);
+
+        assert_eq!(
+            String::from_utf8(rendered.bytes).unwrap(),
+            "\n1 abcdef\n  ghij\n2 k\n"
+        );
+    }
+
+    #[test]
+    fn intra_document_links_print_section_numbers() {
+        let options = Options {
+            dialect: Dialect::Plain,
+            ..Default::default()
+        };
+        let input = "# Intro\n## Parts\n## Setup {#setup}\nsee [setup](#setup)";
+        let rendered = render_markdown(input, &options);
+
+        assert!(String::from_utf8(rendered.bytes)
+            .unwrap()
+            .ends_with("see setup (\u{a7}1.2)\n"));
+        assert!(rendered.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn unknown_cross_references_are_reported() {
+        let rendered = render_markdown("[nowhere](#missing)", &Options::default());
+
+        assert_eq!(rendered.bytes.as_slice(), b"nowhere\n");
+        assert_eq!(
+            rendered.diagnostics[0].message,
+            "unknown cross-reference #missing"
+        );
+    }
+
+    #[test]
+    fn page_numbers_need_the_total_page_count() {
+        let options = Options {
+            dialect: Dialect::Plain,
+            layout: Layout {
+                width: Some(12),
+                page_length: Some(4),
+                ..Default::default()
+            },
+            page_footer: Some(String::from("{{page}}/{{pages}}")),
+            ..Default::default()
+        };
+        let rendered = render_markdown("one\\\\two\\\\three", &options);
+
+        assert_eq!(
+            String::from_utf8(rendered.bytes).unwrap(),
+            "one\ntwo\n\n    1/2\x0Cthree\n\n\n    2/2\x0C"
+        );
+    }
+
+    #[test]
+    fn pages_count_image_bands_but_not_command_bytes() {
+        let options = Options {
+            layout: Layout {
+                page_length: Some(4),
+                ..Default::default()
+            },
+            page_footer: Some(String::from("{{page}}/{{pages}}")),
+            ..Default::default()
+        };
+        let rendered = render_markdown("![ramp](mdtoepson:gradient)", &options);
+        let footers = rendered.bytes.iter().filter(|&&b| b == 0x0C).count();
+
+        assert_eq!(footers, 2);
+        assert_eq!(rendered.stats.lines, 1);
+
+        // Seven bytes of data make the QR code's store command 0x0A long
+        let options = Options {
+            dialect: Dialect::EscPos,
+            ..Default::default()
+        };
+        let rendered = render_markdown("```qr\nabcdefg\n```", &options);
+
+        assert!(rendered.bytes.contains(&b'\n'));
+        assert_eq!(rendered.stats.lines, 1);
+    }
+
+    #[test]
+    fn cut_per_heading_separates_tickets() {
+        let options = Options {
+            dialect: Dialect::EscPos,
+            cut_level: Some(2),
+            ..Default::default()
+        };
+        let rendered = render_markdown("## One\nx\n## Two\ny", &options);
+
+        assert_eq!(
+            rendered.bytes.as_slice(),
+            b"\n\n\x1D!\x01One\x1D!\x00\nx \n\x1DVA\x03\n\n\x1D!\x01Two\x1D!\x00\ny\n"
+        );
+    }
+
+    #[test]
+    fn cut_without_cutter_ejects_the_page() {
+        let options = Options {
+            cut_level: Some(2),
+            ..Default::default()
+        };
+        let rendered = render_markdown("## One\nx\n## Two\ny", &options);
+
+        assert!(rendered.bytes.windows(2).any(|w| w == b"\n\x0C"));
+    }
+
+    #[test]
+    fn qr_falls_back_to_text_without_support() {
+        let input = "```qr\nhttps://example.com\n```";
+        let options = Options {
+            dialect: Dialect::EscPos,
+            capabilities: Some(Capabilities::default()),
+            ..Default::default()
+        };
+        let rendered = render_markdown(input, &options);
+
+        assert_eq!(rendered.bytes.as_slice(), b"https://example.com\n");
+
+        let options = Options {
+            dialect: Dialect::EscPos,
+            ..Default::default()
+        };
+        let rendered = render_markdown(input, &options);
+
+        assert!(rendered.bytes.starts_with(b"\x1D(k"));
+    }
+
+    #[test]
+    fn comments_are_red_with_a_second_colour() {
+        let options = Options {
+            dialect: Dialect::Html,
+            highlight: true,
+            ..Default::default()
+        };
+        let rendered = render_markdown("```sh\n# hi\n```", &options);
+
+        assert_eq!(
+            rendered.bytes.as_slice(),
+            b"\n<span class=\"red\"># hi</span>\n"
+        );
+    }
+
+    #[test]
+    fn width_is_clamped_to_the_printer() {
+        let options = Options {
+            layout: Layout {
+                width: Some(136),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let rendered = render_markdown(&"word ".repeat(20), &options);
+
+        assert_eq!(rendered.diagnostics.len(), 1);
+        let lines = String::from_utf8(rendered.bytes).unwrap();
+        assert_eq!(lines.lines().next(), Some("word ".repeat(16).trim_end()));
+        assert!(lines.lines().all(|line| line.len() <= 80));
+    }
+
+    #[test]
+    fn comments_are_red_or_else_inverse() {
+        let options = |colors| Options {
+            dialect: Dialect::EscPos,
+            highlight: true,
+            capabilities: Some(Capabilities {
+                colors,
+                ..Dialect::EscPos.capabilities()
+            }),
+            ..Default::default()
+        };
+        let input = "```sh\n# hi\n```";
+
+        let red = b"\x1Br\x01# hi\x1Br\x00";
+        let rendered = render_markdown(input, &options(2));
+        assert!(rendered.bytes.windows(red.len()).any(|w| w == red));
+        let inverse = b"\x1DB\x01# hi\x1DB\x00";
+        let rendered = render_markdown(input, &options(1));
+        assert!(rendered.bytes.windows(inverse.len()).any(|w| w == inverse));
+    }
+
+    #[test]
+    fn init_wraps_the_job() {
+        let options = Options {
+            dialect: Dialect::EscPos,
+            init: true,
+            ..Default::default()
+        };
+        let rendered = render_markdown("# hi", &options);
+
+        assert_eq!(
+            rendered.bytes.as_slice(),
+            b"\x1B@\n\n\x1BE\x01\x1D!\x11hi\x1BE\x00\x1D!\x00\n\n\x1BE\x00\x1B-\x00\x1DB\x00\x1D!\x00\x1Br\x00"
+        );
+    }
+
+    #[test]
+    fn labels_feed_to_the_next_one_instead_of_cutting() {
+        let options = Options {
+            dialect: Dialect::EscPos,
+            labels: true,
+            layout: Layout {
+                label_height: Some(10),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let rendered = render_markdown("a\n<!-- cut -->\nb\\\\c\\\\d", &options);
+        assert_eq!(rendered.bytes, b"a \n\x1D\x0Cb\nc\nd\n");
+        assert_eq!(rendered.diagnostics.len(), 1);
+        assert_eq!(
+            rendered.diagnostics[0].message,
+            "1 of the labels run past their 10 mm and onto the next"
+        );
+    }
+
+    #[test]
+    fn whitespace_and_comments_are_blank() {
+        assert!(is_blank(""));
+        assert!(is_blank(" \n\n\t\r\n<!-- draft -->\n"));
+        assert!(!is_blank("\n."));
+        assert!(!is_blank("<!-- beep -->"));
+    }
+
+    #[test]
+    fn front_matter_changes_spacing() {
+        let options = Options {
+            dialect: Dialect::Plain,
+            ..Default::default()
+        };
+        let input = "+++\n[spacing]\nheading_before = 0\nheading_after = 1\nlist_before = 1\nlist_after = 1\n+++\n## Hi\ntext\n- a\n- b\nend";
+        let rendered = render_markdown(input, &options);
+
+        assert_eq!(
+            String::from_utf8(rendered.bytes).unwrap(),
+            "\nHi\n--\n\ntext \n\n- a\n- b\n\nend\n"
+        );
+        assert!(rendered.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn frames_fall_back_to_ascii() {
+        let options = |capabilities| Options {
+            dialect: Dialect::Plain,
+            frames: true,
+            capabilities,
+            ..Default::default()
+        };
+        let input = "```\nab\nc\n```";
+
+        let rendered = render_markdown(input, &options(None));
+        assert_eq!(
+            String::from_utf8(rendered.bytes).unwrap(),
+            "┌──┐\n│ab│\n│c │\n└──┘\n"
+        );
+        let rendered = render_markdown(input, &options(Some(Capabilities::default())));
+        assert_eq!(
+            String::from_utf8(rendered.bytes).unwrap(),
+            "+--+\n|ab|\n|c |\n+--+\n"
+        );
+    }
+
+    #[test]
+    fn abbreviations_are_underlined_and_listed_at_the_end() {
+        let options = Options {
+            dialect: Dialect::Html,
+            ..Default::default()
+        };
+        let input = "HTML, not XHTML or HTMLs.\n\n*[HTML]: HyperText Markup Language\n";
+        let rendered = render_markdown(input, &options);
+
+        assert_eq!(
+            String::from_utf8(rendered.bytes).unwrap(),
+            "<u>HTML</u>, not XHTML or HTMLs.\n\n<u>HTML</u>: HyperText Markup Language\n"
+        );
+    }
+
+    #[test]
+    fn density_is_set_after_init() {
+        let options = |dialect| Options {
+            dialect,
+            init: true,
+            density: Some(-2),
+            ..Default::default()
+        };
+
+        let rendered = render_markdown("x", &options(Dialect::EscPos));
+        assert!(rendered.bytes.starts_with(b"\x1B@\x1D(K\x02\x001\xFEx"));
+        let rendered = render_markdown("x", &options(Dialect::EscP));
+        assert!(rendered.bytes.starts_with(b"\x1B@x"));
+    }
+
+    #[test]
+    fn continued_documents_skip_setup_but_reset() {
+        let options = Options {
+            dialect: Dialect::EscPos,
+            init: true,
+            density: Some(-2),
+            continued: true,
+            ..Default::default()
+        };
+        let rendered = render_markdown("x", &options);
+
+        assert!(rendered.bytes.starts_with(b"x"));
+        assert!(rendered.bytes.ends_with(Dialect::EscPos.sequences().reset));
+    }
+
+    #[test]
+    fn feed_after_adds_lines_past_the_last() {
+        let options = Options {
+            dialect: Dialect::Plain,
+            feed_after: 3,
+            ..Default::default()
+        };
+        let rendered = render_markdown("x", &options);
+
+        assert_eq!(rendered.bytes, b"x\n\n\n\n");
+        assert_eq!(rendered.stats.lines, 4);
+    }
+
+    #[test]
+    fn characters_outside_the_codepage_use_glyphs() {
+        let tables = toml::parse("[glyphs]\n\"❤\" = \"#\"").unwrap();
+        let options = Options {
+            dialect: Dialect::EscPos,
+            glyphs: Glyphs::from_table(&tables[0]).unwrap(),
+            ..Default::default()
+        };
+        let rendered = render_markdown("é❤€", &options);
+
+        let defined = options.glyphs.define(Dialect::EscPos);
+        let printed = [&[0x82][..], &glyphs::print(0x7E), b"?\n"].concat();
+        assert_eq!(rendered.bytes, [defined, printed].concat());
+    }
+
+    #[test]
+    fn codepages_switch_per_character() {
+        let options = Options {
+            dialect: Dialect::EscPos,
+            ..Default::default()
+        };
+        let rendered = render_markdown("é λ┼", &options);
+
+        assert_eq!(
+            rendered.bytes.as_slice(),
+            b"\x82 \x1Bt\x0E\xA2\xC5\n\x1Bt\x00"
+        );
+    }
+
+    #[test]
+    fn transliteration_replaces_question_marks() {
+        let mut options = Options {
+            dialect: Dialect::EscPos,
+            ..Default::default()
+        };
+        assert_eq!(render_markdown("a→b", &options).bytes.as_slice(), b"a?b\n");
+
+        options.transliterate = true;
+        assert_eq!(render_markdown("a→b", &options).bytes.as_slice(), b"a->b\n");
+    }
+
+    #[test]
+    fn csv_blocks_print_as_tables() {
+        let options = Options {
+            dialect: Dialect::EscPos,
+            ..Default::default()
+        };
+        let rendered = render_markdown("Totals:\n```csv\nItem,Qty\nTea,2\n```", &options);
+
+        assert_eq!(
+            rendered.bytes.as_slice(),
+            b"Totals:\n\x1BE\x01Item Qty\x1BE\x00\n---- ---\nTea    2\n"
+        );
+    }
+
+    #[test]
+    fn tables_wider_than_the_paper_are_reported() {
+        let options = Options {
+            dialect: Dialect::Plain,
+            layout: Layout {
+                width: Some(12),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let input = "Grid:\n\n```csv\na,b,c,d,e,f,g,h\n1,2,3,4,5,6,7,8\n```";
+        let rendered = render_markdown(input, &options);
+
+        assert_eq!(
+            rendered.diagnostics,
+            vec![Diagnostic {
+                line: 3,
+                column: 1,
+                message: String::from("table is 15 characters wide, wider than the paper's 12"),
+            }]
+        );
+        let fits = render_markdown("```csv\na,b\n1,2\n```", &options);
+        assert!(fits.diagnostics.is_empty());
+    }
+}
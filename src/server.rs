@@ -0,0 +1,188 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use crate::service::{self, POLL_INTERVAL};
+use crate::websocket::{self, BINARY, CLOSE, CONTINUATION, PING, PONG, TEXT};
+
+/// Path of the endpoint streaming markdown to the printer
+pub const STREAM_PATH: &str = "/ws";
+
+/// Largest request head we read, in bytes and in lines
+const MAX_HEAD: u64 = 8 << 10;
+const MAX_HEADER_LINES: usize = 100;
+
+/// How long a client may stall partway through a request or a frame
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where a connection's markdown goes as it arrives.
+pub trait Sink {
+    fn push(&mut self, fragment: &[u8]) -> Result<(), String>;
+    /// Prints whatever is still held back once the client is gone.
+    fn finish(self) -> Result<(), String>;
+}
+
+/// Serves clients one at a time, so their output never interleaves on paper,
+/// until SIGTERM. `open` starts a job for a connection, given a name for it.
+pub fn serve<S: Sink>(
+    listener: TcpListener,
+    mut open: impl FnMut(String) -> Result<S, String>,
+) -> std::io::Result<()> {
+    listener.set_nonblocking(true)?;
+    while !service::terminating() {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                let served = stream
+                    .set_nonblocking(false)
+                    .map_err(|e| e.to_string())
+                    .and_then(|_| connection(stream, &mut open));
+                if let Err(message) = served {
+                    eprintln!("warning: {}: {}", peer, message);
+                }
+            }
+            Err(e) if service::timed_out(&e) => thread::sleep(POLL_INTERVAL),
+            Err(e) => eprintln!("warning: could not accept a connection: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+fn connection<S: Sink>(
+    mut stream: TcpStream,
+    open: &mut impl FnMut(String) -> Result<S, String>,
+) -> Result<(), String> {
+    let peer = stream
+        .peer_addr()
+        .map_or_else(|_| String::from("unknown"), |peer| peer.to_string());
+    stream
+        .set_read_timeout(Some(READ_TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    let (path, key) = read_request(&mut stream).map_err(|e| e.to_string())?;
+    let key = match (path.as_str(), key) {
+        (STREAM_PATH, Some(key)) => key,
+        (STREAM_PATH, None) => return respond(&mut stream, "400 Bad Request"),
+        _ => return respond(&mut stream, "404 Not Found"),
+    };
+
+    let mut sink = open(format!("websocket {}", peer))?;
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        websocket::accept_key(&key)
+    );
+    stream
+        .write_all(response.as_bytes())
+        .map_err(|e| e.to_string())?;
+
+    let closed = loop {
+        match wait_for_data(&stream) {
+            Ok(true) => {}
+            Ok(false) => {
+                let _ = websocket::write_frame(&mut stream, CLOSE, &[0x03, 0xE9]);
+                break Ok(());
+            }
+            Err(e) => break Err(e.to_string()),
+        }
+        let frame = match websocket::read_frame(&mut stream) {
+            Ok(frame) => frame,
+            Err(e) => break Err(e.to_string()),
+        };
+        let result = match frame.opcode {
+            TEXT | BINARY | CONTINUATION => sink.push(&frame.payload),
+            PING => {
+                websocket::write_frame(&mut stream, PONG, &frame.payload).map_err(|e| e.to_string())
+            }
+            CLOSE => {
+                let _ = websocket::write_frame(&mut stream, CLOSE, &[]);
+                break Ok(());
+            }
+            _ => Ok(()),
+        };
+        if let Err(message) = result {
+            let _ = websocket::write_frame(&mut stream, CLOSE, &[0x03, 0xF3]);
+            break Err(message);
+        }
+    };
+
+    sink.finish().and(closed)
+}
+
+/// Waits for the client to send something, or returns false on SIGTERM so
+/// the job ends with what has arrived.
+fn wait_for_data(stream: &TcpStream) -> std::io::Result<bool> {
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    let ready = loop {
+        match stream.peek(&mut [0]) {
+            Ok(_) => break true,
+            Err(e) if service::timed_out(&e) && service::terminating() => break false,
+            Err(e) if service::timed_out(&e) => {}
+            Err(e) => return Err(e),
+        }
+    };
+    // A frame arriving slowly mustn't be cut off partway, nor hang forever
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+    Ok(ready)
+}
+
+/// Reads an HTTP request head, returning its path and WebSocket key, if any.
+fn read_request(stream: &mut impl Read) -> std::io::Result<(String, Option<String>)> {
+    let too_large = || std::io::Error::other("request head is too large");
+    let mut reader = BufReader::new(stream.take(MAX_HEAD));
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let path = line.split_whitespace().nth(1).unwrap_or("").to_string();
+
+    let mut key = None;
+    for _ in 0..MAX_HEADER_LINES {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return match reader.get_ref().limit() {
+                0 => Err(too_large()),
+                _ => Ok((path, key)),
+            };
+        }
+        if line.trim().is_empty() {
+            return Ok((path, key));
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    Err(too_large())
+}
+
+fn respond(stream: &mut TcpStream, status: &str) -> Result<(), String> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status
+    );
+    stream
+        .write_all(response.as_bytes())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_heads_are_capped() {
+        let mut request = &b"GET /ws HTTP/1.1\r\nSec-WebSocket-Key: abc\r\n\r\n"[..];
+        let (path, key) = read_request(&mut request).unwrap();
+        assert_eq!((path.as_str(), key.as_deref()), ("/ws", Some("abc")));
+
+        let many = "GET /ws HTTP/1.1\r\n".to_string() + &"X: y\r\n".repeat(MAX_HEADER_LINES);
+        assert!(read_request(&mut many.as_bytes()).is_err());
+
+        let long = format!(
+            "GET /ws HTTP/1.1\r\nX: {}\r\n\r\n",
+            "y".repeat(MAX_HEAD as usize)
+        );
+        assert!(read_request(&mut long.as_bytes()).is_err());
+    }
+}
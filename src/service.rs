@@ -0,0 +1,140 @@
+use std::io::{self, ErrorKind};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// How often waiting listeners check whether to stop
+pub const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+static TERMINATING: AtomicBool = AtomicBool::new(false);
+
+/// Whether SIGTERM has arrived since [`handle_sigterm`].
+pub fn terminating() -> bool {
+    TERMINATING.load(Ordering::SeqCst)
+}
+
+/// Whether an I/O error is a read or accept that merely timed out, or was
+/// interrupted by a signal.
+pub fn timed_out(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+    )
+}
+
+#[cfg(unix)]
+pub use unix::{handle_sigterm, inherited};
+
+#[cfg(unix)]
+mod unix {
+    use std::env;
+    use std::os::fd::{FromRawFd, RawFd};
+    use std::sync::atomic::Ordering;
+
+    use super::TERMINATING;
+
+    /// First descriptor passed by socket activation, after stdin, stdout and stderr
+    const SD_LISTEN_FDS_START: RawFd = 3;
+    const SIGTERM: i32 = 15;
+
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+
+    extern "C" fn on_sigterm(_: i32) {
+        TERMINATING.store(true, Ordering::SeqCst);
+    }
+
+    /// Records SIGTERM instead of dying on it, so the job in flight finishes.
+    pub fn handle_sigterm() {
+        // SAFETY: the handler only stores to an atomic, which is signal safe
+        unsafe {
+            signal(SIGTERM, on_sigterm);
+        }
+    }
+
+    /// The listening socket systemd passed us by socket activation, if any.
+    pub fn inherited<T: FromRawFd>() -> Option<T> {
+        let fd = listen_fd()?;
+
+        // SAFETY: systemd hands the descriptor to this process alone, and
+        // listen_fd clears the variables so nothing else claims it
+        Some(unsafe { T::from_raw_fd(fd) })
+    }
+
+    /// Our descriptor from `LISTEN_FDS`, like `sd_listen_fds`. The variables
+    /// are cleared so programs we run don't take it for theirs.
+    fn listen_fd() -> Option<RawFd> {
+        let pid = env::var("LISTEN_PID").ok();
+        let count = env::var("LISTEN_FDS").ok();
+        env::remove_var("LISTEN_PID");
+        env::remove_var("LISTEN_FDS");
+        env::remove_var("LISTEN_FDNAMES");
+
+        passed_fd(pid.as_deref(), count.as_deref(), std::process::id())
+    }
+
+    /// The descriptor `LISTEN_PID` and `LISTEN_FDS` pass to process `id`.
+    pub(super) fn passed_fd(pid: Option<&str>, count: Option<&str>, id: u32) -> Option<RawFd> {
+        let ours = pid.and_then(|pid| pid.parse::<u32>().ok()) == Some(id);
+        let count = count.and_then(|count| count.parse::<RawFd>().ok());
+        match (ours, count) {
+            (true, Some(1)) => Some(SD_LISTEN_FDS_START),
+            (true, Some(count)) if count > 1 => {
+                eprintln!(
+                    "warning: systemd passed {} sockets; serving only the first",
+                    count
+                );
+                Some(SD_LISTEN_FDS_START)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn handle_sigterm() {}
+
+#[cfg(not(unix))]
+pub fn inherited<T>() -> Option<T> {
+    None
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Held by tests that raise SIGTERM or wait on [`terminating`], so none
+    /// sees another's signal.
+    pub(crate) static SIGNALS: Mutex<()> = Mutex::new(());
+
+    #[cfg(unix)]
+    extern "C" {
+        fn raise(signum: i32) -> i32;
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sigterm_is_recorded_instead_of_fatal() {
+        let _signals = SIGNALS.lock().unwrap_or_else(|e| e.into_inner());
+        handle_sigterm();
+        // SAFETY: the handler installed above only stores to an atomic
+        assert_eq!(unsafe { raise(15) }, 0);
+
+        assert!(terminating());
+        TERMINATING.store(false, Ordering::SeqCst);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_sockets_passed_to_this_process_are_taken() {
+        use unix::passed_fd;
+
+        assert_eq!(passed_fd(Some("1"), Some("1"), 42), None);
+        assert_eq!(passed_fd(Some("42"), Some("1"), 42), Some(3));
+        assert_eq!(passed_fd(Some("42"), Some("2"), 42), Some(3));
+        assert_eq!(passed_fd(Some("42"), Some("0"), 42), None);
+        assert_eq!(passed_fd(None, None, 42), None);
+    }
+}
@@ -0,0 +1,154 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::thread;
+
+use crate::service::{self, POLL_INTERVAL};
+
+/// Largest job we accept, length-prefixed or a line
+const MAX_JOB: usize = 16 << 20;
+
+/// Binds a Unix socket, replacing one left behind by an earlier run.
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    if fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        fs::remove_file(path)?;
+    }
+
+    UnixListener::bind(path)
+}
+
+/// Serves jobs on a Unix socket until SIGTERM, printing each and answering
+/// `ok` or `error: …` on a line. A job is a line of markdown, or, when it
+/// starts with a zero byte, a 4 byte big-endian length followed by that much
+/// markdown. Clients are served one at a time.
+pub fn listen(
+    listener: UnixListener,
+    mut print: impl FnMut(&str) -> Result<(), String>,
+) -> io::Result<()> {
+    listener.set_nonblocking(true)?;
+    while !service::terminating() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let served = stream
+                    .set_nonblocking(false)
+                    .and_then(|_| connection(stream, &mut print));
+                if let Err(e) = served {
+                    eprintln!("warning: {}: {}", name(&listener), e);
+                }
+            }
+            Err(e) if service::timed_out(&e) => thread::sleep(POLL_INTERVAL),
+            Err(e) => eprintln!("warning: could not accept a connection: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+fn name(listener: &UnixListener) -> String {
+    let address = listener.local_addr().ok();
+    match address.as_ref().and_then(|address| address.as_pathname()) {
+        Some(path) => path.display().to_string(),
+        None => String::from("socket"),
+    }
+}
+
+fn connection(
+    stream: UnixStream,
+    print: &mut impl FnMut(&str) -> Result<(), String>,
+) -> io::Result<()> {
+    let mut reply = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    while wait_for_job(&mut reader)? {
+        let Some(job) = read_job(&mut reader)? else {
+            break;
+        };
+        let answer = match print(&String::from_utf8_lossy(&job)) {
+            Ok(()) => String::from("ok\n"),
+            Err(message) => format!("error: {}\n", message.replace('\n', " ")),
+        };
+        reply.write_all(answer.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Waits for the client to start a job, or returns false on SIGTERM.
+fn wait_for_job(reader: &mut BufReader<UnixStream>) -> io::Result<bool> {
+    reader.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+    let ready = loop {
+        match reader.fill_buf() {
+            Ok(_) => break true,
+            Err(e) if service::timed_out(&e) && service::terminating() => break false,
+            Err(e) if service::timed_out(&e) => {}
+            Err(e) => return Err(e),
+        }
+    };
+    // A job arriving slowly mustn't be cut off partway
+    reader.get_ref().set_read_timeout(None)?;
+
+    Ok(ready)
+}
+
+/// The next job on a connection, or `None` once the client has finished.
+fn read_job(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let first = match reader.fill_buf()?.first() {
+        Some(&first) => first,
+        None => return Ok(None),
+    };
+
+    let mut job = Vec::new();
+    if first == 0 {
+        reader.consume(1);
+        let mut length = [0; 4];
+        reader.read_exact(&mut length)?;
+        let length = u32::from_be_bytes(length) as usize;
+        if length > MAX_JOB {
+            let message = format!("{} byte job is over the {} byte limit", length, MAX_JOB);
+            return Err(io::Error::other(message));
+        }
+        job.resize(length, 0);
+        reader.read_exact(&mut job)?;
+    } else {
+        // One byte over, so a full-length line still has room for its \n
+        reader
+            .take(MAX_JOB as u64 + 1)
+            .read_until(b'\n', &mut job)?;
+        if job.len() > MAX_JOB && job.last() != Some(&b'\n') {
+            let message = format!("job line is over the {} byte limit", MAX_JOB);
+            return Err(io::Error::other(message));
+        }
+        while matches!(job.last(), Some(b'\n' | b'\r')) {
+            job.pop();
+        }
+    }
+
+    Ok(Some(job))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jobs_are_lines_or_length_prefixed() {
+        let mut input = &b"# one\r\n\x00\x00\x00\x00\x06a\nb\n\nc**two**\n"[..];
+
+        assert_eq!(read_job(&mut input).unwrap(), Some(b"# one".to_vec()));
+        assert_eq!(read_job(&mut input).unwrap(), Some(b"a\nb\n\nc".to_vec()));
+        assert_eq!(read_job(&mut input).unwrap(), Some(b"**two**".to_vec()));
+        assert_eq!(read_job(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn long_lines_stop_at_the_job_limit() {
+        let mut line = vec![b'x'; MAX_JOB];
+        line.push(b'\n');
+        assert_eq!(read_job(&mut &line[..]).unwrap().unwrap().len(), MAX_JOB);
+
+        line.insert(0, b'x');
+        let error = read_job(&mut &line[..]).unwrap_err();
+        assert!(error.to_string().contains("limit"), "{}", error);
+    }
+}
@@ -0,0 +1,115 @@
+use alloc::format;
+use alloc::string::String;
+
+use crate::toml::Table;
+
+/// Blank lines before and after one kind of block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Around {
+    pub before: usize,
+    pub after: usize,
+}
+
+/// Blank lines printed around each kind of block, on top of the line break
+/// that always sets it apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Spacing {
+    pub top_heading: Around,
+    /// Headings below the top level
+    pub heading: Around,
+    pub list: Around,
+    pub code: Around,
+}
+
+impl Default for Spacing {
+    fn default() -> Self {
+        Spacing {
+            top_heading: Around {
+                before: 1,
+                after: 1,
+            },
+            heading: Around {
+                before: 1,
+                after: 0,
+            },
+            list: Around::default(),
+            code: Around::default(),
+        }
+    }
+}
+
+impl Spacing {
+    /// Overrides whatever a `[spacing]` table sets, like `heading_before = 2`
+    /// or `list_after = 1`.
+    pub fn apply(&mut self, table: &Table) -> Result<(), String> {
+        let blocks = [
+            ("top_heading", &mut self.top_heading),
+            ("heading", &mut self.heading),
+            ("list", &mut self.list),
+            ("code", &mut self.code),
+        ];
+        for (name, around) in blocks {
+            for (side, lines) in [("before", &mut around.before), ("after", &mut around.after)] {
+                let key = format!("{}_{}", name, side);
+                if let Some(n) = table.integer(&key) {
+                    *lines =
+                        usize::try_from(n).map_err(|_| format!("{} {} is negative", key, n))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// TOML between `+++` lines at the very start of a document, as Hugo writes
+/// it, and the offset the markdown after it starts at.
+pub fn front_matter(input: &str) -> Option<(&str, usize)> {
+    let body = input.strip_prefix("+++\n")?;
+    let mut offset = 0;
+    for line in body.split_inclusive('\n') {
+        if line.trim_end() == "+++" {
+            let end = input.len() - body.len() + offset + line.len();
+            return Some((&body[..offset], end));
+        }
+        offset += line.len();
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toml;
+
+    #[test]
+    fn tables_override_single_sides() {
+        let tables = toml::parse("[spacing]\nheading_after = 2\nlist_before = 1").unwrap();
+        let mut spacing = Spacing::default();
+        spacing.apply(&tables[0]).unwrap();
+
+        assert_eq!(
+            spacing.heading,
+            Around {
+                before: 1,
+                after: 2
+            }
+        );
+        assert_eq!(spacing.list.before, 1);
+        assert_eq!(spacing.top_heading, Spacing::default().top_heading);
+    }
+
+    #[test]
+    fn front_matter_needs_both_fences() {
+        let input = "+++\n[spacing]\nlist_after = 1\n+++\n# Hi";
+
+        assert_eq!(
+            front_matter(input),
+            Some(("[spacing]\nlist_after = 1\n", 33))
+        );
+        assert_eq!(&input[33..], "# Hi");
+        assert_eq!(front_matter("+++\nunclosed"), None);
+        assert_eq!(front_matter("text\n+++\n"), None);
+    }
+}
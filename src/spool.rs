@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// How often the directory is scanned for new files
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A hot folder: markdown files written into it are printed, then moved to
+/// `done/`, `failed/` or `cancelled/`. Files written into `urgent/` jump the
+/// queue, and the job printing sits in `printing/` until it's done.
+#[derive(Debug)]
+pub struct Spool {
+    dir: PathBuf,
+    /// Size of each waiting file at the last scan
+    sizes: HashMap<PathBuf, u64>,
+}
+
+/// What `Spool::cancel` did with a job.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Cancelled {
+    /// Taken out of the queue, into `cancelled/`
+    Queued(PathBuf),
+    /// Printing, so it stops at the next line
+    Printing,
+}
+
+impl Spool {
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        for folder in ["done", "failed", "cancelled", "urgent", "printing"] {
+            fs::create_dir_all(dir.join(folder))?;
+        }
+
+        Ok(Spool {
+            dir: dir.to_path_buf(),
+            sizes: HashMap::new(),
+        })
+    }
+
+    /// Prints files as they arrive, forever. `print` is given the file and a
+    /// check to make between chunks of output, which turns true once the job
+    /// has been cancelled.
+    pub fn watch(
+        mut self,
+        mut print: impl FnMut(&Path, &dyn Fn() -> bool) -> Result<(), String>,
+    ) -> ! {
+        if let Err(e) = self.requeue() {
+            eprintln!("warning: could not requeue unfinished jobs: {}", e);
+        }
+        loop {
+            for path in self.ready() {
+                // Let urgent files that turned up meanwhile go first
+                if !self.is_urgent(&path) && self.urgent_waiting() {
+                    break;
+                }
+                let path = match self.start(&path) {
+                    Ok(path) => path,
+                    Err(e) => {
+                        eprintln!("warning: could not take {}: {}", path.display(), e);
+                        continue;
+                    }
+                };
+                let marker = cancel_marker(&path);
+                let result = print(&path, &|| marker.exists());
+                let cancelled = marker.exists();
+                let _ = fs::remove_file(&marker);
+                let folder = match &result {
+                    _ if cancelled => "cancelled",
+                    Ok(()) => "done",
+                    Err(message) => {
+                        eprintln!("warning: {}: {}", path.display(), message);
+                        "failed"
+                    }
+                };
+                if let Err(e) = self.finish(&path, folder) {
+                    eprintln!("warning: could not move {}: {}", path.display(), e);
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Markdown files whose size hasn't changed since the last scan, so
+    /// whatever wrote them is done: those in `urgent/` first, then the rest,
+    /// each in name order.
+    pub fn ready(&mut self) -> Vec<PathBuf> {
+        let mut sizes = HashMap::new();
+        for dir in [self.dir.join("urgent"), self.dir.clone()] {
+            for (path, size) in markdown_files(&dir) {
+                sizes.insert(path, size);
+            }
+        }
+
+        let mut ready = sizes
+            .iter()
+            .filter(|(path, size)| self.sizes.get(*path) == Some(size))
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        ready.sort_by_key(|path| (!self.is_urgent(path), path.clone()));
+        self.sizes = sizes;
+
+        ready
+    }
+
+    /// Puts jobs left in `printing/` by a run that stopped partway back in
+    /// `urgent/`, so they print again before anything else. One that was
+    /// being cancelled goes to `cancelled/` instead.
+    pub fn requeue(&self) -> io::Result<()> {
+        for (path, _) in markdown_files(&self.dir.join("printing")) {
+            let marker = cancel_marker(&path);
+            let folder = match marker.exists() {
+                true => "cancelled",
+                false => "urgent",
+            };
+            self.move_to(&path, folder)?;
+            let _ = fs::remove_file(&marker);
+        }
+
+        Ok(())
+    }
+
+    /// Takes a job out of the queue: a waiting one is moved straight to
+    /// `cancelled/`, and the one printing is told to stop.
+    pub fn cancel(&self, name: &str) -> io::Result<Cancelled> {
+        // Only a file directly in the spool, never one elsewhere
+        if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not the name of a job", name),
+            ));
+        }
+
+        for folder in [self.dir.join("urgent"), self.dir.clone()] {
+            let path = folder.join(name);
+            if path.is_file() {
+                return self.move_to(&path, "cancelled").map(Cancelled::Queued);
+            }
+        }
+
+        let printing = self.dir.join("printing").join(name);
+        if printing.is_file() {
+            fs::write(cancel_marker(&printing), "")?;
+            return Ok(Cancelled::Printing);
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no job {} is queued or printing", name),
+        ))
+    }
+
+    /// Moves a file to `printing/` while it prints.
+    fn start(&mut self, path: &Path) -> io::Result<PathBuf> {
+        self.sizes.remove(path);
+        let target = self
+            .dir
+            .join("printing")
+            .join(path.file_name().unwrap_or_default());
+        fs::rename(path, &target)?;
+
+        Ok(target)
+    }
+
+    /// Moves a handled file to `done/`, `failed/` or `cancelled/`.
+    pub fn finish(&mut self, path: &Path, folder: &str) -> io::Result<PathBuf> {
+        self.sizes.remove(path);
+        self.move_to(path, folder)
+    }
+
+    /// Moves a file into one of the spool's folders, without replacing one of
+    /// the same name already there. Returns where it went.
+    fn move_to(&self, path: &Path, folder: &str) -> io::Result<PathBuf> {
+        let folder = self.dir.join(folder);
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+        let mut target = folder.join(&*name);
+        let mut copy = 1;
+        while target.exists() {
+            target = folder.join(format!("{}.{}", name, copy));
+            copy += 1;
+        }
+        fs::rename(path, &target)?;
+
+        Ok(target)
+    }
+
+    fn is_urgent(&self, path: &Path) -> bool {
+        path.parent() == Some(&self.dir.join("urgent"))
+    }
+
+    fn urgent_waiting(&self) -> bool {
+        !markdown_files(&self.dir.join("urgent")).is_empty()
+    }
+}
+
+/// Markdown files directly inside a folder, with their sizes.
+fn markdown_files(dir: &Path) -> Vec<(PathBuf, u64)> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        let is_markdown = path.extension().is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("md") || extension.eq_ignore_ascii_case("markdown")
+        });
+        match entry.metadata() {
+            Ok(meta) if meta.is_file() && is_markdown => files.push((path, meta.len())),
+            _ => {}
+        }
+    }
+
+    files
+}
+
+/// File whose presence tells the job printing from `path` to stop.
+fn cancel_marker(path: &Path) -> PathBuf {
+    let mut marker = path.as_os_str().to_owned();
+    marker.push(".cancel");
+
+    PathBuf::from(marker)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_are_printed_once_they_stop_growing() {
+        let dir = std::env::temp_dir().join(format!("mdtoepson-spool-{}", std::process::id()));
+        let mut spool = Spool::open(&dir).unwrap();
+        fs::write(dir.join("a.md"), "# hi").unwrap();
+        fs::write(dir.join("notes.txt"), "skip").unwrap();
+
+        assert!(spool.ready().is_empty());
+        assert_eq!(spool.ready(), vec![dir.join("a.md")]);
+
+        let done = spool.finish(&dir.join("a.md"), "done").unwrap();
+        fs::write(dir.join("a.md"), "# again").unwrap();
+        spool.ready();
+        let again = spool.finish(&dir.join("a.md"), "done").unwrap();
+
+        assert_eq!(done, dir.join("done").join("a.md"));
+        assert_eq!(again, dir.join("done").join("a.md.1"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn urgent_files_go_first_and_jobs_can_be_cancelled() {
+        let dir = std::env::temp_dir().join(format!("mdtoepson-urgent-{}", std::process::id()));
+        let mut spool = Spool::open(&dir).unwrap();
+        fs::write(dir.join("a.md"), "# a").unwrap();
+        fs::write(dir.join("b.md"), "# b").unwrap();
+        fs::write(dir.join("urgent").join("z.md"), "# z").unwrap();
+        spool.ready();
+
+        assert_eq!(
+            spool.ready(),
+            vec![
+                dir.join("urgent").join("z.md"),
+                dir.join("a.md"),
+                dir.join("b.md")
+            ]
+        );
+
+        assert_eq!(
+            spool.cancel("b.md").unwrap(),
+            Cancelled::Queued(dir.join("cancelled").join("b.md"))
+        );
+        let printing = spool.start(&dir.join("a.md")).unwrap();
+        assert_eq!(spool.cancel("a.md").unwrap(), Cancelled::Printing);
+        assert!(cancel_marker(&printing).exists());
+        assert!(spool.cancel("c.md").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cancelling_takes_only_names_in_the_spool() {
+        let dir = std::env::temp_dir().join(format!("mdtoepson-names-{}", std::process::id()));
+        let spool = Spool::open(&dir.join("spool")).unwrap();
+        fs::write(dir.join("outside.md"), "# keep").unwrap();
+
+        for name in [
+            "../outside.md",
+            "..",
+            "done/../../outside.md",
+            "..\\outside.md",
+            "",
+        ] {
+            let error = spool.cancel(name).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{}", name);
+        }
+        assert!(dir.join("outside.md").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn jobs_left_printing_are_requeued_first() {
+        let dir = std::env::temp_dir().join(format!("mdtoepson-requeue-{}", std::process::id()));
+        let mut spool = Spool::open(&dir).unwrap();
+        fs::write(dir.join("a.md"), "# a").unwrap();
+        fs::write(dir.join("printing").join("b.md"), "# b").unwrap();
+        fs::write(dir.join("printing").join("c.md"), "# c").unwrap();
+        fs::write(cancel_marker(&dir.join("printing").join("c.md")), "").unwrap();
+
+        spool.requeue().unwrap();
+        spool.ready();
+
+        assert_eq!(
+            spool.ready(),
+            vec![dir.join("urgent").join("b.md"), dir.join("a.md")]
+        );
+        assert!(dir.join("cancelled").join("c.md").exists());
+        assert_eq!(fs::read_dir(dir.join("printing")).unwrap().count(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,62 @@
+use core::fmt;
+
+use crate::dialect::Dialect;
+
+/// How much paper a rendered job takes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub lines: usize,
+    /// Height of printed images, in dots
+    pub image_dots: usize,
+    pub dialect: Dialect,
+    /// Lines per page, for cut sheets
+    pub page_length: Option<usize>,
+}
+
+impl Stats {
+    /// Estimated length of paper fed, in millimetres.
+    pub fn paper_mm(&self) -> f64 {
+        self.lines as f64 * self.dialect.line_pitch_mm()
+            + self.image_dots as f64 / self.dialect.dots_per_mm()
+    }
+
+    /// Estimated time to print, in seconds, at a feed speed in mm per second.
+    pub fn print_seconds(&self, speed_mm_s: f64) -> f64 {
+        self.paper_mm() / speed_mm_s
+    }
+
+    pub fn pages(&self) -> Option<usize> {
+        self.page_length
+            .map(|length| self.lines.div_ceil(length.max(1)).max(1))
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} lines", self.lines)?;
+        match self.pages() {
+            Some(pages) => write!(f, ", {} pages", pages),
+            None => write!(f, ", about {:.0} mm of paper", self.paper_mm()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn length_counts_lines_and_images() {
+        let stats = Stats {
+            lines: 6,
+            image_dots: 80,
+            dialect: Dialect::EscPos,
+            page_length: None,
+        };
+
+        assert_eq!(stats.paper_mm().round(), 35.0);
+        assert_eq!(stats.to_string(), "6 lines, about 35 mm of paper");
+        assert_eq!(stats.print_seconds(35.0).round(), 1.0);
+    }
+}
@@ -0,0 +1,178 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use mdtoepson::locale::Locale;
+
+/// Values for the `{{name}}` placeholders expanded before rendering.
+#[derive(Debug)]
+pub struct Placeholders {
+    /// Seconds since the Unix epoch, in UTC
+    now: u64,
+    /// Next job number, once the counter has been read and bumped
+    job_number: Option<u64>,
+    counter: Option<PathBuf>,
+    /// How `{{date}}` and `{{time}}` are written
+    locale: Locale,
+}
+
+impl Placeholders {
+    pub fn new(locale: Locale) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+
+        Placeholders {
+            now,
+            job_number: None,
+            counter: counter_path(),
+            locale,
+        }
+    }
+
+    /// Replaces every known `{{name}}`, leaving unknown ones for later passes.
+    pub fn expand(&mut self, text: &str) -> String {
+        let mut res = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(open) = rest.find("{{") {
+            let Some(close) = rest[open..].find("}}") else {
+                break;
+            };
+            let name = rest[open + 2..open + close].trim();
+            res.push_str(&rest[..open]);
+            match self.value(name) {
+                Some(value) => res.push_str(&value),
+                None => res.push_str(&rest[open..open + close + 2]),
+            }
+            rest = &rest[open + close + 2..];
+        }
+        res.push_str(rest);
+
+        res
+    }
+
+    fn value(&mut self, name: &str) -> Option<String> {
+        let (date, time) = local_time(self.now, &self.locale);
+        match name {
+            "date" => Some(date),
+            "time" => Some(time),
+            "job_number" => Some(self.job_number().to_string()),
+            _ => None,
+        }
+    }
+
+    /// Bumps the persisted counter the first time it's needed in a job.
+    fn job_number(&mut self) -> u64 {
+        if let Some(number) = self.job_number {
+            return number;
+        }
+
+        let number = match &self.counter {
+            Some(path) => {
+                let last = fs::read_to_string(path)
+                    .ok()
+                    .and_then(|text| text.trim().parse::<u64>().ok())
+                    .unwrap_or(0);
+                let number = last + 1;
+                let saved = path
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::write(path, format!("{}\n", number)));
+                if let Err(e) = saved {
+                    eprintln!(
+                        "warning: could not save job number to {}: {}",
+                        path.display(),
+                        e
+                    );
+                }
+                number
+            }
+            None => 1,
+        };
+        self.job_number = Some(number);
+
+        number
+    }
+}
+
+/// Directory for state that persists between jobs.
+pub fn state_dir() -> Option<PathBuf> {
+    env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .map(|dir| dir.join("mdtoepson"))
+}
+
+fn counter_path() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("job_number"))
+}
+
+/// Formats a Unix timestamp as an ISO 8601 date and a 24 hour time.
+pub fn civil_time(timestamp: u64) -> (String, String) {
+    local_time(timestamp, &Locale::default())
+}
+
+/// Formats a Unix timestamp as a date and a time the locale's way.
+pub fn local_time(timestamp: u64, locale: &Locale) -> (String, String) {
+    let days = (timestamp / 86_400) as i64;
+    let seconds = timestamp % 86_400;
+
+    // Days to civil date, after Howard Hinnant's algorithm
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (
+        locale.date(year, month, day),
+        locale.time(seconds / 3_600, seconds % 3_600 / 60),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(now: u64) -> Placeholders {
+        Placeholders {
+            now,
+            job_number: Some(42),
+            counter: None,
+            locale: Locale::default(),
+        }
+    }
+
+    #[test]
+    fn civil_time_handles_leap_days() {
+        assert_eq!(
+            civil_time(951_782_400 + 45_296),
+            (String::from("2000-02-29"), String::from("12:34"))
+        );
+    }
+
+    #[test]
+    fn expands_known_placeholders_only() {
+        let text = at(0).expand("#{{ job_number }} on {{date}} at {{time}} for {{name}}");
+
+        assert_eq!(text, "#42 on 1970-01-01 at 00:00 for {{name}}");
+    }
+
+    #[test]
+    fn dates_follow_the_locale() {
+        let mut placeholders = Placeholders {
+            locale: Locale::find("en-US").unwrap(),
+            ..at(951_782_400 + 45_296)
+        };
+
+        assert_eq!(
+            placeholders.expand("{{date}} {{time}}"),
+            "02/29/2000 12:34 PM"
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// A printed line of a table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Row {
+    /// Line of the first record, printed in bold
+    Header(String),
+    /// Dashes under the header, or a border of a framed table
+    Rule(String),
+    Body(String),
+}
+
+/// Characters drawing the borders of framed tables and code blocks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub horizontal: char,
+    pub vertical: char,
+    /// Corners and joints, left to right for the top, middle and bottom rules
+    pub top: [char; 3],
+    pub middle: [char; 3],
+    pub bottom: [char; 3],
+}
+
+/// Box drawing characters, which the usual printer codepages all have.
+pub const BOX: Frame = Frame {
+    horizontal: '─',
+    vertical: '│',
+    top: ['┌', '┬', '┐'],
+    middle: ['├', '┼', '┤'],
+    bottom: ['└', '┴', '┘'],
+};
+
+pub const ASCII: Frame = Frame {
+    horizontal: '-',
+    vertical: '|',
+    top: ['+'; 3],
+    middle: ['+'; 3],
+    bottom: ['+'; 3],
+};
+
+impl Frame {
+    /// A horizontal border over columns of these widths, from one of the
+    /// `top`, `middle` or `bottom` sets of joints.
+    pub fn rule(&self, joints: [char; 3], widths: &[usize]) -> String {
+        let mut rule = String::new();
+        rule.push(joints[0]);
+        for (i, &width) in widths.iter().enumerate() {
+            if i > 0 {
+                rule.push(joints[1]);
+            }
+            rule.extend(core::iter::repeat_n(self.horizontal, width));
+        }
+        rule.push(joints[2]);
+
+        rule
+    }
+}
+
+/// Lays records out in columns a space apart, or between the lines of a
+/// frame, the first record as the header. Columns that are all numbers below
+/// the header are right-aligned. When the table is wider than `width`, the
+/// widest columns are narrowed and their cells wrap onto extra lines.
+pub fn layout(records: &[Vec<String>], width: Option<usize>, frame: Option<&Frame>) -> Vec<Row> {
+    let records = records
+        .iter()
+        .filter(|record| record.iter().any(|cell| !cell.trim().is_empty()))
+        .collect::<Vec<_>>();
+    let columns = records.iter().map(|record| record.len()).max().unwrap_or(0);
+    if columns == 0 {
+        return Vec::new();
+    }
+
+    let cell = |record: &Vec<String>, column: usize| {
+        record
+            .get(column)
+            .map_or("", |cell| cell.trim())
+            .to_string()
+    };
+    let mut widths = (0..columns)
+        .map(|column| {
+            records
+                .iter()
+                .map(|record| cell(record, column).chars().count())
+                .max()
+                .unwrap_or(0)
+                .max(1)
+        })
+        .collect::<Vec<_>>();
+    let numeric = (0..columns)
+        .map(|column| {
+            let cells = records[1..].iter().map(|record| cell(record, column));
+            records.len() > 1 && cells.clone().all(|cell| is_number(&cell))
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(width) = width {
+        let borders = match frame {
+            Some(_) => columns + 1,
+            None => columns - 1,
+        };
+        let available = width.saturating_sub(borders);
+        while widths.iter().sum::<usize>() > available {
+            let widest = (0..columns).max_by_key(|&i| widths[i]).unwrap_or(0);
+            if widths[widest] <= 1 {
+                break;
+            }
+            widths[widest] -= 1;
+        }
+    }
+
+    let (separator, edge) = match frame {
+        Some(frame) => (frame.vertical.to_string(), frame.vertical.to_string()),
+        None => (String::from(" "), String::new()),
+    };
+    let mut rows = Vec::new();
+    if let Some(frame) = frame {
+        rows.push(Row::Rule(frame.rule(frame.top, &widths)));
+    }
+    for (i, record) in records.iter().enumerate() {
+        let cells = (0..columns)
+            .map(|column| wrap(&cell(record, column), widths[column]))
+            .collect::<Vec<_>>();
+        let height = cells.iter().map(Vec::len).max().unwrap_or(1);
+        for line in 0..height {
+            let text = (0..columns)
+                .map(|column| {
+                    let text = cells[column].get(line).map_or("", String::as_str);
+                    let width = widths[column];
+                    match numeric[column] && i > 0 {
+                        true => format!("{:>width$}", text),
+                        false => format!("{:<width$}", text),
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(&separator);
+            let text = match frame {
+                Some(_) => format!("{}{}{}", edge, text, edge),
+                None => text.trim_end().to_string(),
+            };
+            rows.push(match i {
+                0 => Row::Header(text),
+                _ => Row::Body(text),
+            });
+        }
+        if i == 0 && records.len() > 1 {
+            let rule = match frame {
+                Some(frame) => frame.rule(frame.middle, &widths),
+                None => {
+                    let dashes = widths.iter().map(|&width| "-".repeat(width));
+                    dashes.collect::<Vec<_>>().join(" ")
+                }
+            };
+            rows.push(Row::Rule(rule));
+        }
+    }
+    if let Some(frame) = frame {
+        rows.push(Row::Rule(frame.rule(frame.bottom, &widths)));
+    }
+
+    rows
+}
+
+fn is_number(cell: &str) -> bool {
+    let digits = cell.trim_start_matches(['-', '+', '$']);
+    !digits.is_empty()
+        && digits.chars().any(|c| c.is_ascii_digit())
+        && digits
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '.' || c == ',')
+}
+
+/// Breaks a cell into lines of at most `width` characters, at spaces where it can.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word = word.chars().collect::<Vec<_>>();
+        let used = line.chars().count();
+        if used > 0 && used + 1 + word.len() <= width {
+            line.push(' ');
+            line.extend(&word);
+            continue;
+        }
+        if used > 0 {
+            lines.push(core::mem::take(&mut line));
+        }
+        while word.len() > width {
+            lines.push(word.drain(..width).collect());
+        }
+        line.extend(word);
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn records(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn columns_fit_their_widest_cell() {
+        let rows = layout(
+            &records(&[&["Item", "Qty"], &["Tea", "2"], &["Cake", "10"]]),
+            None,
+            None,
+        );
+
+        assert_eq!(
+            rows,
+            vec![
+                Row::Header(String::from("Item Qty")),
+                Row::Rule(String::from("---- ---")),
+                Row::Body(String::from("Tea    2")),
+                Row::Body(String::from("Cake  10")),
+            ]
+        );
+    }
+
+    #[test]
+    fn wide_tables_wrap_their_widest_column() {
+        let rows = layout(
+            &records(&[&["Name", "Note"], &["Al", "paid in full today"]]),
+            Some(12),
+            None,
+        );
+
+        assert_eq!(
+            rows,
+            vec![
+                Row::Header(String::from("Name Note")),
+                Row::Rule(String::from("---- -------")),
+                Row::Body(String::from("Al   paid in")),
+                Row::Body(String::from("     full")),
+                Row::Body(String::from("     today")),
+            ]
+        );
+    }
+
+    #[test]
+    fn framed_tables_fit_inside_their_borders() {
+        let rows = layout(
+            &records(&[&["Item", "Qty"], &["Tea", "2"]]),
+            Some(9),
+            Some(&BOX),
+        );
+        let lines = rows
+            .iter()
+            .map(|row| match row {
+                Row::Header(text) | Row::Rule(text) | Row::Body(text) => text.as_str(),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lines,
+            [
+                "┌───┬───┐",
+                "│Ite│Qty│",
+                "│m  │   │",
+                "├───┼───┤",
+                "│Tea│  2│",
+                "└───┴───┘"
+            ]
+        );
+    }
+}
@@ -0,0 +1,621 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::fmt::Write;
+
+use crate::locale::Locale;
+
+/// Structured data fed to a template, read from JSON or CSV.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    /// A number as written in the source, so `3.50` keeps its zero
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn is_truthy(&self) -> bool {
+        match self {
+            Value::Null | Value::Bool(false) => false,
+            Value::String(s) => !s.is_empty(),
+            Value::Array(items) => !items.is_empty(),
+            _ => true,
+        }
+    }
+
+    fn write_text(&self, out: &mut String) {
+        match self {
+            Value::Null => {}
+            Value::Bool(b) => {
+                let _ = write!(out, "{}", b);
+            }
+            Value::Number(n) => out.push_str(n),
+            Value::String(s) => out.push_str(s),
+            Value::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    item.write_text(out);
+                }
+            }
+            Value::Object(_) => {}
+        }
+    }
+}
+
+/// Expands `{{name}}`, `{{#section}}…{{/section}}` and `{{^section}}…{{/section}}`
+/// the way Mustache does, plus filters like `{{price | money("USD")}}` that
+/// write numbers the locale's way. Names missing from the data are left for
+/// later passes.
+pub fn render(template: &str, data: &Value, locale: &Locale) -> Result<String, String> {
+    let mut out = String::with_capacity(template.len());
+    render_into(&mut out, template, &mut vec![data], locale)?;
+
+    Ok(out)
+}
+
+fn render_into(
+    out: &mut String,
+    text: &str,
+    stack: &mut Vec<&Value>,
+    locale: &Locale,
+) -> Result<(), String> {
+    let mut rest = text;
+    while let Some((open, after, tag)) = next_tag(rest)? {
+        let sigil = tag.chars().next().unwrap_or(' ');
+        match sigil {
+            '#' | '^' => {
+                let name = tag[1..].trim();
+                let (line_start, body_start) = standalone(rest, open, after);
+                out.push_str(&rest[..line_start]);
+                let (body_end, resume) = section_end(rest, body_start, name)?;
+                let body = &rest[body_start..body_end];
+
+                let value = lookup(stack, name);
+                match (sigil, value) {
+                    ('#', Some(Value::Array(items))) => {
+                        for item in items {
+                            stack.push(item);
+                            render_into(out, body, stack, locale)?;
+                            stack.pop();
+                        }
+                    }
+                    ('#', Some(value)) if value.is_truthy() => {
+                        stack.push(value);
+                        render_into(out, body, stack, locale)?;
+                        stack.pop();
+                    }
+                    ('^', value) if !value.is_some_and(Value::is_truthy) => {
+                        render_into(out, body, stack, locale)?;
+                    }
+                    _ => {}
+                }
+                rest = &rest[resume..];
+            }
+            '/' => return Err(format!("{{{{{}}}}} closes a section that isn't open", tag)),
+            '!' => {
+                let (line_start, resume) = standalone(rest, open, after);
+                out.push_str(&rest[..line_start]);
+                rest = &rest[resume..];
+            }
+            _ => {
+                out.push_str(&rest[..open]);
+                let mut filters = tag.split('|');
+                let name = filters.next().unwrap_or_default().trim();
+                match lookup(stack, name) {
+                    Some(value) => {
+                        let mut text = String::new();
+                        value.write_text(&mut text);
+                        for filter in filters {
+                            text = apply_filter(&text, filter.trim(), locale)?;
+                        }
+                        out.push_str(&text);
+                    }
+                    None => out.push_str(&rest[open..after]),
+                }
+                rest = &rest[after..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    Ok(())
+}
+
+/// Formats a value's text with a filter: `money("USD")` for an amount with
+/// its currency, `fixed(2)` for a number rounded to that many decimals,
+/// `number` for one with its thousands separated, or `pad(3)` to right-align
+/// text in that many columns.
+fn apply_filter(text: &str, filter: &str, locale: &Locale) -> Result<String, String> {
+    let (name, arg) = match filter.split_once('(') {
+        Some((name, arg)) => (name.trim(), arg.trim_end().trim_end_matches(')').trim()),
+        None => (filter, ""),
+    };
+    let arg = arg.trim_matches(|c| c == '"' || c == '\'');
+    let count = || {
+        arg.parse::<usize>()
+            .map_err(|_| format!("{}({}) needs a count", name, arg))
+    };
+
+    match name {
+        "money" => {
+            let (symbol, places) = match arg {
+                "" | "USD" => ("$", 2),
+                "EUR" => ("€", 2),
+                "GBP" => ("£", 2),
+                "JPY" => ("¥", 0),
+                _ => ("", 2),
+            };
+            let amount = locale.number(&round_decimal(text, places)?);
+            let res = match (symbol, locale.symbol_after) {
+                ("", _) => format!("{} {}", amount, arg),
+                (symbol, true) => format!("{} {}", amount, symbol),
+                (symbol, false) => match amount.strip_prefix('-') {
+                    Some(amount) => format!("-{}{}", symbol, amount),
+                    None => format!("{}{}", symbol, amount),
+                },
+            };
+            Ok(res)
+        }
+        "fixed" => {
+            let rounded = round_decimal(text, count()?)?;
+            Ok(rounded.replace('.', &locale.decimal.to_string()))
+        }
+        "number" => {
+            let places = text
+                .trim()
+                .split_once('.')
+                .map_or(0, |(_, fraction)| fraction.len());
+            Ok(locale.number(&round_decimal(text, places)?))
+        }
+        "pad" => Ok(format!("{:>1$}", text, count()?)),
+        _ => Err(format!("unknown filter {}", name)),
+    }
+}
+
+/// Rounds a decimal number half away from zero, in decimal so amounts like
+/// `2.675` aren't thrown off by binary floating point.
+fn round_decimal(text: &str, places: usize) -> Result<String, String> {
+    let not_a_number = || format!("{} is not a number", text);
+    let (negative, digits) = match text.trim().strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.trim()),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if whole.is_empty() && fraction.is_empty()
+        || !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return Err(not_a_number());
+    }
+
+    let mut kept = whole
+        .bytes()
+        .chain(
+            fraction
+                .bytes()
+                .chain(core::iter::repeat(b'0'))
+                .take(places),
+        )
+        .map(|b| b - b'0')
+        .collect::<Vec<_>>();
+    if fraction.as_bytes().get(places).is_some_and(|&b| b >= b'5') {
+        // Carry the rounding up through any nines
+        let mut i = kept.len();
+        loop {
+            if i == 0 {
+                kept.insert(0, 1);
+                break;
+            }
+            i -= 1;
+            if kept[i] == 9 {
+                kept[i] = 0;
+            } else {
+                kept[i] += 1;
+                break;
+            }
+        }
+    }
+
+    let split = kept.len() - places;
+    let digit = |d: &u8| char::from(b'0' + d);
+    let mut whole = kept[..split]
+        .iter()
+        .map(digit)
+        .collect::<String>()
+        .trim_start_matches('0')
+        .to_string();
+    if whole.is_empty() {
+        whole.push('0');
+    }
+    let fraction = kept[split..].iter().map(digit).collect::<String>();
+    let zero = whole == "0" && fraction.bytes().all(|b| b == b'0');
+
+    let mut res = String::new();
+    if negative && !zero {
+        res.push('-');
+    }
+    res.push_str(&whole);
+    if places > 0 {
+        res.push('.');
+        res.push_str(&fraction);
+    }
+
+    Ok(res)
+}
+
+/// Finds the next `{{tag}}`, as its start, the end of its braces and its trimmed content.
+fn next_tag(text: &str) -> Result<Option<(usize, usize, &str)>, String> {
+    let Some(open) = text.find("{{") else {
+        return Ok(None);
+    };
+    let close = text[open..]
+        .find("}}")
+        .ok_or_else(|| String::from("{{ without a closing }}"))?;
+
+    Ok(Some((
+        open,
+        open + close + 2,
+        text[open + 2..open + close].trim(),
+    )))
+}
+
+/// Widens a tag alone on its line to the whole line, so section markers
+/// don't leave blank lines behind.
+fn standalone(text: &str, open: usize, after: usize) -> (usize, usize) {
+    let line_start = text[..open].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = text[after..]
+        .find('\n')
+        .map_or(text.len(), |i| after + i + 1);
+    match text[line_start..open].trim().is_empty() && text[after..line_end].trim().is_empty() {
+        true => (line_start, line_end),
+        false => (open, after),
+    }
+}
+
+/// Where the body of section `name` starting at `from` ends, and where the
+/// text continues after its closing tag.
+fn section_end(text: &str, from: usize, name: &str) -> Result<(usize, usize), String> {
+    let mut depth = 1;
+    let mut at = from;
+    while let Some((open, after, tag)) = next_tag(&text[at..])? {
+        let (open, after) = (at + open, at + after);
+        match tag.split_at(tag.len().min(1)) {
+            ("#" | "^", inner) if inner.trim() == name => depth += 1,
+            ("/", inner) if inner.trim() == name => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(standalone(text, open, after));
+                }
+            }
+            _ => {}
+        }
+        at = after;
+    }
+
+    Err(format!("section {} is never closed", name))
+}
+
+/// Resolves `.` or a dotted name, searching outwards through enclosing sections.
+fn lookup<'a>(stack: &[&'a Value], name: &str) -> Option<&'a Value> {
+    if name == "." {
+        return stack.last().copied();
+    }
+
+    let mut parts = name.split('.');
+    let first = parts.next()?;
+    let mut value = stack.iter().rev().find_map(|scope| scope.get(first))?;
+    for part in parts {
+        value = value.get(part)?;
+    }
+
+    Some(value)
+}
+
+/// Parses a JSON document.
+pub fn parse_json(text: &str) -> Result<Value, String> {
+    let mut parser = Json { text, pos: 0 };
+    let value = parser.value()?;
+    parser.skip_space();
+    match parser.pos == text.len() {
+        true => Ok(value),
+        false => Err(parser.error("trailing characters")),
+    }
+}
+
+struct Json<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Json<'_> {
+    fn error(&self, message: &str) -> String {
+        let line = self.text[..self.pos].matches('\n').count() + 1;
+        format!("line {}: {}", line, message)
+    }
+
+    fn skip_space(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_space();
+        let found = self.text[self.pos..].starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_space();
+        let rest = &self.text[self.pos..];
+        match rest.chars().next() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Value::String),
+            Some('-' | '0'..='9') => {
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+                    .unwrap_or(rest.len());
+                self.pos += len;
+                Ok(Value::Number(rest[..len].to_string()))
+            }
+            _ if self.eat("true") => Ok(Value::Bool(true)),
+            _ if self.eat("false") => Ok(Value::Bool(false)),
+            _ if self.eat("null") => Ok(Value::Null),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.eat("{");
+        let mut entries = Vec::new();
+        if self.eat("}") {
+            return Ok(Value::Object(entries));
+        }
+        loop {
+            self.skip_space();
+            let key = self.string()?;
+            if !self.eat(":") {
+                return Err(self.error("expected :"));
+            }
+            entries.push((key, self.value()?));
+            if self.eat("}") {
+                return Ok(Value::Object(entries));
+            }
+            if !self.eat(",") {
+                return Err(self.error("expected , or }"));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.eat("[");
+        let mut items = Vec::new();
+        if self.eat("]") {
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            if self.eat("]") {
+                return Ok(Value::Array(items));
+            }
+            if !self.eat(",") {
+                return Err(self.error("expected , or ]"));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if !self.text[self.pos..].starts_with('"') {
+            return Err(self.error("expected a string"));
+        }
+        self.pos += 1;
+
+        let mut res = String::new();
+        let mut chars = self.text[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(res);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\x08',
+                        Some('f') => '\x0C',
+                        Some('u') => {
+                            let bad = || self.error("bad \\u escape");
+                            let high = hex_unit(&mut chars).ok_or_else(bad)?;
+                            let code = match high {
+                                0xD800..=0xDBFF => {
+                                    let escape = (chars.next(), chars.next());
+                                    let low = match escape {
+                                        (Some((_, '\\')), Some((_, 'u'))) => hex_unit(&mut chars),
+                                        _ => None,
+                                    };
+                                    let low = low.filter(|low| (0xDC00..=0xDFFF).contains(low));
+                                    0x10000
+                                        + ((high - 0xD800) << 10)
+                                        + (low.ok_or_else(bad)? - 0xDC00)
+                                }
+                                _ => high,
+                            };
+                            char::from_u32(code).unwrap_or('\u{FFFD}')
+                        }
+                        Some(c) => c,
+                        None => break,
+                    };
+                    res.push(escaped);
+                }
+                c => res.push(c),
+            }
+        }
+
+        Err(self.error("unterminated string"))
+    }
+}
+
+fn hex_unit(chars: &mut core::str::CharIndices) -> Option<u32> {
+    let hex = chars.take(4).map(|(_, c)| c).collect::<String>();
+
+    u32::from_str_radix(&hex, 16).ok()
+}
+
+/// Parses CSV with a header row into `{"rows": [{column: cell, …}, …]}`.
+pub fn parse_csv(text: &str) -> Result<Value, String> {
+    let mut records = csv_records(text)?.into_iter();
+    let header = records.next().unwrap_or_default();
+    let rows = records
+        .filter(|record| record.iter().any(|cell| !cell.is_empty()))
+        .map(|record| {
+            let entries = header
+                .iter()
+                .zip(record.into_iter().chain(core::iter::repeat(String::new())))
+                .map(|(column, cell)| (column.clone(), Value::String(cell)))
+                .collect();
+            Value::Object(entries)
+        })
+        .collect();
+
+    Ok(Value::Object(vec![(
+        String::from("rows"),
+        Value::Array(rows),
+    )]))
+}
+
+/// Splits CSV into records of cells, with RFC 4180 quoting.
+pub fn csv_records(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => cell.push(c),
+            (false, '"') if cell.is_empty() => quoted = true,
+            (false, ',') => record.push(core::mem::take(&mut cell)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                record.push(core::mem::take(&mut cell));
+                records.push(core::mem::take(&mut record));
+            }
+            (false, c) => cell.push(c),
+        }
+    }
+    if quoted {
+        return Err(String::from("unterminated quoted cell"));
+    }
+    if !cell.is_empty() || !record.is_empty() {
+        record.push(cell);
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_loop_over_items_without_blank_lines() {
+        let data =
+            parse_json(r#"{"items": [{"name": "Tea", "price": 2.50}, {"name": "Cake"}]}"#).unwrap();
+        let text = render(
+            "{{#items}}\n- {{name}} {{price}}\n{{/items}}\nat {{time}}",
+            &data,
+            &Locale::default(),
+        );
+
+        assert_eq!(text.unwrap(), "- Tea 2.50\n- Cake {{price}}\nat {{time}}");
+    }
+
+    #[test]
+    fn inverted_sections_render_when_falsy() {
+        let data = parse_json(r#"{"paid": false, "note": "thanks \u00e9\ud83d\ude00"}"#).unwrap();
+        let text = render(
+            "{{^paid}}DUE{{/paid}}{{#paid}}PAID{{/paid}} {{note}}",
+            &data,
+            &Locale::default(),
+        );
+
+        assert_eq!(text.unwrap(), "DUE thanks é😀");
+    }
+
+    #[test]
+    fn unclosed_sections_are_errors() {
+        assert!(render("{{#items}}x", &Value::Null, &Locale::default()).is_err());
+        assert!(parse_json("{\"a\": }").is_err());
+    }
+
+    #[test]
+    fn filters_format_amounts_and_columns() {
+        let data = parse_json(r#"{"price": 1234.5, "qty": "7", "tip": "-0.004"}"#).unwrap();
+        let template = "{{price | money(\"USD\")}} {{price|money('CHF')}} \
+            [{{qty | pad(3)}}] {{tip | money}} {{qty | fixed(1)}}";
+
+        assert_eq!(
+            render(template, &data, &Locale::default()).unwrap(),
+            "$1,234.50 1,234.50 CHF [  7] $0.00 7.0"
+        );
+        assert_eq!(round_decimal("2.675", 2).unwrap(), "2.68");
+        assert_eq!(round_decimal("-9.995", 2).unwrap(), "-10.00");
+        assert!(render("{{qty | shout}}", &data, &Locale::default()).is_err());
+        assert!(render("{{qty | pad(x)}}", &data, &Locale::default()).is_err());
+    }
+
+    #[test]
+    fn filters_follow_the_locale() {
+        let data = parse_json(r#"{"price": 1234.5, "count": 1234567}"#).unwrap();
+        let de = Locale::find("de-DE").unwrap();
+        let template = "{{price | money(\"EUR\")}} {{price | fixed(1)}} {{count | number}}";
+
+        assert_eq!(
+            render(template, &data, &de).unwrap(),
+            "1.234,50 € 1234,5 1.234.567"
+        );
+        assert_eq!(
+            render(template, &data, &Locale::default()).unwrap(),
+            "€1,234.50 1234.5 1,234,567"
+        );
+    }
+
+    #[test]
+    fn csv_rows_are_objects_keyed_by_header() {
+        let data = parse_csv("name,price\n\"Tea, green\",2.50\n").unwrap();
+        let text = render(
+            "{{#rows}}{{name}}={{price}};{{/rows}}",
+            &data,
+            &Locale::default(),
+        );
+
+        assert_eq!(text.unwrap(), "Tea, green=2.50;");
+    }
+}
@@ -0,0 +1,178 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+
+/// A value in the small subset of TOML our data files use.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+}
+
+/// A `[name]` table and its keys, in file order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Table {
+    pub name: String,
+    pub entries: Vec<(String, Value)>,
+}
+
+impl Table {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn string(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn integer(&self, key: &str) -> Option<i64> {
+        match self.get(key)? {
+            Value::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn boolean(&self, key: &str) -> Option<bool> {
+        match self.get(key)? {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Adds another table's keys, replacing those already set.
+    pub fn merge(&mut self, other: &Table) {
+        for (key, value) in &other.entries {
+            match self.entries.iter_mut().find(|(k, _)| k == key) {
+                Some(entry) => entry.1 = value.clone(),
+                None => self.entries.push((key.clone(), value.clone())),
+            }
+        }
+    }
+}
+
+/// Lays `over` on top of `base`, merging tables of the same name.
+pub fn overlay(mut base: Vec<Table>, over: &[Table]) -> Vec<Table> {
+    for table in over {
+        match base.iter_mut().find(|t| t.name == table.name) {
+            Some(existing) => existing.merge(table),
+            None => base.push(table.clone()),
+        }
+    }
+
+    base
+}
+
+/// Parses tables of strings, integers and booleans. Keys before the first
+/// table header land in a table with an empty name.
+pub fn parse(text: &str) -> Result<Vec<Table>, String> {
+    let mut tables = vec![Table::default()];
+
+    for (number, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: &str| format!("line {}: {}", number + 1, message);
+
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| error("unclosed table header"))?;
+            tables.push(Table {
+                name: unquote(name.trim()).to_string(),
+                entries: Vec::new(),
+            });
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error("expected `key = value`"))?;
+        let value = parse_value(value.trim()).ok_or_else(|| error("unsupported value"))?;
+        tables
+            .last_mut()
+            .unwrap()
+            .entries
+            .push((unquote(key.trim()).to_string(), value));
+    }
+
+    if tables[0].entries.is_empty() {
+        tables.remove(0);
+    }
+
+    Ok(tables)
+}
+
+fn parse_value(value: &str) -> Option<Value> {
+    if let Some(s) = value.strip_prefix('"') {
+        let s = s.strip_suffix('"')?;
+        let mut res = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next()? {
+                    'n' => res.push('\n'),
+                    't' => res.push('\t'),
+                    'e' => res.push('\x1B'),
+                    c => res.push(c),
+                },
+                c => res.push(c),
+            }
+        }
+        return Some(Value::String(res));
+    }
+
+    match value {
+        "true" => Some(Value::Boolean(true)),
+        "false" => Some(Value::Boolean(false)),
+        _ => value.replace('_', "").parse().ok().map(Value::Integer),
+    }
+}
+
+/// Drops a `#` comment that isn't inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+fn unquote(s: &str) -> &str {
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tables_of_scalars() {
+        let tables = parse("[a] # model\nname = \"A # 1\"\nwidth = 48\ncut = true\n").unwrap();
+
+        assert_eq!(tables.len(), 1);
+        assert_eq!(tables[0].name, "a");
+        assert_eq!(tables[0].string("name"), Some("A # 1"));
+        assert_eq!(tables[0].integer("width"), Some(48));
+        assert_eq!(tables[0].boolean("cut"), Some(true));
+    }
+
+    #[test]
+    fn reports_the_bad_line() {
+        assert_eq!(
+            parse("[a]\nwidth 48"),
+            Err(String::from("line 2: expected `key = value`"))
+        );
+    }
+}
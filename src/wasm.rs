@@ -0,0 +1,61 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use wasm_bindgen::prelude::*;
+
+use crate::dialect::Dialect;
+use crate::html;
+use crate::paper::Layout;
+use crate::render::{render_markdown, Options};
+
+/// Renders markdown as escape codes for the dialect (0 for ESC/P, 1 for
+/// ESC/POS, 2 for plain text), with the command line's defaults. A `columns`
+/// of 0 keeps the dialect's usual width.
+#[wasm_bindgen(js_name = transpileMarkdown)]
+pub fn transpile_markdown(markdown: &str, dialect: u32, columns: usize) -> Vec<u8> {
+    let dialect = match dialect {
+        1 => Dialect::EscPos,
+        2 => Dialect::Plain,
+        _ => Dialect::EscP,
+    };
+
+    render_markdown(markdown, &options(dialect, columns)).bytes
+}
+
+/// Renders markdown as the HTML page `--format html` prints, for previews.
+/// A `columns` of 0 leaves the width to the page.
+#[wasm_bindgen(js_name = previewHtml)]
+pub fn preview_html(markdown: &str, columns: usize) -> String {
+    let options = options(Dialect::Html, columns);
+    let rendered = render_markdown(markdown, &options);
+    let page = html::page(&rendered.bytes, options.layout.text_width());
+
+    String::from_utf8_lossy(&page).into_owned()
+}
+
+fn options(dialect: Dialect, columns: usize) -> Options {
+    Options {
+        dialect,
+        layout: Layout {
+            width: (columns > 0).then_some(columns),
+            ..Default::default()
+        },
+        highlight: true,
+        init: true,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_for_the_page() {
+        assert_eq!(
+            transpile_markdown("**hi**", 1, 0),
+            b"\x1B@\x1BE\x01hi\x1BE\x00\n\x1BE\x00\x1B-\x00\x1DB\x00\x1D!\x00\x1Br\x00"
+        );
+        assert!(preview_html("**hi**", 32).contains("width: 32ch"));
+    }
+}
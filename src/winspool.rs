@@ -0,0 +1,98 @@
+use std::ffi::c_void;
+use std::io::{self, Write};
+use std::iter;
+use std::ptr;
+
+type Handle = *mut c_void;
+
+#[repr(C)]
+struct DocInfo1 {
+    doc_name: *const u16,
+    output_file: *const u16,
+    datatype: *const u16,
+}
+
+#[link(name = "winspool")]
+extern "system" {
+    fn OpenPrinterW(name: *const u16, printer: *mut Handle, defaults: *const c_void) -> i32;
+    fn StartDocPrinterW(printer: Handle, level: u32, info: *const DocInfo1) -> u32;
+    fn StartPagePrinter(printer: Handle) -> i32;
+    fn WritePrinter(printer: Handle, buf: *const c_void, count: u32, written: *mut u32) -> i32;
+    fn EndPagePrinter(printer: Handle) -> i32;
+    fn EndDocPrinter(printer: Handle) -> i32;
+    fn ClosePrinter(printer: Handle) -> i32;
+}
+
+/// A job sent to a Windows printer queue with the RAW datatype, so the
+/// spooler passes our escape codes to the printer untouched. The job is
+/// printed once this is dropped.
+pub struct Printer {
+    handle: Handle,
+}
+
+impl Printer {
+    pub fn open(name: &str, job: &str) -> io::Result<Self> {
+        let name = wide(name);
+        let job = wide(job);
+        let datatype = wide("RAW");
+
+        let mut handle = ptr::null_mut();
+        // SAFETY: the strings are NUL terminated and outlive the calls, and
+        // the handle is closed again on every failure
+        unsafe {
+            if OpenPrinterW(name.as_ptr(), &mut handle, ptr::null()) == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let info = DocInfo1 {
+                doc_name: job.as_ptr(),
+                output_file: ptr::null(),
+                datatype: datatype.as_ptr(),
+            };
+            if StartDocPrinterW(handle, 1, &info) == 0 {
+                let e = io::Error::last_os_error();
+                ClosePrinter(handle);
+                return Err(e);
+            }
+            if StartPagePrinter(handle) == 0 {
+                let e = io::Error::last_os_error();
+                EndDocPrinter(handle);
+                ClosePrinter(handle);
+                return Err(e);
+            }
+        }
+
+        Ok(Printer { handle })
+    }
+}
+
+impl Write for Printer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = buf.len().min(u32::MAX as usize) as u32;
+        let mut written = 0;
+        // SAFETY: the handle is open and `count` bytes of `buf` are readable
+        let ok = unsafe { WritePrinter(self.handle, buf.as_ptr().cast(), count, &mut written) };
+        match ok {
+            0 => Err(io::Error::last_os_error()),
+            _ => Ok(written as usize),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Printer {
+    fn drop(&mut self) {
+        // SAFETY: the handle was opened with a document and page started
+        unsafe {
+            EndPagePrinter(self.handle);
+            EndDocPrinter(self.handle);
+            ClosePrinter(self.handle);
+        }
+    }
+}
+
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(iter::once(0)).collect()
+}
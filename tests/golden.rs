@@ -0,0 +1,73 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Extension of the expected output and the arguments producing it
+const DIALECTS: &[(&str, &[&str])] = &[
+    ("escp", &["--dialect", "escp"]),
+    ("escpos", &["--dialect", "escpos"]),
+    ("txt", &["--format", "plain"]),
+    ("html", &["--format", "html"]),
+];
+
+fn fixtures() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut inputs = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "md"))
+        .collect::<Vec<_>>();
+    inputs.sort();
+
+    inputs
+}
+
+fn render(input: &Path, args: &[&str]) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_mdtoepson"))
+        .args(["--no-history", "--width", "32"])
+        .args(args)
+        .arg(input)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{} failed: {}",
+        input.display(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    output.stdout
+}
+
+/// Renders every `tests/fixtures/*.md` in each dialect and compares the bytes
+/// with the expected output beside it, like `receipt.escpos` for `receipt.md`.
+/// With `MDTOEPSON_BLESS=1` set, writes the current output as expected instead.
+#[test]
+fn fixtures_match_their_expected_output() {
+    let bless = env::var_os("MDTOEPSON_BLESS").is_some();
+    let mut mismatches = Vec::new();
+
+    for input in fixtures() {
+        for (extension, args) in DIALECTS {
+            let expected_path = input.with_extension(extension);
+            let actual = render(&input, args);
+            if bless {
+                fs::write(&expected_path, &actual).unwrap();
+                continue;
+            }
+
+            match fs::read(&expected_path) {
+                Ok(expected) if expected == actual => {}
+                Ok(_) => mismatches.push(format!("{} differs", expected_path.display())),
+                Err(_) => mismatches.push(format!("{} is missing", expected_path.display())),
+            }
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "{}\nrerun with MDTOEPSON_BLESS=1 to accept the new output",
+        mismatches.join("\n")
+    );
+}
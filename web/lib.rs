@@ -0,0 +1,4 @@
+//! The exports of `mdtoepson::wasm`, linked into a module for the browser.
+//! See mdtoepson.js for how to build it.
+
+pub use mdtoepson::wasm::*;
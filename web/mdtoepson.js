@@ -0,0 +1,26 @@
+// Loads the renderer built for the browser with
+//
+//   cargo build -p mdtoepson-web --release --target wasm32-unknown-unknown
+//   wasm-bindgen --target web --out-dir web/pkg \
+//       target/wasm32-unknown-unknown/release/mdtoepson_web.wasm
+//
+// which writes the module and its bindings to web/pkg.
+
+import init, { transpileMarkdown, previewHtml } from "./pkg/mdtoepson_web.js";
+
+export const Dialect = { EscP: 0, EscPos: 1, Plain: 2 };
+
+export async function load(url) {
+  await init(url);
+
+  return {
+    // Escape codes for the printer, as a Uint8Array
+    transpileMarkdown(markdown, dialect = Dialect.EscP, columns = 0) {
+      return transpileMarkdown(markdown, dialect, columns);
+    },
+    // The page `--format html` prints, as a string
+    previewHtml(markdown, columns = 0) {
+      return previewHtml(markdown, columns);
+    },
+  };
+}